@@ -1,6 +1,6 @@
 #![doc = include_str!("../../rfcs/001_bagua.md")]
 
-use crate::{App, CubeRng, BUZZER, RNG};
+use crate::{sound::SoundEvent, App, CubeRng, BUZZER, RNG};
 use embassy_time::Timer;
 
 /// 八卦
@@ -117,7 +117,9 @@ impl BaGua {
         Self::bagua(num)
     }
 
-    pub async fn run<T: esp_hal::i2c::Instance>(app: &mut App<'_, T>) {
+    /// 返回起卦的次数
+    pub async fn run<T: esp_hal::i2c::Instance>(app: &mut App<'_, T>) -> u32 {
+        let mut casts = 0;
         app.ledc.clear();
         loop {
             let accel = app.accel();
@@ -128,7 +130,8 @@ impl BaGua {
                     .any(|(x, y)| !(-0.3..=0.3).contains(&x) && !(-0.3..=0.3).contains(&y))
             {
                 app.ledc.write_bytes(Self::random());
-                unsafe { BUZZER.assume_init_mut().bagua().await };
+                casts += 1;
+                unsafe { BUZZER.assume_init_mut().play(SoundEvent::BaGua) };
             }
             Timer::after_millis(800).await;
 
@@ -136,5 +139,6 @@ impl BaGua {
                 break;
             }
         }
+        casts
     }
 }
@@ -0,0 +1,316 @@
+#![doc = include_str!("../../rfcs/011_board_games.md")]
+
+use crate::{
+    lobby::{self, EspNowTransport},
+    sound::SoundEvent,
+    Ad, App, BUZZER,
+};
+use alloc::vec::Vec;
+use cube_board::{connect_four, reversi, tic_tac_toe, ConnectFour, Reversi, TicTacToe};
+use cube_net::{
+    game,
+    lobby::Transport,
+    turn::{Outcome, Player, Rules, Session, Status},
+};
+use embassy_time::{Instant, Timer};
+use embedded_graphics::{
+    pixelcolor::{Rgb888, WebColors},
+    Pixel,
+};
+
+/// 光标每移动一格需要的帧数
+const CURSOR_FRAMES: u32 = 3;
+
+/// 棋子的颜色
+fn color(player: Player) -> Rgb888 {
+    match player {
+        Player::First => Rgb888::CSS_RED,
+        Player::Second => Rgb888::CSS_BLUE,
+    }
+}
+
+/// 在点阵上显示棋盘
+trait Board: Rules {
+    /// 游戏代码
+    const GAME: u8;
+    /// 选择游戏时显示的图标
+    const ICON: [u8; 8];
+    /// 光标可以移动的范围
+    const CURSOR: (i32, i32);
+
+    /// 光标所在位置对应的着法
+    fn to_move(cursor: (i32, i32)) -> u8;
+
+    /// 绘制棋盘,轮到自己时显示光标
+    fn draw(&self, cursor: Option<(i32, i32)>, me: Player) -> Vec<Pixel<Rgb888>>;
+}
+
+/// 空白的点阵
+fn blank() -> Vec<Pixel<Rgb888>> {
+    let mut pixels = Vec::with_capacity(64);
+    for y in 0..8 {
+        for x in 0..8 {
+            pixels.push(Pixel((x, y).into(), Rgb888::CSS_BLACK));
+        }
+    }
+    pixels
+}
+
+impl Board for TicTacToe {
+    const GAME: u8 = game::TIC_TAC_TOE;
+    #[rustfmt::skip]
+    const ICON: [u8; 8] = [
+        0b00100100,
+        0b00100100,
+        0b11111111,
+        0b00100100,
+        0b00100100,
+        0b11111111,
+        0b00100100,
+        0b00100100,
+    ];
+    const CURSOR: (i32, i32) = (tic_tac_toe::SIZE as i32, tic_tac_toe::SIZE as i32);
+
+    fn to_move((x, y): (i32, i32)) -> u8 {
+        (y * tic_tac_toe::SIZE as i32 + x) as u8
+    }
+
+    /// 每一格占 2*2 个点,格子之间用暗色的线隔开
+    fn draw(&self, cursor: Option<(i32, i32)>, me: Player) -> Vec<Pixel<Rgb888>> {
+        let mut pixels = blank();
+        for i in [2, 5] {
+            for j in 0..8 {
+                pixels[(i * 8 + j) as usize].1 = Rgb888::CSS_DIM_GRAY;
+                pixels[(j * 8 + i) as usize].1 = Rgb888::CSS_DIM_GRAY;
+            }
+        }
+        for y in 0..3 {
+            for x in 0..3 {
+                let c = match self.get(x, y) {
+                    Some(player) => color(player),
+                    None if cursor == Some((x as i32, y as i32)) => color(me),
+                    None => continue,
+                };
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    pixels[((y * 3 + dy) * 8 + x * 3 + dx) as usize].1 = c;
+                }
+            }
+        }
+        pixels
+    }
+}
+
+impl Board for ConnectFour {
+    const GAME: u8 = game::CONNECT_FOUR;
+    #[rustfmt::skip]
+    const ICON: [u8; 8] = [
+        0b00010000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00001000,
+        0b00011000,
+        0b00111100,
+        0b01111110,
+    ];
+    const CURSOR: (i32, i32) = (connect_four::COLS as i32, 1);
+
+    fn to_move((x, _): (i32, i32)) -> u8 {
+        x as u8
+    }
+
+    /// 第一行显示光标,下面七行是棋盘
+    fn draw(&self, cursor: Option<(i32, i32)>, me: Player) -> Vec<Pixel<Rgb888>> {
+        let mut pixels = blank();
+        for y in 0..connect_four::ROWS {
+            for x in 0..connect_four::COLS {
+                if let Some(player) = self.get(x, y) {
+                    pixels[(y + 1) * 8 + x].1 = color(player);
+                }
+            }
+        }
+        if let Some((x, _)) = cursor {
+            pixels[x as usize].1 = color(me);
+        }
+        pixels
+    }
+}
+
+impl Board for Reversi {
+    const GAME: u8 = game::REVERSI;
+    #[rustfmt::skip]
+    const ICON: [u8; 8] = [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011000,
+        0b00011000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ];
+    const CURSOR: (i32, i32) = (reversi::SIZE as i32, reversi::SIZE as i32);
+
+    fn to_move((x, y): (i32, i32)) -> u8 {
+        (y * reversi::SIZE as i32 + x) as u8
+    }
+
+    /// 光标所在的格子可以落子时显示自己的颜色,否则显示黄色
+    fn draw(&self, cursor: Option<(i32, i32)>, me: Player) -> Vec<Pixel<Rgb888>> {
+        let mut pixels = blank();
+        for y in 0..8 {
+            for x in 0..8 {
+                if let Some(player) = self.get(x, y) {
+                    pixels[(y * 8 + x) as usize].1 = color(player);
+                }
+            }
+        }
+        if let Some(cursor) = cursor {
+            let c = if self.is_valid(me, Self::to_move(cursor)) {
+                color(me)
+            } else {
+                Rgb888::CSS_YELLOW
+            };
+            pixels[(cursor.1 * 8 + cursor.0) as usize].1 = c;
+        }
+        pixels
+    }
+}
+
+/// 可以选择的棋类游戏
+const GAMES: [[u8; 8]; 3] = [TicTacToe::ICON, ConnectFour::ICON, Reversi::ICON];
+
+/// 棋类游戏
+#[derive(Debug, Default)]
+pub struct BoardGame {
+    /// 当前游戏在 GAMES 中的索引
+    idx: usize,
+}
+
+impl BoardGame {
+    pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
+        if !self.pick(app).await {
+            return;
+        }
+        match self.idx {
+            0 => play::<TicTacToe, T>(app).await,
+            1 => play::<ConnectFour, T>(app).await,
+            _ => play::<Reversi, T>(app).await,
+        }
+    }
+
+    /// 左右倾斜选择游戏,向上确认,平放退出
+    async fn pick<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) -> bool {
+        app.ad = Ad::default();
+        // 从菜单进入时仍是向上倾斜,回正之后才能确认
+        let mut armed = false;
+        loop {
+            app.ledc.write_bytes(GAMES[self.idx]);
+            Timer::after_millis(300).await;
+
+            app.acc_direction();
+            match app.ad {
+                Ad::None => armed = true,
+                Ad::Right => {
+                    self.idx = (self.idx + 1) % GAMES.len();
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuSelect) };
+                }
+                Ad::Left => {
+                    self.idx = (self.idx + GAMES.len() - 1) % GAMES.len();
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuSelect) };
+                }
+                Ad::Front if armed => {
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuConfirm) };
+                    return true;
+                }
+                Ad::Down => return false,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// 配对之后开始对局:倾斜移动光标,摇一摇落子,一局结束后摇一摇再来一局,平放退出
+async fn play<B: Board, T: esp_hal::i2c::Instance>(app: &mut App<'_, T>) {
+    app.ledc.clear();
+    app.ad = Ad::default();
+
+    let Some(peer) = lobby::find_peer(app, B::GAME).await else {
+        return;
+    };
+    let mut session = Session::<B>::new(B::GAME, peer);
+    let mut cursor = (B::CURSOR.0 / 2, B::CURSOR.1 / 2);
+    let mut frame = 0;
+    // 上一帧的状态,用来在一局结束时播放一次音效
+    let mut last = Status::Playing;
+
+    loop {
+        Timer::after_millis(100).await;
+        frame += 1;
+
+        app.acc_direction();
+        if app.quit() {
+            session.leave(&mut EspNowTransport(&mut app.esp_now));
+            return;
+        }
+        let shake = app.shake();
+
+        let now = Instant::now().as_millis();
+        let status = session.poll(now, &mut EspNowTransport(&mut app.esp_now));
+        match status {
+            Status::Playing if session.my_turn() => {
+                if shake {
+                    let mv = B::to_move(cursor);
+                    if session.play(now, &mut EspNowTransport(&mut app.esp_now), mv) {
+                        unsafe { BUZZER.assume_init_mut().play(SoundEvent::PlayBallHit) };
+                    }
+                } else if frame % CURSOR_FRAMES == 0 {
+                    cursor = move_cursor::<B>(cursor, app.ad);
+                }
+            }
+            Status::Playing => {}
+            Status::Over(outcome) => {
+                if last == Status::Playing {
+                    match outcome {
+                        Outcome::Win(player) if player == session.me() => {
+                            unsafe { BUZZER.assume_init_mut().victory() };
+                            app.face.break_record_animate(&mut app.ledc).await;
+                        }
+                        Outcome::Win(_) => unsafe {
+                            BUZZER.assume_init_mut().play(SoundEvent::PlayBallMiss)
+                        },
+                        Outcome::Draw => unsafe {
+                            BUZZER.assume_init_mut().play(SoundEvent::PlayBallScore)
+                        },
+                    }
+                }
+                if shake {
+                    session.request_rematch(&mut EspNowTransport(&mut app.esp_now));
+                }
+            }
+            Status::Disconnected => {
+                EspNowTransport(&mut app.esp_now).remove_peer(&session.peer().address);
+                unsafe { BUZZER.assume_init_mut().play(SoundEvent::PlayBallMiss) };
+                return;
+            }
+        }
+        last = status;
+
+        // 轮到自己时光标闪烁
+        let show = session.my_turn() && frame % 4 < 3;
+        let pixels = session.rules().draw(show.then_some(cursor), session.me());
+        app.ledc.write_pixels(pixels);
+    }
+}
+
+/// 倾斜时光标移动一格,到边上时从另一边出来
+fn move_cursor<B: Board>((x, y): (i32, i32), ad: Ad) -> (i32, i32) {
+    let (w, h) = B::CURSOR;
+    match ad {
+        Ad::Left => ((x + w - 1) % w, y),
+        Ad::Right => ((x + 1) % w, y),
+        Ad::Front => (x, (y + h - 1) % h),
+        Ad::Back => (x, (y + 1) % h),
+        _ => (x, y),
+    }
+}
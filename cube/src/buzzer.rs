@@ -1,32 +1,83 @@
-use crate::{BUZZER, RNG};
-use alloc::vec::Vec;
+use crate::{
+    sound::{Priority, Sound, SoundEvent, Theme, Volume},
+    RNG,
+};
+use cube_melody::Melody;
 use cube_rand::CubeRng;
-use embassy_executor::Spawner;
-use embassy_time::Timer;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Duration, Instant, Timer};
 use esp_hal::{
     gpio::GpioPin,
     ledc::{
         channel::{self, config::PinConfig},
         timer, Ledc, LowSpeed,
     },
+    peripherals::LEDC,
     prelude::*,
 };
 
-/// 蜂鸣器
-pub struct Buzzer<'d> {
+/// 胜利的旋律,RTTTL 格式
+pub const VICTORY: &str = "victory:d=8,o=6,b=180:c,e,g,4c7,p,g,2c7";
+
+/// 蜂鸣器使用的定时器和通道,与 [`Speaker::configure`] 中的一致
+const TIMER: usize = 0;
+const CHANNEL: usize = 0;
+/// 定时器的时钟,APB 时钟 80MHz
+const CLOCK: u64 = 80_000_000;
+/// 占空比分辨率为 13 位
+const DUTY_BITS: u32 = 13;
+const DUTY_MAX: u32 = (1 << DUTY_BITS) - 1;
+/// 初始配置时的频率,配置完成后立即关闭输出
+const IDLE_FREQUENCY: u32 = 1000;
+
+/// 最多排队的声音数,超过时丢弃优先级最低的
+const QUEUE_LEN: usize = 8;
+
+/// 发给音频任务的命令,播放的命令带上发送时的音量
+pub enum Command {
+    /// 播放音效
+    Sound(&'static Sound, Volume),
+    /// 播放一个音
+    Tone {
+        frequency: u32,
+        duration: u64,
+        priority: Priority,
+        volume: Volume,
+    },
+    /// 播放旋律
+    Melody(Melody, Priority, Volume),
+    /// 停止播放并清空队列
+    Stop,
+}
+
+/// 音频任务的命令队列
+pub static AUDIO: Channel<CriticalSectionRawMutex, Command, QUEUE_LEN> = Channel::new();
+
+/// 蜂鸣器,只保存设置并向音频任务发送命令,发声的硬件由 [`audio_task`] 独占
+pub struct Buzzer {
     pub open: bool,
-    pin: GpioPin<11>,
-    ledc: Ledc<'d>,
-    spawner: Spawner,
+    /// 音效主题
+    pub theme: Theme,
+    /// 音量
+    pub volume: Volume,
+    /// 解析好的胜利旋律,只在新建时解析一次
+    victory: Option<Melody>,
+}
+
+impl Default for Buzzer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl<'d> Buzzer<'d> {
-    pub fn new(pin: GpioPin<11>, ledc: Ledc<'d>, spawner: Spawner) -> Self {
+impl Buzzer {
+    pub fn new() -> Self {
         Self {
             open: true,
-            ledc,
-            pin,
-            spawner,
+            theme: Theme::default(),
+            volume: Volume::default(),
+            victory: cube_melody::parse(VICTORY).ok(),
         }
     }
 
@@ -42,263 +93,300 @@ impl<'d> Buzzer<'d> {
         self.open = !self.open
     }
 
-    /// FIXME: esp_hal::ledc 暂时仅支持固定频率输出，不同频率需要重新配置定时器和通道
-    async fn drive(&mut self, frequency: u32, duty_pct: u8) {
-        // 定时器配置:指定 PWM 信号的频率和占空比分辨率
-        let mut lstimer0 = self.ledc.get_timer::<LowSpeed>(timer::Number::Timer0);
-        lstimer0
-            .configure(timer::config::Config {
-                duty: timer::config::Duty::Duty13Bit,
-                clock_source: timer::LSClockSource::APBClk,
-                frequency: frequency.Hz(),
-            })
-            .unwrap();
-        // 通道配置:绑定定时器和输出 PWM 信号的 GPIO
-        let mut channel0 = self
-            .ledc
-            .get_channel(channel::Number::Channel0, &mut self.pin);
-        channel0
-            .configure(channel::config::Config {
-                timer: &lstimer0,
-                duty_pct,
-                pin_config: PinConfig::PushPull,
-            })
-            .unwrap();
-    }
-
-    /// 发声
+    /// 发声,等到播放结束再返回
     /// frequency: 发声频率,单位HZ
     /// duration: 发声时长,单位毫秒
     pub async fn tone(&mut self, frequency: u32, duration: u64) {
-        self.drive(frequency, 50).await;
-        Timer::after_millis(duration).await;
-        if duration != 0 {
-            self.no_tone().await;
+        if self.open {
+            AUDIO
+                .try_send(Command::Tone {
+                    frequency,
+                    duration,
+                    priority: Priority::Normal,
+                    volume: self.volume,
+                })
+                .ok();
         }
+        Timer::after_millis(duration).await;
     }
 
-    /// 停止发声
-    pub async fn no_tone(&mut self) {
-        self.drive(1, 0).await;
-    }
-
-    /// 菜单选择音效
-    pub async fn menu_select(&mut self) {
+    /// 按当前的主题播放事件对应的音效,关闭声音时不播放,队列满时丢弃
+    pub fn play(&mut self, event: SoundEvent) {
         if !self.open {
             return;
         }
-        self.spawner.spawn(tone_task(1500, 300)).ok();
+        let sounds = self.theme.sounds(event);
+        let sound = match sounds.len() {
+            0 => return,
+            1 => &sounds[0],
+            len => {
+                let i =
+                    unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random_range(0..len) };
+                &sounds[i]
+            }
+        };
+        AUDIO.try_send(Command::Sound(sound, self.volume)).ok();
     }
 
-    /// 菜单确认音效
-    pub async fn menu_confirm(&mut self) {
+    /// 在后台播放旋律,会打断优先级更低的声音
+    pub fn play_melody(&mut self, melody: Melody, priority: Priority) {
         if !self.open {
             return;
         }
-        let range = (400..2000).step_by(100).collect::<Vec<u32>>();
-        self.spawner.spawn(tone_range_task(range, 50)).ok();
+        AUDIO
+            .try_send(Command::Melody(melody, priority, self.volume))
+            .ok();
     }
 
-    /// 菜单进入音效
-    pub async fn menu_access(&mut self) {
-        if !self.open {
-            return;
-        }
-        let range = (200..=3000).rev().step_by(200).collect::<Vec<u32>>();
-        self.spawner.spawn(tone_range_task(range, 50)).ok();
+    /// 停止播放
+    pub fn stop(&mut self) {
+        AUDIO.try_send(Command::Stop).ok();
     }
 
-    /// 八卦音效
-    pub async fn bagua(&mut self) {
-        if !self.open {
+    /// 胜利音效,只播放提醒时不播放
+    pub fn victory(&mut self) {
+        if self.theme == Theme::Alerts {
             return;
         }
-        let range = (200..=3000).rev().step_by(200).collect::<Vec<u32>>();
-        self.spawner.spawn(tone_range_task(range, 50)).ok();
-    }
-
-    /// 骰子音效
-    pub async fn dice(&mut self) {
-        if !self.open {
-            return;
+        if let Some(melody) = self.victory.clone() {
+            self.play_melody(melody, Priority::Normal);
         }
-        let range = (200..=3000).rev().step_by(400).collect::<Vec<u32>>();
-        self.spawner.spawn(tone_range_task(range, 50)).ok();
     }
+}
 
-    /// 迷宫移动音效
-    pub async fn maze_move(&mut self) {
-        if !self.open {
-            return;
-        }
-        self.spawner.spawn(tone_task(5000, 100)).ok();
-    }
+/// 蜂鸣器的硬件
+pub struct Speaker<'d> {
+    /// 配置之后直接操作寄存器,只持有引脚和 LEDC 防止被其他地方使用
+    _pin: GpioPin<11>,
+    _ledc: Ledc<'d>,
+}
 
-    /// 迷宫结束音效
-    pub async fn maze_over(&mut self) {
-        if !self.open {
-            return;
+impl<'d> Speaker<'d> {
+    pub fn new(mut pin: GpioPin<11>, mut ledc: Ledc<'d>) -> Self {
+        Self::configure(&mut pin, &mut ledc);
+        Self {
+            _ledc: ledc,
+            _pin: pin,
         }
-        self.spawner
-            .spawn(tone_ranges_task(&[
-                (6000, 100),
-                (6000, 100),
-                (6000, 100),
-                (6000, 150),
-            ]))
-            .ok();
-    }
-
-    /// 休眠开启音效
-    pub async fn hibernation(&mut self) {
-        self.spawner
-            .spawn(tone_ranges_task(&[(8000, 100), (2500, 100), (800, 100)]))
-            .ok();
     }
 
-    /// 开机音效
-    pub async fn power_on(&mut self) {
-        self.spawner
-            .spawn(tone_ranges_task(&[(800, 200), (2500, 100), (8000, 200)]))
-            .ok();
-    }
-
-    /// 唤醒音效
-    pub async fn wakeup(&mut self) {
-        self.spawner
-            .spawn(tone_ranges_task(&[(1500, 200), (8000, 200)]))
-            .ok();
-    }
-
-    /// 沙漏像素闪烁音效
-    pub async fn timer_pixel_blinky(&mut self) {
-        if !self.open {
-            return;
-        }
-        self.spawner.spawn(tone_task(8000, 100)).ok();
+    /// 配置一次定时器和通道,之后只修改分频和占空比
+    ///
+    /// 配置完成之后通道先关闭输出,esp_hal 的通道不能和 Ledc 保存在同一个结构体中,所以之后直接操作寄存器
+    fn configure(pin: &mut GpioPin<11>, ledc: &mut Ledc<'d>) {
+        // 定时器配置:指定 PWM 信号的频率和占空比分辨率
+        let mut lstimer0 = ledc.get_timer::<LowSpeed>(timer::Number::Timer0);
+        lstimer0
+            .configure(timer::config::Config {
+                duty: timer::config::Duty::Duty13Bit,
+                clock_source: timer::LSClockSource::APBClk,
+                frequency: IDLE_FREQUENCY.Hz(),
+            })
+            .unwrap();
+        // 通道配置:绑定定时器和输出 PWM 信号的 GPIO
+        let mut channel0 = ledc.get_channel(channel::Number::Channel0, pin);
+        channel0
+            .configure(channel::config::Config {
+                timer: &lstimer0,
+                duty_pct: 0,
+                pin_config: PinConfig::PushPull,
+            })
+            .unwrap();
+        Self::gate();
     }
 
-    /// 沙漏像素反弹音效
-    pub async fn timer_pixel_rebound(&mut self) {
-        if !self.open {
-            return;
-        }
-        self.spawner.spawn(tone_task(4000, 100)).ok();
+    /// 输出指定频率和占空比的 PWM 信号
+    ///
+    /// 频率通过定时器的分频系数修改,在下一个周期生效,不需要重新配置定时器和通道
+    fn drive(&mut self, frequency: u32, duty_pct: u8) {
+        let ledc = unsafe { &*LEDC::ptr() };
+        let timer = ledc.timer(TIMER);
+        timer.conf().modify(|_, w| unsafe {
+            w.clk_div()
+                .bits(divisor(frequency))
+                .pause()
+                .clear_bit()
+                .para_up()
+                .set_bit()
+        });
+
+        let duty = (DUTY_MAX * duty_pct.min(100) as u32 / 100) << 4;
+        let ch = ledc.ch(CHANNEL);
+        ch.hpoint().write(|w| unsafe { w.hpoint().bits(0) });
+        ch.duty().write(|w| unsafe { w.duty().bits(duty) });
+        ch.conf1().write(|w| unsafe {
+            w.duty_start()
+                .set_bit()
+                .duty_inc()
+                .set_bit()
+                .duty_num()
+                .bits(1)
+                .duty_cycle()
+                .bits(1)
+                .duty_scale()
+                .bits(0)
+        });
+        ch.conf0()
+            .modify(|_, w| w.sig_out_en().set_bit().para_up().set_bit());
     }
 
-    /// 沙漏结束音效
-    pub async fn timers_over(&mut self) {
-        if !self.open {
-            return;
-        }
-        self.spawner
-            .spawn(tone_ranges_task(&[
-                (6000, 100),
-                (6000, 100),
-                (6000, 100),
-                (6000, 150),
-            ]))
-            .ok();
+    /// 关闭输出,引脚保持低电平并暂停定时器,避免静音时的杂音
+    fn gate() {
+        let ledc = unsafe { &*LEDC::ptr() };
+        ledc.ch(CHANNEL).conf0().modify(|_, w| {
+            w.sig_out_en()
+                .clear_bit()
+                .idle_lv()
+                .clear_bit()
+                .para_up()
+                .set_bit()
+        });
+        ledc.timer(TIMER).conf().modify(|_, w| w.pause().set_bit());
     }
 
-    /// 贪吃蛇移动音效
-    pub async fn snake_move(&mut self) {
-        if !self.open {
-            return;
-        }
-        self.spawner.spawn(tone_task(5000, 100)).ok();
+    /// 停止发声
+    fn no_tone(&mut self) {
+        Self::gate();
     }
+}
 
-    /// 贪吃蛇得分音效
-    pub async fn snake_score(&mut self) {
-        if !self.open {
-            return;
-        }
-        self.spawner
-            .spawn(tone_ranges_task(&[
-                (2000, 1000),
-                (3000, 1000),
-                (2000, 1000),
-            ]))
-            .ok();
-    }
+/// 定时器的分频系数,低 8 位为小数部分,超出范围时取最近的可用值
+///
+/// 13 位分辨率时可以输出约 10Hz 到 9.7kHz 的频率
+fn divisor(frequency: u32) -> u32 {
+    let divisor = (CLOCK << 8) / ((frequency.max(1) as u64) << DUTY_BITS);
+    divisor.clamp(1 << 8, (1 << 18) - 1) as u32
+}
 
-    /// 贪吃蛇死亡音效
-    pub async fn snake_die(&mut self) {
-        if !self.open {
-            return;
-        }
-        self.spawner
-            .spawn(tone_ranges_task(&[(500, 1000), (300, 1000), (100, 1000)]))
-            .ok();
-    }
+/// 正在播放或者排队的声音
+struct Clip {
+    priority: Priority,
+    volume: Volume,
+    source: Source,
+    /// 下一个要播放的音
+    idx: usize,
+}
 
-    /// 推箱子移动音效
-    pub async fn sokoban_move(&mut self) {
-        if !self.open {
-            return;
-        }
-        self.spawner.spawn(tone_task(5000, 100)).ok();
-    }
+enum Source {
+    Sound(&'static Sound),
+    Tone(u32, u64),
+    Melody(Melody),
+}
 
-    /// 休眠音效
-    pub async fn sleep(&mut self) {
-        if !self.open {
-            return;
-        }
-        self.spawner.spawn(tone_task(6000, 100)).ok();
+impl Clip {
+    /// 停止命令返回 None
+    fn new(command: Command) -> Option<Self> {
+        let (priority, volume, source) = match command {
+            Command::Sound(sound, volume) => (sound.priority, volume, Source::Sound(sound)),
+            Command::Tone {
+                frequency,
+                duration,
+                priority,
+                volume,
+            } => (priority, volume, Source::Tone(frequency, duration)),
+            Command::Melody(melody, priority, volume) => (priority, volume, Source::Melody(melody)),
+            Command::Stop => return None,
+        };
+        Some(Self {
+            priority,
+            volume,
+            source,
+            idx: 0,
+        })
     }
 
-    /// 休眠音效2
-    pub async fn sleep2(&mut self) {
-        if !self.open {
-            return;
-        }
-        self.spawner
-            .spawn(tone_task(
-                unsafe {
-                    CubeRng(RNG.assume_init_mut().random() as u64).random_range(3000..=9000) as u32
-                },
-                100,
-            ))
-            .ok();
+    /// 取出下一个音的频率和时长,播放完时返回 None
+    fn next(&mut self) -> Option<(u32, u64)> {
+        let tone = match &self.source {
+            Source::Sound(sound) => sound.tones.get(self.idx).copied(),
+            Source::Tone(frequency, duration) => (self.idx == 0).then_some((*frequency, *duration)),
+            Source::Melody(melody) => melody
+                .notes
+                .get(self.idx)
+                .map(|n| (n.frequency(), n.duration.millis(melody.bpm) as u64)),
+        };
+        self.idx += 1;
+        tone
     }
+}
 
-    /// 眨眼音效
-    pub async fn blinky(&mut self) {
-        if !self.open {
-            return;
+/// 排队的声音
+type Queue = heapless::Vec<Clip, QUEUE_LEN>;
+
+/// 处理一个命令,打断当前的声音时返回 true
+///
+/// 优先级比当前的声音高时立即播放,否则排队;队列满时丢弃优先级最低的
+fn accept(command: Command, current: &mut Option<Clip>, queue: &mut Queue) -> bool {
+    let Some(clip) = Clip::new(command) else {
+        queue.clear();
+        *current = None;
+        return true;
+    };
+    match current {
+        Some(c) if clip.priority <= c.priority => {
+            if let Err(clip) = queue.push(clip) {
+                // 同样的优先级先来的先播放,所以丢弃最后一个优先级最低的
+                let lowest = queue
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .min_by_key(|(_, c)| c.priority)
+                    .map(|(i, c)| (i, c.priority));
+                if let Some((i, priority)) = lowest {
+                    if clip.priority > priority {
+                        queue.remove(i);
+                        queue.push(clip).ok();
+                    }
+                }
+            }
+            false
         }
-        self.spawner.spawn(tone_task(8000, 100)).ok();
-    }
-
-    /// 眨眼音效2
-    pub async fn blinky2(&mut self) {
-        if !self.open {
-            return;
+        _ => {
+            *current = Some(clip);
+            true
         }
-        self.spawner.spawn(tone_task(5000, 100)).ok();
     }
 }
 
-#[embassy_executor::task]
-async fn tone_task(frequency: u32, duration: u64) {
-    let buzzer = unsafe { BUZZER.assume_init_mut() };
-    buzzer.tone(frequency, duration).await;
+/// 从队列中取出优先级最高、最先到的声音
+fn dequeue(queue: &mut Queue) -> Option<Clip> {
+    let priority = queue.iter().map(|c| c.priority).max()?;
+    let i = queue.iter().position(|c| c.priority == priority)?;
+    Some(queue.remove(i))
 }
 
+/// 音频任务,独占蜂鸣器的硬件,所有的声音都由它播放
 #[embassy_executor::task]
-async fn tone_range_task(freq_range: Vec<u32>, duration: u64) {
-    let buzzer = unsafe { BUZZER.assume_init_mut() };
-    for i in freq_range {
-        buzzer.tone(i, duration).await;
-    }
-}
-
-#[embassy_executor::task]
-async fn tone_ranges_task(range: &'static [(u32, u64)]) {
-    let buzzer = unsafe { BUZZER.assume_init_mut() };
-    for (freq, dur) in range {
-        buzzer.tone(*freq, *dur).await;
+pub async fn audio_task(mut speaker: Speaker<'static>) {
+    let mut queue = Queue::new();
+    let mut current: Option<Clip> = None;
+
+    loop {
+        let Some(clip) = current.as_mut() else {
+            current = dequeue(&mut queue);
+            if current.is_none() {
+                // 空闲时停止发声,等待新的命令
+                speaker.no_tone();
+                accept(AUDIO.receive().await, &mut current, &mut queue);
+            }
+            continue;
+        };
+        let Some((frequency, duration)) = clip.next() else {
+            current = None;
+            continue;
+        };
+
+        if frequency == 0 {
+            speaker.no_tone();
+        } else {
+            speaker.drive(frequency, clip.volume.duty_pct());
+        }
+        // 播放的过程中继续接收命令,被打断时立即播放新的声音
+        let deadline = Instant::now() + Duration::from_millis(duration);
+        while let Either::Second(command) = select(Timer::at(deadline), AUDIO.receive()).await {
+            if accept(command, &mut current, &mut queue) {
+                break;
+            }
+        }
     }
 }
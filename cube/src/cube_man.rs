@@ -11,15 +11,39 @@ use embedded_graphics_core::{
     Pixel,
 };
 
+/// 易碎楼梯碎裂前闪烁的次数
+const FRAGILE_BLINKS: usize = 3;
+/// 闪烁一次的帧数,熄灭一帧再点亮一帧,碎裂的时间跟着帧间隔变化
+const BLINK_FRAMES: usize = 2;
+/// 弹簧楼梯的反弹高度
+const SPRING_HEIGHT: u8 = 2;
+/// 楼梯之间最少间隔的行数,即一个人物的高度
+const MIN_GAP: usize = 1;
+/// 每下降多少层提升一次难度
+const DIFFICULTY_STEP: usize = 50;
+/// 人物初始下落速度,单位格/帧
+const FALL_SPEED: f32 = 1.0;
+/// 人物下落的加速度,单位格/帧
+const GRAVITY: f32 = 0.25;
+/// 人物最大下落速度,比楼梯上升的速度快
+const MAX_FALL_SPEED: f32 = 2.0;
+/// 初始帧间隔,单位毫秒
+const WAITING_TIME: u64 = 230;
+/// 最小帧间隔,单位毫秒
+const MIN_WAITING_TIME: u64 = 120;
+/// 每下降多少层帧间隔缩短一次
+const SPEED_STEP: usize = 30;
+
 /// 是方块人就下一百层
 #[derive(Debug)]
 pub struct CubeManGame {
     man: CubeMan,
     floors: VecDeque<Option<Floor>>,
     floor_gen: FloorGen,
+    /// 下降的层数
     depth: usize,
-    score: u8,
-    pub highest: u8,
+    score: u16,
+    pub highest: u16,
     game_over: bool,
     /// ms
     waiting_time: u64,
@@ -33,27 +57,40 @@ impl Default for CubeManGame {
 
 impl CubeManGame {
     pub fn new() -> Self {
-        let floors = FloorGen::init();
+        let mut floors = FloorGen::init();
+        // 人物最开始站在正常的楼梯上
+        let data = (2..6).map(|x| Point::new(x, 4)).collect::<Vec<_>>();
+        floors[4] = Some(Floor::new(FloorType::Normal, &data));
 
         Self {
-            man: CubeMan::new((0, 0).into()),
+            man: CubeMan::new((3, 3).into()),
             floors,
             floor_gen: FloorGen::new(),
             depth: 0,
             score: 0,
             highest: 0,
             game_over: false,
-            waiting_time: 230,
+            waiting_time: WAITING_TIME,
         }
     }
 
+    /// 越过的楼梯层数
+    pub fn score(&self) -> u16 {
+        self.score
+    }
+
+    /// 下到的层数
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
     pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
         app.ledc.clear();
         app.ad = Ad::default();
 
         loop {
             if self.game_over {
-                app.ledc.draw_score(self.score);
+                app.ledc.draw_score(self.score.into()).await;
                 Timer::after_millis(1500).await;
                 if self.score > self.highest {
                     self.highest = self.score;
@@ -63,52 +100,84 @@ impl CubeManGame {
                 break;
             }
             app.acc_direction();
+            // 碎裂的楼梯消失
+            self.floors.iter_mut().for_each(|f| {
+                if f.as_ref().is_some_and(Floor::broken) {
+                    *f = None;
+                }
+            });
+            let standing = self.on_floor(&self.man.pos).is_some();
             {
                 self.floors.pop_front();
                 self.floors.push_back(self.floor_gen.floor(self.depth));
-                self.floors.iter_mut().for_each(|f| {
-                    if let Some(f) = f {
-                        f.data.iter_mut().for_each(|f| f.0.y -= 1);
-                    }
+                self.floors.iter_mut().flatten().for_each(|f| {
+                    f.data.iter_mut().for_each(|f| f.0.y -= 1);
+                    f.tick();
                 });
             }
-            self.r#move(app).await;
+            // 随楼梯一起向上运动
+            if standing {
+                self.man.up();
+            }
+            self.r#move(app.ad);
+            self.calc_score();
             // TODO: 移动音效,得分音效和画面效果,死亡音效
             self.draw(app);
 
             Timer::after_millis(self.waiting_time).await;
             self.depth += 1;
+            self.speed_up();
         }
     }
 
-    async fn r#move<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
-        let np = self.man.next_pos(app);
-        if self.outside(&np) {
-            self.game_over = true;
-        } else if self.hit_wall(&np) {
-            return;
+    fn r#move(&mut self, ad: Ad) {
+        // 左右移动
+        let np = self.man.next_pos(ad);
+        if !self.hit_wall(&np) {
+            self.man.r#move(ad);
+        }
+        // 如果下面是楼梯,在停在楼梯上
+        if let Some(idx) = self.on_floor(&self.man.pos) {
+            self.man.land();
+            self.moving_on_floor(idx, ad);
         } else {
-            self.man.r#move(app);
-            // 如果下面是楼梯,在停在楼梯上
-            if let Some(floor) = Self::on_floor(
-                &self
-                    .floors
-                    .iter()
-                    .filter_map(|f| f.clone())
-                    .collect::<Vec<_>>(),
-                &self.man.pos,
-            ) {
-                // 随楼梯一起向上运动
-                self.man.up();
-                self.moving_on_floor(&floor, app).await;
-            } else {
-                self.man.fall();
+            self.fall();
+        }
+        if self.outside(&self.man.pos) {
+            self.game_over = true;
+        }
+    }
+
+    /// 人物下落,逐格检测是否落到楼梯上
+    fn fall(&mut self) {
+        let target = self.man.y + self.man.fall_speed;
+        self.man.accelerate();
+        while (self.man.pos.y + 1) as f32 <= target {
+            self.man.set_y((self.man.pos.y + 1) as f32);
+            if self.on_floor(&self.man.pos).is_some() {
+                self.man.land();
+                return;
             }
         }
+        self.man.set_y(target);
     }
 
+    /// 人物每越过一层楼梯得一分
     fn calc_score(&mut self) {
-        self.score += 1;
+        let y = self.man.pos.y;
+        for f in self.floors.iter_mut().flatten() {
+            if !f.passed && f.data.iter().all(|p| p.0.y < y) {
+                f.passed = true;
+                self.score = self.score.saturating_add(1);
+            }
+        }
+    }
+
+    /// 越往下帧间隔越短
+    fn speed_up(&mut self) {
+        self.waiting_time = WAITING_TIME
+            .saturating_sub((self.depth / SPEED_STEP) as u64 * 10)
+            .max(MIN_WAITING_TIME);
     }
 
     fn outside(&self, pos: &Point) -> bool {
@@ -116,65 +185,56 @@ impl CubeManGame {
     }
 
     fn hit_wall(&self, pos: &Point) -> bool {
-        pos.x < 0 || pos.x >= 8
+        pos.x < 0 || pos.x >= 8 || self.occupied(pos)
     }
 
-    /// 是否在楼梯上
-    fn on_floor(floors: &[Floor], pos: &Point) -> Option<Floor> {
-        let floor = floors.iter().find(|f| {
-            let min = f.data.iter().min_by(|x, y| x.cmp(y)).unwrap();
-            let max = f.data.iter().max_by(|x, y| x.cmp(y)).unwrap();
-            f.data
-                .iter()
-                .any(|p| min.0.x <= pos.x && pos.x <= max.0.x && p.0.y == pos.y + 1)
-        });
-        floor.cloned()
+    /// 该位置是否有楼梯
+    fn occupied(&self, pos: &Point) -> bool {
+        self.floors
+            .iter()
+            .flatten()
+            .any(|f| f.data.iter().any(|p| p.0 == *pos))
+    }
+
+    /// 人物所在楼梯的索引
+    fn on_floor(&self, pos: &Point) -> Option<usize> {
+        self.floors.iter().position(|f| {
+            f.as_ref()
+                .is_some_and(|f| f.data.iter().any(|p| p.0.x == pos.x && p.0.y == pos.y + 1))
+        })
     }
 
     /// 在楼梯上的移动
-    async fn moving_on_floor<T: esp_hal::i2c::Instance>(
-        &mut self,
-        floor: &Floor,
-        app: &mut App<'_, T>,
-    ) {
-        match &floor.r#type {
+    fn moving_on_floor(&mut self, idx: usize, ad: Ad) {
+        let Some(floor) = self.floors[idx].as_mut() else {
+            return;
+        };
+        let ft = floor.r#type;
+        match ft {
             FloorType::Normal => {}
-            FloorType::Fragile(t) => {
-                let mut fds = floor.data.clone();
-                for _ in 0..3 {
-                    for fd in fds.iter_mut() {
-                        fd.1 = BinaryColor::from(fd.1).invert().into();
-                    }
-                    app.ledc.write_pixels(fds.clone());
-                    Timer::after_millis(50).await;
-                }
-
-                for fd in fds.iter_mut() {
-                    fd.1 = BinaryColor::Off.into();
-                }
-                app.ledc.write_pixels(fds);
-
-                Timer::after_millis(*t).await;
-            }
+            FloorType::Fragile(_) => floor.crumble(),
             FloorType::Conveyor(cd) => {
-                if app.ad == Ad::Left || app.ad == Ad::Right {
+                if ad == Ad::Left || ad == Ad::Right {
                     return;
                 }
-                match cd {
-                    ConveyorDir::Clockwise => {
-                        if self.man.pos.x + 1 < 8 {
-                            self.man.pos.x += 1;
-                        }
-                    }
-                    ConveyorDir::Counterclockwise => {
-                        if self.man.pos.x - 1 < 8 {
-                            self.man.pos.x -= 1;
-                        }
-                    }
+                let dx = match cd {
+                    ConveyorDir::Clockwise => 1,
+                    ConveyorDir::Counterclockwise => -1,
+                };
+                let np = Point::new(self.man.pos.x + dx, self.man.pos.y);
+                if !self.hit_wall(&np) {
+                    self.man.shift(dx);
                 }
             }
             FloorType::Spring(h) => {
-                self.man.pos.y -= *h as i32;
+                // 弹起,碰到上方的楼梯则停下
+                for _ in 0..h {
+                    let np = Point::new(self.man.pos.x, self.man.pos.y - 1);
+                    if self.occupied(&np) {
+                        break;
+                    }
+                    self.man.up();
+                }
             }
         };
     }
@@ -182,13 +242,8 @@ impl CubeManGame {
     pub fn draw<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<T>) {
         app.ledc.clear_with_color(BinaryColor::Off.into());
         // 楼梯
-        app.ledc.write_pixels(
-            self.floors
-                .iter()
-                .filter(|f| f.is_some())
-                .cloned()
-                .flat_map(|f| f.unwrap().data),
-        );
+        app.ledc
+            .write_pixels(self.floors.iter().flatten().flat_map(|f| f.pixels()));
 
         // 人物
         let mp = self.man.pos;
@@ -211,19 +266,53 @@ enum ConveyorDir {
 enum FloorType {
     /// 正常
     Normal,
-    /// 易碎(碎裂时间)
-    Fragile(u64),
+    /// 易碎(碎裂前闪烁的次数)
+    Fragile(usize),
     /// 传送带(传送带旋转方向)
     Conveyor(ConveyorDir),
     /// 弹簧(反弹的高度)
     Spring(u8),
 }
 
-// impl FloorType {
-//     fn random() -> Self {
-//         todo!()
-//     }
-// }
+impl FloorType {
+    /// 根据深度按权重随机生成楼梯类型
+    fn random(depth: usize) -> Self {
+        let [normal, fragile, conveyor, _] = Self::weights(depth);
+        let per = unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random(0, 100) };
+        if per < normal {
+            FloorType::Normal
+        } else if per < normal + fragile {
+            FloorType::Fragile(FRAGILE_BLINKS)
+        } else if per < normal + fragile + conveyor {
+            let dir = unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random(0, 2) };
+            if dir == 0 {
+                FloorType::Conveyor(ConveyorDir::Clockwise)
+            } else {
+                FloorType::Conveyor(ConveyorDir::Counterclockwise)
+            }
+        } else {
+            FloorType::Spring(SPRING_HEIGHT)
+        }
+    }
+
+    /// 各楼梯类型的权重(百分比),依次为正常,易碎,传送带,弹簧
+    /// 初始为 70%,10%,10%,10%,每下降 DIFFICULTY_STEP 层正常楼梯减少 5%,最少 40%,
+    /// 减少的部分平分给陷阱楼梯
+    fn weights(depth: usize) -> [u32; 4] {
+        let shift = (depth / DIFFICULTY_STEP) as u32 * 5;
+        let normal = 70u32.saturating_sub(shift).max(40);
+        let trap = (100 - normal) / 3;
+        [normal, trap, trap, 100 - normal - trap * 2]
+    }
+
+    /// 楼梯的最小长度,传送带两端不闪烁,中间需要闪烁,最少为 4
+    fn min_len(&self) -> usize {
+        match self {
+            FloorType::Conveyor(_) => 4,
+            _ => 3,
+        }
+    }
+}
 
 /// 楼梯
 #[derive(Debug, Clone)]
@@ -231,47 +320,84 @@ struct Floor {
     /// 类型
     r#type: FloorType,
     data: Vec<Pixel<Rgb888>>,
+    /// 动画帧,每一帧前进一次
+    frame: usize,
+    /// 易碎楼梯是否正在碎裂,开始碎裂时 frame 从 0 开始计数
+    crumbling: bool,
+    /// 人物是否已经越过该楼梯
+    passed: bool,
 }
 
 impl Floor {
     fn new(ft: FloorType, data: &[Point]) -> Self {
-        match ft {
-            FloorType::Normal => Self {
-                r#type: ft,
-                data: data
-                    .iter()
-                    .map(|p| Pixel((p.x, p.y).into(), RgbColor::WHITE))
-                    .collect::<Vec<_>>(),
-            },
-            FloorType::Fragile(_) => Self {
-                r#type: ft,
-                data: data
-                    .iter()
-                    .map(|p| Pixel((p.x, p.y).into(), RgbColor::RED))
-                    .collect::<Vec<_>>(),
-            },
-            FloorType::Conveyor(_) => Self {
-                r#type: ft,
-                data: data
-                    .iter()
-                    .map(|p| Pixel((p.x, p.y).into(), RgbColor::GREEN))
-                    .collect::<Vec<_>>(),
-            },
-            FloorType::Spring(_) => Self {
-                r#type: ft,
-                data: data
-                    .iter()
-                    .map(|p| Pixel((p.x, p.y).into(), RgbColor::YELLOW))
-                    .collect::<Vec<_>>(),
-            },
+        let color = match ft {
+            FloorType::Normal => RgbColor::WHITE,
+            FloorType::Fragile(_) => RgbColor::RED,
+            FloorType::Conveyor(_) => RgbColor::GREEN,
+            FloorType::Spring(_) => RgbColor::YELLOW,
+        };
+        Self {
+            r#type: ft,
+            data: data
+                .iter()
+                .map(|p| Pixel((p.x, p.y).into(), color))
+                .collect::<Vec<_>>(),
+            frame: 0,
+            crumbling: false,
+            passed: false,
         }
     }
+
+    /// 动画前进一帧
+    fn tick(&mut self) {
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    /// 人物站上易碎楼梯,开始碎裂
+    fn crumble(&mut self) {
+        if !self.crumbling {
+            self.frame = 0;
+            self.crumbling = true;
+        }
+    }
+
+    /// 是否已经碎裂,闪烁完指定的次数之后碎裂
+    fn broken(&self) -> bool {
+        match self.r#type {
+            FloorType::Fragile(blinks) => self.crumbling && self.frame >= blinks * BLINK_FRAMES,
+            _ => false,
+        }
+    }
+
+    /// 当前帧的像素
+    /// 传送带两端不闪烁,中间按旋转方向依次闪烁;易碎楼梯碎裂前闪烁
+    fn pixels(&self) -> impl Iterator<Item = Pixel<Rgb888>> + '_ {
+        let len = self.data.len();
+        let blink = match self.r#type {
+            FloorType::Conveyor(cd) if len > 2 => {
+                let i = self.frame % (len - 2);
+                match cd {
+                    ConveyorDir::Clockwise => Some(1 + i),
+                    ConveyorDir::Counterclockwise => Some(len - 2 - i),
+                }
+            }
+            _ => None,
+        };
+        let hidden = self.crumbling && self.frame % BLINK_FRAMES == 1;
+        self.data.iter().enumerate().map(move |(i, p)| {
+            if hidden || blink == Some(i) {
+                Pixel(p.0, BinaryColor::Off.into())
+            } else {
+                *p
+            }
+        })
+    }
 }
 
 #[derive(Debug)]
 struct FloorGen {
-    pos: Point,
-    data: VecDeque<Option<Floor>>,
+    /// 距离上一个楼梯间隔的行数
+    gap: usize,
 }
 
 impl FloorGen {
@@ -282,81 +408,45 @@ impl FloorGen {
     }
 
     fn new() -> Self {
-        Self {
-            pos: (0, 0).into(),
-            data: Self::init(),
-        }
+        Self { gap: MIN_GAP }
     }
 
     /// 随机生成楼梯
-    fn random(level: usize) -> Option<Floor> {
-        // 概率生成楼梯
-        let per = unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random_range(1..=10) };
-        if per < 7 {
-            return None;
-        }
-
-        // 楼梯长度
-        let len = unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random_range(3..=5) };
-        let mut data = Vec::<Point>::with_capacity(len);
-        for i in 0..len {
-            data.push(Point::new(i as i32, 0));
-        }
-
-        // TODO: 根据关卡等级生成楼梯
-        let floor = if level <= 5 {
-            Floor::new(FloorType::Normal, &data)
-        } else if level > 5 && level <= 150 {
-            Floor::new(FloorType::Fragile(500), &data)
-        } else {
-            Floor::new(FloorType::Conveyor(ConveyorDir::Clockwise), &data)
-            // Floor::new(FloorType::Conveyor(ConveyorDir::Counterclockwise), &data)
-            // Floor::new(FloorType::Spring(2), &data)
-        };
-        Some(floor)
+    fn random(depth: usize) -> Floor {
+        let ft = FloorType::random(depth);
+
+        // 楼梯长度,最长为 5
+        let len = unsafe {
+            CubeRng(RNG.assume_init_mut().random() as u64).random(ft.min_len() as u32, 6)
+        } as i32;
+        // 楼梯的起始位置
+        let x = unsafe {
+            CubeRng(RNG.assume_init_mut().random() as u64).random(0, (8 - len + 1) as u32)
+        } as i32;
+        let data = (x..x + len).map(|x| Point::new(x, 0)).collect::<Vec<_>>();
+
+        Floor::new(ft, &data)
     }
 
     /// 生成楼梯，y坐标为8
-    fn floor(&mut self, level: usize) -> Option<Floor> {
-        let mut floor = Self::random(level);
-        if let Some(ref mut floor) = floor {
-            floor.data.iter_mut().for_each(|f| f.0.y = 8);
-        }
-        floor
-    }
-
-    fn floors(&mut self, level: usize) -> VecDeque<Option<Floor>> {
-        let mut floors = VecDeque::<Option<Floor>>::new();
-        floors.push_back(None);
-        floors.push_back(None);
-        let mut floor = Self::random(level);
-        if let Some(ref mut floor) = floor {
-            floor.data.iter_mut().for_each(|f| f.0.y = 8);
+    fn floor(&mut self, depth: usize) -> Option<Floor> {
+        // 楼梯之间至少间隔一个人物的高度
+        if self.gap < MIN_GAP {
+            self.gap += 1;
+            return None;
         }
-        floors.push_back(floor);
 
-        let span = unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random_range(2..=6) };
-        for _ in 0..span {
-            floors.push_back(None);
-        }
-        let mut floor = Self::random(level);
-        if let Some(ref mut floor) = floor {
-            floor.data.iter_mut().for_each(|f| f.0.y += span as i32);
+        // 概率生成楼梯
+        let per = unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random_range(1..=10) };
+        if per < 7 {
+            self.gap += 1;
+            return None;
         }
-        floors.push_back(floor);
-
-        floors
 
-        // (0..2)
-        //     .map(|_| {
-        //         let span = unsafe {
-        //             CubeRng(RNG.assume_init_mut().random() as u64).random_range(2..=6) as usize
-        //         };
-        //         let mut floor = Self::random(level);
-        //         floor.data.iter_mut().for_each(|f| f.0.y += span as i32);
-        //         Some(floor)
-        //     })
-        //     .collect::<VecDeque<_>>()
+        self.gap = 0;
+        let mut floor = Self::random(depth);
+        floor.data.iter_mut().for_each(|f| f.0.y = 8);
+        Some(floor)
     }
 }
 
@@ -365,6 +455,9 @@ impl FloorGen {
 struct CubeMan {
     /// 位置
     pos: Point,
+    /// 亚像素位置
+    x: f32,
+    y: f32,
     /// 移动速度
     move_speed: f32,
     /// 下落速度
@@ -377,33 +470,70 @@ impl CubeMan {
     fn new(pos: Point) -> Self {
         Self {
             pos,
-            fall_speed: 1.0,
+            x: pos.x as f32,
+            y: pos.y as f32,
+            fall_speed: FALL_SPEED,
             move_speed: 1.0,
             color: Rgb888::CSS_ORANGE_RED,
         }
     }
 
-    fn next_pos<T: esp_hal::i2c::Instance>(&self, app: &mut App<T>) -> Point {
-        let mut pos = self.pos;
-        match app.ad {
-            Ad::Right => pos.x += 1,
-            Ad::Left => pos.x -= 1,
-            _ => {}
-        };
-        pos
+    fn next_x(&self, ad: Ad) -> f32 {
+        match ad {
+            Ad::Right => self.x + self.move_speed,
+            Ad::Left => self.x - self.move_speed,
+            _ => self.x,
+        }
     }
 
-    fn r#move<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<T>) {
-        self.pos = self.next_pos(app);
+    fn next_pos(&self, ad: Ad) -> Point {
+        Point::new(cell(self.next_x(ad)), self.pos.y)
     }
 
-    /// 下落
-    fn fall(&mut self) {
-        self.pos.y += 1;
+    fn r#move(&mut self, ad: Ad) {
+        self.x = self.next_x(ad);
+        self.sync();
+    }
+
+    /// 被传送带带动
+    fn shift(&mut self, dx: i32) {
+        self.x += dx as f32;
+        self.sync();
+    }
+
+    fn set_y(&mut self, y: f32) {
+        self.y = y;
+        self.sync();
     }
 
     /// 向上
     fn up(&mut self) {
-        self.pos.y -= 1;
+        self.set_y(self.y - 1.0);
+    }
+
+    /// 落到楼梯上,下落速度复位
+    fn land(&mut self) {
+        self.y = self.pos.y as f32;
+        self.fall_speed = FALL_SPEED;
+    }
+
+    /// 下落加速
+    fn accelerate(&mut self) {
+        self.fall_speed = (self.fall_speed + GRAVITY).min(MAX_FALL_SPEED);
+    }
+
+    /// 根据亚像素位置更新所在的格子
+    fn sync(&mut self) {
+        self.pos = Point::new(cell(self.x), cell(self.y));
+    }
+}
+
+/// 亚像素坐标所在的格子,向下取整
+fn cell(v: f32) -> i32 {
+    let i = v as i32;
+    if (i as f32) > v {
+        i - 1
+    } else {
+        i
     }
 }
@@ -1,6 +1,6 @@
 #![doc = include_str!("../../rfcs/002_dice.md")]
 
-use crate::{App, CubeRng, BUZZER, RNG};
+use crate::{sound::SoundEvent, App, CubeRng, BUZZER, RNG};
 use embassy_time::Timer;
 
 /// 骰子
@@ -9,7 +9,7 @@ pub struct Dice;
 
 impl Dice {
     #[rustfmt::skip]
-    fn dice(num: u8) -> [u8; 8] {
+    pub fn dice(num: u8) -> [u8; 8] {
         match num {
             1 => [
                 0b00000000,
@@ -75,12 +75,14 @@ impl Dice {
         }
     }
 
-    fn random() -> [u8; 8] {
-        let num = unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random(1, 7_u32) } as u8;
-        Self::dice(num)
+    /// 掷出的点数
+    fn random() -> u8 {
+        unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random(1, 7_u32) as u8 }
     }
 
-    pub async fn run<T: esp_hal::i2c::Instance>(&self, app: &mut App<'_, T>) {
+    /// 返回每一面掷出的次数
+    pub async fn run<T: esp_hal::i2c::Instance>(&self, app: &mut App<'_, T>) -> [u32; 6] {
+        let mut rolls = [0; 6];
         app.ledc.clear();
         loop {
             let accel = app.accel();
@@ -90,8 +92,10 @@ impl Dice {
                     .map(|_| (app.accel().x(), app.accel().y()))
                     .any(|(x, y)| !(-0.3..=0.3).contains(&x) && !(-0.3..=0.3).contains(&y))
             {
-                app.ledc.write_bytes(Self::random());
-                unsafe { BUZZER.assume_init_mut().dice().await };
+                let num = Self::random();
+                rolls[num as usize - 1] += 1;
+                app.ledc.write_bytes(Self::dice(num));
+                unsafe { BUZZER.assume_init_mut().play(SoundEvent::Dice) };
             }
             Timer::after_millis(800).await;
 
@@ -99,5 +103,6 @@ impl Dice {
                 break;
             }
         }
+        rolls
     }
 }
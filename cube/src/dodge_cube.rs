@@ -1,21 +1,53 @@
 #![doc = include_str!("../../rfcs/008_dodge_cube.md")]
 
-use crate::{ledc::LedControl, player::Player, Ad, App, Point};
-use alloc::collections::LinkedList;
+use crate::{ledc::LedControl, player::Player, sound::SoundEvent, Ad, App, Point, BUZZER, RNG};
+use alloc::vec::Vec;
+use cube_rand::CubeRng;
 use embassy_time::Timer;
-use embedded_graphics::{pixelcolor::Rgb888, Pixel};
+use embedded_graphics::{
+    pixelcolor::{Rgb888, WebColors},
+    Pixel,
+};
+
+/// 障碍物的形状,坐标相对于左上角
+const SHAPES: [&[(i32, i32)]; 6] = [
+    // 单点
+    &[(0, 0)],
+    // 横条
+    &[(0, 0), (1, 0)],
+    // 竖条
+    &[(0, 0), (0, 1)],
+    // 长横条
+    &[(0, 0), (1, 0), (2, 0)],
+    // 方块
+    &[(0, 0), (1, 0), (0, 1), (1, 1)],
+    // L形
+    &[(0, 0), (0, 1), (1, 1)],
+];
+
+/// 障碍物的颜色,红色表示玩家,不作为障碍物的颜色
+const COLORS: [Rgb888; 4] = [
+    Rgb888::CSS_BLUE,
+    Rgb888::CSS_GREEN,
+    Rgb888::CSS_YELLOW,
+    Rgb888::CSS_CYAN,
+];
 
 #[derive(Debug)]
 pub struct DodgeCubeGame {
     width: i32,
     height: i32,
     player: Player,
+    /// 障碍物
+    cubes: Vec<Cube>,
+    /// 帧数
+    ticks: u64,
     /// ms
     waiting_time: u64,
     /// 得分
-    score: u8,
+    score: u16,
     /// 最高分
-    pub highest: u8,
+    pub highest: u16,
     game_over: bool,
 }
 
@@ -34,13 +66,20 @@ impl DodgeCubeGame {
             width,
             height,
             player: Player::new(Point::new(3, 7)),
-            waiting_time: 600,
+            cubes: Vec::new(),
+            ticks: 0,
+            waiting_time: 200,
             score: 0,
             highest: 0,
             game_over: false,
         }
     }
 
+    /// 本局的得分
+    pub fn score(&self) -> u16 {
+        self.score
+    }
+
     pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
         app.ledc.clear();
         app.ad = Ad::default();
@@ -49,7 +88,8 @@ impl DodgeCubeGame {
             Timer::after_millis(self.waiting_time).await;
 
             if self.game_over {
-                app.ledc.draw_score(self.score);
+                unsafe { BUZZER.assume_init_mut().play(SoundEvent::DodgeCubeDie) };
+                app.ledc.draw_score(self.score.into()).await;
                 Timer::after_millis(1500).await;
                 if self.score > self.highest {
                     self.highest = self.score;
@@ -59,16 +99,69 @@ impl DodgeCubeGame {
                 break;
             }
             app.acc_direction();
-            self.r#move(&app.ad);
-            // TODO: 移动音效,得分音效和画面效果,死亡音效
+            self.r#move(app.ad);
+            // TODO: 得分画面效果
             self.draw(&mut app.ledc);
         }
     }
 
-    fn r#move(&mut self, _gd: &Ad) {}
+    fn r#move(&mut self, gd: Ad) {
+        // 玩家上下左右移动躲避
+        let np = self.player.next_pos(gd);
+        if !self.outside(np) && self.player.r#move(gd) {
+            unsafe { BUZZER.assume_init_mut().play(SoundEvent::DodgeCubeMove) };
+        }
+        // 移动之后和下落之后各检查一次,否则迎着障碍物移动时会和它交换位置穿过去
+        if self.hit() {
+            self.game_over = true;
+            return;
+        }
+
+        // 障碍物下落,得分越高下落越快
+        self.ticks += 1;
+        if self.ticks % self.fall_interval() == 0 {
+            self.cubes.iter_mut().for_each(Cube::fall);
+            let height = self.height;
+            let before = self.cubes.len();
+            self.cubes.retain(|c| !c.outside(height));
+            for _ in self.cubes.len()..before {
+                self.calc_score();
+            }
+            self.spawn();
+        }
+
+        if self.hit() {
+            self.game_over = true;
+        }
+    }
+
+    /// 玩家是否碰到障碍物
+    fn hit(&self) -> bool {
+        self.cubes.iter().any(|c| c.hit(self.player.pos))
+    }
+
+    /// 障碍物下落的间隔帧数
+    fn fall_interval(&self) -> u64 {
+        3u64.saturating_sub(self.score as u64 / 10).max(1)
+    }
+
+    /// 在视野上方随机生成障碍物,障碍物之间至少间隔一个像素
+    fn spawn(&mut self) {
+        let per = unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random(0, 10) };
+        if per < 4 {
+            return;
+        }
+        for _ in 0..3 {
+            let cube = Cube::random(self.width);
+            if !self.cubes.iter().any(|c| c.near(&cube)) {
+                self.cubes.push(cube);
+                return;
+            }
+        }
+    }
 
     fn calc_score(&mut self) {
-        self.score += 1;
+        self.score = self.score.saturating_add(1);
     }
 
     fn outside(&self, pos: Point) -> bool {
@@ -77,14 +170,67 @@ impl DodgeCubeGame {
 
     pub fn draw(&mut self, ledc: &mut LedControl<'_>) {
         ledc.clear();
-        // let mut pixels = self.snake.body.clone();
-        // ledc.write_pixels(pixels);
+        let mut pixels = self
+            .cubes
+            .iter()
+            .flat_map(|c| c.body.iter().copied())
+            .collect::<Vec<_>>();
+        pixels.push(self.player.into());
+        ledc.write_pixels(pixels);
     }
 }
 
+/// 障碍物
 #[derive(Debug)]
 struct Cube {
-    body: LinkedList<Pixel<Rgb888>>,
+    body: Vec<Pixel<Rgb888>>,
 }
 
-impl Cube {}
+impl Cube {
+    /// 随机生成一个障碍物,位于视野上方
+    fn random(width: i32) -> Self {
+        let shape = unsafe {
+            SHAPES[CubeRng(RNG.assume_init_mut().random() as u64).random(0, SHAPES.len() as u32)
+                as usize]
+        };
+        let color = unsafe {
+            COLORS[CubeRng(RNG.assume_init_mut().random() as u64).random(0, COLORS.len() as u32)
+                as usize]
+        };
+        let w = shape.iter().map(|p| p.0).max().unwrap_or(0) + 1;
+        let h = shape.iter().map(|p| p.1).max().unwrap_or(0) + 1;
+        let x = unsafe {
+            CubeRng(RNG.assume_init_mut().random() as u64).random(0, (width - w + 1) as u32)
+        } as i32;
+        let body = shape
+            .iter()
+            .map(|(dx, dy)| Pixel((x + dx, dy - h).into(), color))
+            .collect::<Vec<_>>();
+        Self { body }
+    }
+
+    /// 下落一格
+    fn fall(&mut self) {
+        self.body.iter_mut().for_each(|p| p.0.y += 1);
+    }
+
+    /// 是否撞到该位置
+    fn hit(&self, pos: Point) -> bool {
+        self.body.iter().any(|p| p.0.x == pos.x && p.0.y == pos.y)
+    }
+
+    /// 与另一个障碍物之间是否没有间隔
+    fn near(&self, other: &Cube) -> bool {
+        self.body.iter().any(|p| {
+            other
+                .body
+                .iter()
+                .any(|o| (p.0.x - o.0.x).abs() <= 1 && (p.0.y - o.0.y).abs() <= 1)
+        })
+    }
+
+    /// 是否完全掉出视野
+    fn outside(&self, height: i32) -> bool {
+        self.body.iter().all(|p| p.0.y >= height)
+    }
+}
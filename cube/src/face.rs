@@ -1,4 +1,4 @@
-use crate::{buzzer::Buzzer, ledc::LedControl, BUZZER, RNG};
+use crate::{buzzer::Buzzer, ledc::LedControl, sound::SoundEvent, BUZZER, RNG};
 use alloc::vec::Vec;
 use cube_rand::CubeRng;
 use embassy_time::Timer;
@@ -171,7 +171,7 @@ impl Face {
         x: u8,
         y: u8,
         ledc: &mut LedControl<'d>,
-        buzzer: &mut Buzzer<'d>,
+        buzzer: &mut Buzzer,
     ) {
         self.clear();
 
@@ -190,11 +190,7 @@ impl Face {
     }
 
     /// 休眠动画
-    pub async fn dormancy_animate<'d>(
-        &mut self,
-        ledc: &mut LedControl<'d>,
-        buzzer: &mut Buzzer<'d>,
-    ) {
+    pub async fn dormancy_animate<'d>(&mut self, ledc: &mut LedControl<'d>, buzzer: &mut Buzzer) {
         self.clear();
 
         let ex: u8 = 1;
@@ -271,12 +267,12 @@ impl Face {
     }
 
     /// 唤醒动画
-    pub async fn wakeup_animate<'d>(&mut self, ledc: &mut LedControl<'d>, buzzer: &mut Buzzer<'d>) {
+    pub async fn wakeup_animate<'d>(&mut self, ledc: &mut LedControl<'d>, buzzer: &mut Buzzer) {
         let ex: u8 = 1;
         let ey: u8 = 4;
 
+        buzzer.play(SoundEvent::Wakeup);
         for _ in 0..2 {
-            buzzer.tone(8000, 50).await;
             self.clear();
             self.close_eyes();
             self.slack_mouth();
@@ -295,7 +291,7 @@ impl Face {
     pub async fn break_record_animate<'d>(
         &mut self,
         ledc: &mut LedControl<'d>,
-        // buzzer: &mut Buzzer<'d>,
+        // buzzer: &mut Buzzer,
     ) {
         let ex = 1;
         let ey = 4;
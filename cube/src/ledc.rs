@@ -1,4 +1,5 @@
 use crate::mapping;
+use embassy_time::Timer;
 use embedded_graphics::{pixelcolor::*, prelude::*};
 use esp_hal::{
     peripherals::SPI2,
@@ -14,6 +15,10 @@ use ws2812_spi::Ws2812;
 
 /// led 数量
 const NUM_LEDS: usize = 64;
+/// 数字滚动显示时每一帧的间隔,单位毫秒
+const SCROLL_INTERVAL: u64 = 150;
+/// 数字滚动显示前的停留时间,单位毫秒
+const SCROLL_HOLD: u64 = 500;
 
 pub struct LedControl<'d> {
     matrix: SmartLedMatrix<Ws2812<Spi<'d, SPI2, FullDuplexMode>>, Rectangular<NoInvert>, NUM_LEDS>,
@@ -83,21 +88,93 @@ impl<'d> LedControl<'d> {
     }
 
     /// 绘制分数
-    pub fn draw_score(&mut self, score: u8) {
-        self.clear();
+    pub async fn draw_score(&mut self, score: u32) {
+        self.draw_number(score, &[]).await;
+    }
 
-        let dn = score / 10;
-        let sn = score % 10;
-        let dn = mapping::num_map(dn);
-        let mut sn = mapping::num_map(sn);
+    /// 绘制数字
+    /// 两位以内的数字静态显示,超出屏幕宽度时水平滚动显示
+    /// colors: 从高位到低位每一位数字的颜色,循环使用,为空时使用白色
+    pub async fn draw_number(&mut self, num: u32, colors: &[Rgb888]) {
+        // 从高位到低位拆分数字,不足两位时高位补零
+        let mut digits = Vec::<u8, 10>::new();
+        let mut n = num;
+        loop {
+            digits.push((n % 10) as u8).ok();
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        if digits.len() < 2 {
+            digits.push(0).ok();
+        }
+        digits.reverse();
 
-        let mut buf_work = [0; 8];
-        (0..8).for_each(|i| buf_work[i] = dn[i]);
+        let glyphs = digits.iter().map(|d| mapping::num_map(*d));
+        self.draw_glyphs(glyphs, colors).await;
+    }
 
-        (0..8).for_each(|i| sn[i] >>= 4);
-        (0..8).for_each(|i| buf_work[i] |= sn[i]);
-        (0..8).for_each(|i| buf_work[i] >>= 1);
+    /// 绘制文字,支持大写字母、数字和短横线
+    /// 两个字符以内静态显示,超出屏幕宽度时水平滚动显示
+    /// colors: 每一个字符的颜色,循环使用,为空时使用白色
+    pub async fn draw_text(&mut self, text: &[u8], colors: &[Rgb888]) {
+        let glyphs = text.iter().map(|c| mapping::char_map(*c));
+        self.draw_glyphs(glyphs, colors).await;
+    }
+
+    /// 依次绘制 3x5 的字模,超出屏幕宽度时水平滚动显示
+    async fn draw_glyphs<I>(&mut self, glyphs: I, colors: &[Rgb888])
+    where
+        I: IntoIterator<Item = [u8; 8]>,
+    {
+        // 每个字符前空一列,字符占三列,每一列用一个字节表示点亮的行
+        let mut columns = Vec::<(u8, Rgb888), 64>::new();
+        for (i, glyph) in glyphs.into_iter().enumerate() {
+            let color = if colors.is_empty() {
+                Rgb888::WHITE
+            } else {
+                colors[i % colors.len()]
+            };
+            columns.push((0, color)).ok();
+            for x in 0..3 {
+                let column = glyph
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, row)| **row & (0x80 >> x) > 0)
+                    .fold(0u8, |c, (y, _)| c | (1 << y));
+                columns.push((column, color)).ok();
+            }
+        }
 
-        self.write_bytes(buf_work);
+        let overflow = columns.len().saturating_sub(8);
+        for offset in 0..=overflow {
+            self.draw_columns(&columns[offset..offset + 8]);
+            if overflow == 0 {
+                break;
+            }
+            Timer::after_millis(if offset == 0 {
+                SCROLL_HOLD
+            } else {
+                SCROLL_INTERVAL
+            })
+            .await;
+        }
+    }
+
+    /// 按列绘制一屏
+    fn draw_columns(&mut self, columns: &[(u8, Rgb888)]) {
+        let mut pixels = Vec::<Pixel<Rgb888>, NUM_LEDS>::new();
+        for (x, (column, color)) in columns.iter().enumerate() {
+            for y in 0..8 {
+                let color = if column & (1 << y) > 0 {
+                    *color
+                } else {
+                    Rgb888::BLACK
+                };
+                pixels.push(Pixel((x as i32, y).into(), color)).ok();
+            }
+        }
+        self.write_pixels(pixels);
     }
 }
@@ -4,17 +4,21 @@
 use crate::{dodge_cube::DodgeCubeGame, sokoban::Sokoban};
 use alloc::vec::Vec;
 use bagua::BaGua;
+use board::BoardGame;
 use buzzer::Buzzer;
 use core::mem::MaybeUninit;
+use cube_dsp::Calibration;
 use cube_man::CubeManGame;
+use cube_net::game;
 use cube_rand::CubeRng;
+use cube_store::{scores::Table, Profile, Scores, Stats, Store};
 use dice::Dice;
 use embassy_executor::Spawner;
-use embassy_time::Timer;
+use embassy_time::{Instant, Timer};
 use embedded_graphics_core::pixelcolor::Rgb888;
-use embedded_storage::{ReadStorage, Storage};
 use esp_hal::{rng::Rng, Blocking};
 use esp_storage::FlashStorage;
+use esp_wifi::esp_now::EspNow;
 use face::Face;
 use ledc::LedControl;
 use log::info;
@@ -23,7 +27,14 @@ use mpu6050_dmp::{
     accel::{AccelF32, AccelFullScale},
     sensor::Mpu6050,
 };
+use play_ball::PlayBall;
+use sandbox::SandBox;
+use scores::ScoreBoard;
+use settings::Settings;
 use snake::SnakeGame;
+use sound::{SoundEvent, Theme, Volume};
+use spectrum::MusicSpectrum;
+use stats::StatsView;
 use timers::Timers;
 use ui::Ui;
 
@@ -32,18 +43,28 @@ extern crate alloc;
 
 pub mod bagua;
 pub mod battery;
+pub mod board;
 pub mod buzzer;
 pub mod cube_man;
 pub mod dice;
 pub mod dodge_cube;
 pub mod face;
 pub mod ledc;
+pub mod lobby;
 pub mod map;
 pub mod mapping;
 pub mod maze;
+pub mod mic;
+pub mod play_ball;
 pub mod player;
+pub mod sandbox;
+pub mod scores;
+pub mod settings;
 pub mod snake;
 pub mod sokoban;
+pub mod sound;
+pub mod spectrum;
+pub mod stats;
 pub mod timers;
 pub mod ui;
 pub mod wifi_ap;
@@ -53,6 +74,16 @@ pub static mut RNG: MaybeUninit<Rng> = MaybeUninit::uninit();
 pub static mut BUZZER: MaybeUninit<Buzzer> = MaybeUninit::uninit();
 pub static mut LEDCTL: MaybeUninit<LedControl> = MaybeUninit::uninit();
 
+/// 以前的固件在flash中保存设置和记录的地址,只用来迁移
+const LEGACY_PROFILE_ADDR: u32 = 0x9100;
+/// flash中设置和记录的日志的地址,每次写入轮流使用后面的扇区,减少擦除的次数
+const PROFILE_LOG_ADDR: u32 = 0xa000;
+/// 日志使用的扇区数
+const PROFILE_LOG_SECTORS: u32 = 4;
+
+/// 摇一摇的阈值,加速度大小超过该值,单位g
+const SHAKE_THRESHOLD: f32 = 1.6;
+
 /// 物体移动方向
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Direction {
@@ -101,6 +132,33 @@ impl core::fmt::Display for Ad {
     }
 }
 
+/// 帧同步时双方交换的输入
+impl cube_net::lockstep::Input for Ad {
+    fn to_u8(self) -> u8 {
+        match self {
+            Ad::None => 0,
+            Ad::Front => 1,
+            Ad::Right => 2,
+            Ad::Back => 3,
+            Ad::Left => 4,
+            Ad::Up => 5,
+            Ad::Down => 6,
+        }
+    }
+
+    fn from_u8(v: u8) -> Self {
+        match v {
+            1 => Ad::Front,
+            2 => Ad::Right,
+            3 => Ad::Back,
+            4 => Ad::Left,
+            5 => Ad::Up,
+            6 => Ad::Down,
+            _ => Ad::None,
+        }
+    }
+}
+
 impl From<Direction> for Ad {
     fn from(d: Direction) -> Self {
         match d {
@@ -152,6 +210,8 @@ where
 
     mpu6050: Mpu6050<esp_hal::i2c::I2c<'d, T, Blocking>>,
     ledc: LedControl<'d>,
+    /// 联机游戏使用
+    esp_now: EspNow<'d>,
     spawner: Spawner,
 }
 
@@ -198,6 +258,13 @@ where
         }
     }
 
+    /// 摇一摇
+    pub fn shake(&mut self) -> bool {
+        let accel = self.accel();
+        let (ax, ay, az) = (accel.x(), accel.y(), accel.z());
+        ax * ax + ay * ay + az * az > SHAKE_THRESHOLD * SHAKE_THRESHOLD
+    }
+
     /// 退出
     pub fn quit(&self) -> bool {
         Ad::Down.eq(&self.ad)
@@ -206,6 +273,7 @@ where
     pub fn new(
         mpu6050: Mpu6050<esp_hal::i2c::I2c<'d, T, Blocking>>,
         mut ledc: LedControl<'d>,
+        esp_now: EspNow<'d>,
         spawner: Spawner,
     ) -> Self {
         ledc.set_brightness(0x01);
@@ -218,20 +286,63 @@ where
 
             mpu6050,
             ledc,
+            esp_now,
             spawner,
         }
     }
 
     pub async fn run(mut self) -> ! {
-        let flash_addr = 0x9100;
-        let mut flash = FlashStorage::new();
-        let mut flash_data = [0u8; 8];
-        flash.read(flash_addr, &mut flash_data).ok();
-        info!(
-            "Read flash data from {:x}:  {:02x?}",
-            flash_addr,
-            &flash_data[..8]
-        );
+        let mut store = Store::new(
+            FlashStorage::new(),
+            PROFILE_LOG_ADDR,
+            PROFILE_LOG_SECTORS,
+            LEGACY_PROFILE_ADDR,
+        )
+        .unwrap();
+        // 数据损坏时使用默认值,下次保存时覆盖
+        let mut profile = store.load::<Profile>().unwrap_or_else(|e| {
+            info!("Load profile failed: {:?}", e);
+            Profile::default()
+        });
+        info!("Load profile: {:?}", profile);
+        let mut stats = store.load::<Stats>().unwrap_or_else(|e| {
+            info!("Load stats failed: {:?}", e);
+            Stats::default()
+        });
+        let mut scores = store.load::<Scores>().unwrap_or_else(|e| {
+            info!("Load scores failed: {:?}", e);
+            Scores::default()
+        });
+        // 以前的固件只记录了最高分,迁移到空的排行榜
+        for (game, highest) in [
+            (game::SNAKE, profile.snake_highest),
+            (game::CUBE_MAN, profile.cube_man_highest),
+            (game::DODGE_CUBE, profile.dodge_cube_highest),
+        ] {
+            // 没有玩过的游戏不占用排行榜
+            if highest == 0 {
+                continue;
+            }
+            if let Some(table) = scores.table_mut(game, scores::MODE) {
+                if table.entries().is_empty() {
+                    table.insert(scores::NO_INITIALS, highest);
+                }
+            }
+        }
+        self.ledc.set_brightness(profile.brightness);
+        {
+            let buzzer = unsafe { BUZZER.assume_init_mut() };
+            buzzer.open = profile.sound;
+            // 没有设置过时使用默认的主题和音量
+            if let Some(theme) = Theme::from_u8(profile.theme) {
+                buzzer.theme = theme;
+            }
+            if let Some(volume) = Volume::from_u8(profile.volume) {
+                buzzer.volume = volume;
+            }
+            // 读取设置之后再播放,关闭声音时开机也不响
+            buzzer.play(SoundEvent::PowerOn);
+        }
 
         loop {
             Timer::after_millis(500).await;
@@ -247,23 +358,50 @@ where
             match self.ad {
                 // 向上进入对应的界面
                 Ad::Front => {
-                    unsafe { BUZZER.assume_init_mut().menu_confirm().await };
-                    match self.uis[self.ui_current_idx as usize] {
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuConfirm) };
+                    let ui = self.uis[self.ui_current_idx as usize];
+                    let start = Instant::now();
+                    stats.played[ui.id()] = stats.played[ui.id()].saturating_add(1);
+                    match ui {
                         Ui::Timer => Timers::default().run(&mut self).await,
                         Ui::MusicSpectrum => {
-                            // 麦克风采集信息，通过fft转换
+                            let mut ms = MusicSpectrum::default();
+                            // 噪声校准从flash中获取
+                            let saved = Calibration::from_bytes(&profile.spectrum_calibration);
+                            ms.calibration = saved;
+                            ms.run(&mut self).await;
+                            // 重新校准之后写入flash
+                            if let Some(calibration) = ms.calibration.filter(|c| Some(*c) != saved)
+                            {
+                                profile.spectrum_calibration = calibration.to_bytes();
+                                store.save(&profile).ok();
+                            }
+                        }
+                        Ui::Dice => {
+                            let rolls = Dice.run(&mut self).await;
+                            for (n, r) in stats.dice_rolls.iter_mut().zip(rolls) {
+                                *n = n.saturating_add(r);
+                            }
                         }
-                        Ui::Dice => Dice.run(&mut self).await,
                         Ui::Snake => {
                             let mut snake = SnakeGame::new();
-                            // 最高分从flash中获取
-                            snake.highest = flash_data[0x00];
+                            // 最高分从排行榜中获取
+                            snake.highest = scores
+                                .table(game::SNAKE, scores::MODE)
+                                .map_or(0, Table::highest);
                             snake.run(&mut self).await;
-                            // 游戏结束将最高分再次写入flash
-                            flash_data[0x00] = snake.highest;
-                            flash.write(flash_addr, &flash_data).ok();
+                            // 上榜时输入缩写,写入flash
+                            if let Some(table) = scores.table_mut(game::SNAKE, scores::MODE) {
+                                if scores::record(&mut self, table, snake.score()).await {
+                                    store.save(&scores).ok();
+                                }
+                            }
+                            stats.longest_snake = stats.longest_snake.max(snake.snake_len() as u16);
+                        }
+                        Ui::BaGua => {
+                            let casts = BaGua::run(&mut self).await;
+                            stats.hexagrams = stats.hexagrams.saturating_add(casts);
                         }
-                        Ui::BaGua => BaGua::run(&mut self).await,
                         Ui::Maze => {
                             let mut cr = unsafe {
                                 CubeRng(RNG.assume_init_mut().random() as u64).random_range(19..=33)
@@ -271,21 +409,82 @@ where
                             if cr % 2 == 0 {
                                 cr += 1;
                             }
-                            Maze::new(cr, cr).run(&mut self).await;
+                            let mut maze = Maze::new(cr, cr);
+                            maze.run(&mut self).await;
+                            if maze.solved() {
+                                stats.mazes_solved = stats.mazes_solved.saturating_add(1);
+                            }
                         }
                         Ui::CubeMan => {
                             let mut cm = CubeManGame::new();
-                            // 最高分从flash中获取
-                            cm.highest = flash_data[0x01];
+                            // 最高分从排行榜中获取
+                            cm.highest = scores
+                                .table(game::CUBE_MAN, scores::MODE)
+                                .map_or(0, Table::highest);
                             cm.run(&mut self).await;
-                            // 游戏结束将最高分再次写入flash
-                            flash_data[0x01] = cm.highest;
-                            flash.write(flash_addr, &flash_data).ok();
+                            // 上榜时输入缩写,写入flash
+                            if let Some(table) = scores.table_mut(game::CUBE_MAN, scores::MODE) {
+                                if scores::record(&mut self, table, cm.score()).await {
+                                    store.save(&scores).ok();
+                                }
+                            }
+                            stats.deepest_cube_man = stats
+                                .deepest_cube_man
+                                .max(cm.depth().min(u16::MAX as usize) as u16);
+                        }
+                        Ui::Sokoban => {
+                            let mut sokoban = Sokoban::new();
+                            sokoban.run(&mut self).await;
+                            // 通关之后解锁下一关
+                            if sokoban.cleared() && profile.sokoban_levels < 1 {
+                                profile.sokoban_levels = 1;
+                                store.save(&profile).ok();
+                            }
+                        }
+                        Ui::DodgeCube => {
+                            let mut dc = DodgeCubeGame::new();
+                            // 最高分从排行榜中获取
+                            dc.highest = scores
+                                .table(game::DODGE_CUBE, scores::MODE)
+                                .map_or(0, Table::highest);
+                            dc.run(&mut self).await;
+                            // 上榜时输入缩写,写入flash
+                            if let Some(table) = scores.table_mut(game::DODGE_CUBE, scores::MODE) {
+                                if scores::record(&mut self, table, dc.score()).await {
+                                    store.save(&scores).ok();
+                                }
+                            }
+                        }
+                        Ui::SandBox => SandBox::default().run(&mut self).await,
+                        Ui::PlayBall => PlayBall::new().run(&mut self).await,
+                        Ui::BoardGame => BoardGame::default().run(&mut self).await,
+                        Ui::Settings => {
+                            let buzzer = unsafe { BUZZER.assume_init_mut() };
+                            let mut settings = Settings {
+                                theme: buzzer.theme,
+                                volume: buzzer.volume,
+                            };
+                            if settings.run(&mut self).await {
+                                profile.theme = settings.theme.to_u8();
+                                profile.volume = settings.volume.to_u8();
+                                store.save(&profile).ok();
+                            }
+                        }
+                        Ui::Stats => StatsView::new(stats).run(&mut self).await,
+                        Ui::Sound => {
+                            let buzzer = unsafe { BUZZER.assume_init_mut() };
+                            buzzer.change();
+                            profile.sound = buzzer.open;
+                            store.save(&profile).ok();
                         }
-                        Ui::Sokoban => Sokoban::new().run(&mut self).await,
-                        Ui::DodgeCube => DodgeCubeGame::new().run(&mut self).await,
-                        Ui::Sound => unsafe { BUZZER.assume_init_mut().change() },
                     }
+
+                    // 更新统计,解锁新的成就
+                    let seconds = start.elapsed().as_secs() as u32;
+                    stats.play_seconds = stats.play_seconds.saturating_add(seconds);
+                    let unlocked = stats::unlock(&mut stats);
+                    store.save(&stats).ok();
+                    stats::celebrate(&mut self.ledc, &unlocked).await;
                 }
                 Ad::Right => {
                     self.ui_current_idx += 1;
@@ -294,7 +493,7 @@ where
                     }
                     self.ledc
                         .write_bytes(self.uis[self.ui_current_idx as usize].ui());
-                    unsafe { BUZZER.assume_init_mut().menu_select().await };
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuSelect) };
                 }
                 Ad::Left => {
                     self.ui_current_idx -= 1;
@@ -303,7 +502,20 @@ where
                     }
                     self.ledc
                         .write_bytes(self.uis[self.ui_current_idx as usize].ui());
-                    unsafe { BUZZER.assume_init_mut().menu_select().await };
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuSelect) };
+                }
+                // 向后倾斜查看游戏的排行榜
+                Ad::Back => {
+                    let ui = self.uis[self.ui_current_idx as usize];
+                    if let Some(game) = scores::game_of(ui) {
+                        unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuConfirm) };
+                        let table = scores
+                            .table(game, scores::MODE)
+                            .copied()
+                            .unwrap_or(Table::new(game, scores::MODE));
+                        ScoreBoard::new(table).run(&mut self).await;
+                    }
+                    self.ledc.write_bytes(ui.ui());
                 }
                 _ => {
                     self.ledc
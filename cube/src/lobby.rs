@@ -0,0 +1,95 @@
+//! 联机游戏的配对大厅
+//!
+//! 配对的流程在 `cube_net::lobby` 中实现,这里通过 ESP-NOW 收发数据,并在点阵上显示寻找对手的动画
+
+use crate::{sound::SoundEvent, App, BUZZER, RNG};
+use cube_net::lobby::{Address, Lobby, Peer, Status, Transport};
+use embassy_time::{Instant, Timer};
+use embedded_graphics::{
+    pixelcolor::{Rgb888, WebColors},
+    Pixel,
+};
+use esp_wifi::esp_now::{EspNow, PeerInfo};
+
+/// 寻找对手的动画,一个点沿着中间的方框转圈
+const SEARCHING: [(i32, i32); 12] = [
+    (2, 2),
+    (3, 2),
+    (4, 2),
+    (5, 2),
+    (5, 3),
+    (5, 4),
+    (5, 5),
+    (4, 5),
+    (3, 5),
+    (2, 5),
+    (2, 4),
+    (2, 3),
+];
+
+/// 通过 ESP-NOW 收发数据
+pub struct EspNowTransport<'a, 'd>(pub &'a mut EspNow<'d>);
+
+impl Transport for EspNowTransport<'_, '_> {
+    fn send(&mut self, to: &Address, data: &[u8]) -> bool {
+        match self.0.send(to, data) {
+            Ok(waiter) => waiter.wait().is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Option<(Address, usize)> {
+        let r = self.0.receive()?;
+        let len = (r.len as usize).min(buf.len());
+        buf[..len].copy_from_slice(&r.data[..len]);
+        Some((r.info.src_address, len))
+    }
+
+    fn add_peer(&mut self, peer: &Address) -> bool {
+        self.0.peer_exists(peer)
+            || self
+                .0
+                .add_peer(PeerInfo {
+                    peer_address: *peer,
+                    lmk: None,
+                    channel: None,
+                    encrypt: false,
+                })
+                .is_ok()
+    }
+
+    fn remove_peer(&mut self, peer: &Address) {
+        self.0.remove_peer(peer).ok();
+    }
+}
+
+/// 寻找同一款游戏的对手,平放退出或者超时返回 None
+pub async fn find_peer<T: esp_hal::i2c::Instance>(app: &mut App<'_, T>, game: u8) -> Option<Peer> {
+    let nonce = unsafe { RNG.assume_init_mut().random() };
+    let mut lobby = Lobby::new(game, nonce);
+    let mut frame = 0;
+
+    loop {
+        frame = (frame + 1) % SEARCHING.len();
+        app.ledc.clear();
+        app.ledc
+            .write_pixel(Pixel(SEARCHING[frame].into(), Rgb888::CSS_BLUE));
+
+        app.acc_direction();
+        if app.quit() {
+            return None;
+        }
+
+        let now = Instant::now().as_millis();
+        match lobby.poll(now, &mut EspNowTransport(&mut app.esp_now)) {
+            Status::Searching => {}
+            Status::Paired(peer) => {
+                unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuConfirm) };
+                return Some(peer);
+            }
+            Status::TimedOut => return None,
+        }
+
+        Timer::after_millis(100).await;
+    }
+}
@@ -128,6 +128,89 @@ pub const NUM_9: [u8; 8] = [
     0b00000000,
 ];
 
+/// 3x5 的大写字母,每个字节的高三位为一行
+#[rustfmt::skip]
+pub const LETTERS: [[u8; 5]; 26] = [
+    // A
+    [0b01000000, 0b10100000, 0b11100000, 0b10100000, 0b10100000],
+    // B
+    [0b11000000, 0b10100000, 0b11000000, 0b10100000, 0b11000000],
+    // C
+    [0b01100000, 0b10000000, 0b10000000, 0b10000000, 0b01100000],
+    // D
+    [0b11000000, 0b10100000, 0b10100000, 0b10100000, 0b11000000],
+    // E
+    [0b11100000, 0b10000000, 0b11000000, 0b10000000, 0b11100000],
+    // F
+    [0b11100000, 0b10000000, 0b11000000, 0b10000000, 0b10000000],
+    // G
+    [0b01100000, 0b10000000, 0b10100000, 0b10100000, 0b01100000],
+    // H
+    [0b10100000, 0b10100000, 0b11100000, 0b10100000, 0b10100000],
+    // I
+    [0b11100000, 0b01000000, 0b01000000, 0b01000000, 0b11100000],
+    // J
+    [0b00100000, 0b00100000, 0b00100000, 0b10100000, 0b01000000],
+    // K
+    [0b10100000, 0b10100000, 0b11000000, 0b10100000, 0b10100000],
+    // L
+    [0b10000000, 0b10000000, 0b10000000, 0b10000000, 0b11100000],
+    // M
+    [0b10100000, 0b11100000, 0b11100000, 0b10100000, 0b10100000],
+    // N
+    [0b11000000, 0b10100000, 0b10100000, 0b10100000, 0b10100000],
+    // O
+    [0b01000000, 0b10100000, 0b10100000, 0b10100000, 0b01000000],
+    // P
+    [0b11000000, 0b10100000, 0b11000000, 0b10000000, 0b10000000],
+    // Q
+    [0b01000000, 0b10100000, 0b10100000, 0b11000000, 0b01100000],
+    // R
+    [0b11000000, 0b10100000, 0b11000000, 0b10100000, 0b10100000],
+    // S
+    [0b01100000, 0b10000000, 0b01000000, 0b00100000, 0b11000000],
+    // T
+    [0b11100000, 0b01000000, 0b01000000, 0b01000000, 0b01000000],
+    // U
+    [0b10100000, 0b10100000, 0b10100000, 0b10100000, 0b11100000],
+    // V
+    [0b10100000, 0b10100000, 0b10100000, 0b10100000, 0b01000000],
+    // W
+    [0b10100000, 0b10100000, 0b11100000, 0b11100000, 0b10100000],
+    // X
+    [0b10100000, 0b10100000, 0b01000000, 0b10100000, 0b10100000],
+    // Y
+    [0b10100000, 0b10100000, 0b01000000, 0b01000000, 0b01000000],
+    // Z
+    [0b11100000, 0b00100000, 0b01000000, 0b10000000, 0b11100000],
+];
+
+/// 短横线
+#[rustfmt::skip]
+pub const DASH: [u8; 8] = [
+    0b00000000,
+    0b00000000,
+    0b11100000,
+    0b00000000,
+    0b00000000,
+    0b00000000,
+    0b00000000,
+    0b00000000,
+];
+
+/// 字符的字模,支持大写字母、数字和短横线,其他字符为空白
+pub const fn char_map(c: u8) -> [u8; 8] {
+    match c {
+        b'A'..=b'Z' => {
+            let l = LETTERS[(c - b'A') as usize];
+            [l[0], l[1], l[2], l[3], l[4], 0, 0, 0]
+        }
+        b'0'..=b'9' => num_map(c - b'0'),
+        b'-' => DASH,
+        _ => [0; 8],
+    }
+}
+
 /// 全屏点亮图
 #[rustfmt::skip]
 pub const ALL_ON: [u8; 8] = [
@@ -3,6 +3,7 @@
 use crate::{
     map::{Map, Vision},
     player::Player,
+    sound::SoundEvent,
     Ad, App, CubeRng, Point, BUZZER, RNG,
 };
 use alloc::vec::Vec;
@@ -62,6 +63,11 @@ impl Maze {
         maze
     }
 
+    /// 是否走到了出口
+    pub fn solved(&self) -> bool {
+        self.game_over
+    }
+
     pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
         app.ledc.clear();
         app.ad = Ad::default();
@@ -71,7 +77,7 @@ impl Maze {
 
             if self.game_over {
                 // TODO: 结束动画
-                unsafe { BUZZER.assume_init_mut().maze_over().await };
+                unsafe { BUZZER.assume_init_mut().play(SoundEvent::MazeOver) };
                 Timer::after_millis(3000).await;
                 break;
             }
@@ -80,7 +86,7 @@ impl Maze {
             if !self.hit_wall(app) {
                 let moved = self.player.r#move(app.ad);
                 if moved {
-                    unsafe { BUZZER.assume_init_mut().maze_move().await };
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MazeMove) };
                     // 玩家移动之后视野数据改变
                     self.vision.update(app.ad, &self.map.map);
                     // 游戏结束
@@ -0,0 +1,54 @@
+//! 麦克风
+//!
+//! 采集任务按采样率读取 ADC,每凑满一帧就通知界面,只有打开频谱界面时才采集
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use cube_dsp::{SAMPLES, SAMPLE_RATE};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Ticker, Timer};
+use esp_hal::{
+    analog::adc::{Adc, AdcPin},
+    gpio::GpioPin,
+    peripherals::ADC1,
+};
+
+/// 是否采集
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// 最新采集到的一帧
+pub static FRAME: Signal<CriticalSectionRawMutex, [u16; SAMPLES]> = Signal::new();
+
+/// 开始采集
+pub fn enable() {
+    FRAME.reset();
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// 停止采集
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// 麦克风采集任务
+#[embassy_executor::task]
+pub async fn mic_task(mut adc: Adc<'static, ADC1>, mut pin: AdcPin<GpioPin<0>, ADC1>) {
+    let mut ticker = Ticker::every(Duration::from_hz(SAMPLE_RATE as u64));
+    loop {
+        if !ENABLED.load(Ordering::Relaxed) {
+            Timer::after_millis(100).await;
+            continue;
+        }
+
+        let mut frame = [0u16; SAMPLES];
+        ticker.reset();
+        for sample in frame.iter_mut() {
+            ticker.next().await;
+            *sample = loop {
+                if let Ok(v) = adc.read_oneshot(&mut pin) {
+                    break v;
+                }
+            };
+        }
+        FRAME.signal(frame);
+    }
+}
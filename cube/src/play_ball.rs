@@ -0,0 +1,422 @@
+#![doc = include_str!("../../rfcs/010_play_ball.md")]
+
+use crate::{
+    lobby::{self, EspNowTransport},
+    sound::SoundEvent,
+    Ad, App, CubeRng, BUZZER, RNG,
+};
+use alloc::vec::Vec;
+use cube_net::{
+    game, is_newer,
+    lobby::{Transport, BROADCAST_ADDRESS},
+    Message, Packet, MAX_FRAME_LEN,
+};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_graphics::{
+    pixelcolor::{Rgb888, WebColors},
+    Pixel,
+};
+
+/// 赢得比赛需要的分数
+const WIN_SCORE: u8 = 5;
+/// 球板的宽度
+const PADDLE_WIDTH: i32 = 3;
+/// 球每走一格的时间,单位毫秒
+const BALL_TIME: u64 = 200;
+/// 发送心跳的间隔,单位毫秒
+const PING_TIME: u64 = 500;
+/// 超过该时间没有收到对方的数据视为断开,单位毫秒
+const TIMEOUT: u64 = 3000;
+
+/// 游戏中的事件,作为 GameState 消息的负载
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Event {
+    /// 球进入对方的半场,坐标已经转换成对方的视角
+    Ball { x: i8, dx: i8 },
+    /// 没有接住球,对方得分
+    Miss,
+}
+
+impl Event {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Event::Ball { x, dx } => vec![0, *x as u8, *dx as u8],
+            Event::Miss => vec![1],
+        }
+    }
+
+    /// 解码一个事件,同时返回剩下的数据;坐标和方向超出范围时返回 None,避免错误的数据越界
+    fn decode(data: &[u8]) -> Option<(Self, &[u8])> {
+        match data {
+            [0, x, dx, rest @ ..]
+                if (0..8).contains(&(*x as i8)) && (-1..=1).contains(&(*dx as i8)) =>
+            {
+                Some((
+                    Event::Ball {
+                        x: *x as i8,
+                        dx: *dx as i8,
+                    },
+                    rest,
+                ))
+            }
+            [1, rest @ ..] => Some((Event::Miss, rest)),
+            _ => None,
+        }
+    }
+}
+
+/// 比赛结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Win,
+    Lose,
+    /// 对方断开或退出
+    Disconnected,
+    /// 自己退出
+    Quit,
+}
+
+/// 球
+#[derive(Debug, Clone, Copy)]
+struct Ball {
+    x: i32,
+    y: i32,
+    dx: i32,
+    dy: i32,
+}
+
+/// 对打球
+///
+/// 两个小方面对面摆放,每个小方显示自己的半场,球板在最下面一行,球从上边出去就进入对方的半场
+#[derive(Debug)]
+pub struct PlayBall {
+    /// 对方的地址
+    peer: [u8; 6],
+    /// 下一帧使用的序号
+    seq: u16,
+    /// 最后一次收到对方的序号
+    peer_seq: u16,
+    /// 下一个事件的编号
+    next_event: u8,
+    /// 最后一次收到对方事件的编号
+    peer_event: u8,
+    /// 对方还没有回应的事件,每次心跳时重发
+    pending: Vec<Event>,
+    /// 球板最左边的 x 坐标
+    paddle: i32,
+    /// 球在自己的半场时才有
+    ball: Option<Ball>,
+    /// 自己的得分
+    score: u8,
+    /// 对方的得分
+    peer_score: u8,
+    /// 最后一次收到对方数据的时间
+    last_seen: Instant,
+}
+
+impl Default for PlayBall {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PlayBall {
+    pub fn new() -> Self {
+        Self {
+            peer: BROADCAST_ADDRESS,
+            seq: 0,
+            peer_seq: 0,
+            next_event: 1,
+            peer_event: 0,
+            pending: Vec::new(),
+            paddle: (8 - PADDLE_WIDTH) / 2,
+            ball: None,
+            score: 0,
+            peer_score: 0,
+            last_seen: Instant::now(),
+        }
+    }
+
+    pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
+        app.ledc.clear();
+        app.ad = Ad::default();
+
+        let Some(peer) = lobby::find_peer(app, game::PLAY_BALL).await else {
+            return;
+        };
+        self.peer = peer.address;
+        self.seq = peer.seq;
+        self.peer_seq = peer.peer_seq;
+        self.last_seen = Instant::now();
+        // 先手的一方先发球
+        if peer.first {
+            self.serve();
+        }
+
+        let outcome = self.play(app).await;
+        match outcome {
+            Outcome::Quit => {
+                self.send(app, Message::End).await;
+            }
+            Outcome::Win => {
+                unsafe { BUZZER.assume_init_mut().victory() };
+                app.face.break_record_animate(&mut app.ledc).await;
+            }
+            Outcome::Lose => {
+                // 之后没有心跳了,最后一个事件多发几次
+                for _ in 0..3 {
+                    Timer::after_millis(100).await;
+                    self.send_pending(app).await;
+                }
+                unsafe { BUZZER.assume_init_mut().play(SoundEvent::PlayBallMiss) };
+            }
+            Outcome::Disconnected => {}
+        }
+        if outcome != Outcome::Quit {
+            app.ledc.draw_number(self.score.into(), &[]).await;
+            Timer::after_millis(1500).await;
+        }
+        EspNowTransport(&mut app.esp_now).remove_peer(&self.peer);
+    }
+
+    /// 在自己的球板上方发球
+    fn serve(&mut self) {
+        let dx = if unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random(0, 2) } == 0 {
+            -1
+        } else {
+            1
+        };
+        self.ball = Some(Ball {
+            x: self.paddle + PADDLE_WIDTH / 2,
+            y: 6,
+            dx,
+            dy: -1,
+        });
+    }
+
+    async fn play<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) -> Outcome {
+        let mut next_ball = Instant::now() + Duration::from_millis(BALL_TIME);
+        let mut next_ping = Instant::now();
+
+        loop {
+            Timer::after_millis(100).await;
+
+            app.acc_direction();
+            if app.quit() {
+                return Outcome::Quit;
+            }
+            self.move_paddle(app.ad);
+
+            // 处理对方发来的消息
+            while let Some(r) = app.esp_now.receive() {
+                if r.info.src_address != self.peer {
+                    continue;
+                }
+                let Some(message) = self.accept(&r.data[..r.len as usize]) else {
+                    continue;
+                };
+                self.last_seen = Instant::now();
+                match message {
+                    Message::GameState(data) => {
+                        for event in self.new_events(data) {
+                            match event {
+                                Event::Ball { x, dx } => {
+                                    self.ball = Some(Ball {
+                                        x: x as i32,
+                                        y: 0,
+                                        dx: dx as i32,
+                                        dy: 1,
+                                    });
+                                    next_ball = Instant::now() + Duration::from_millis(BALL_TIME);
+                                }
+                                Event::Miss => {
+                                    self.score += 1;
+                                    unsafe {
+                                        BUZZER.assume_init_mut().play(SoundEvent::PlayBallScore)
+                                    };
+                                    if self.score >= WIN_SCORE {
+                                        return Outcome::Win;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    Message::End => return Outcome::Disconnected,
+                    _ => {}
+                }
+            }
+
+            let now = Instant::now();
+            if now > self.last_seen + Duration::from_millis(TIMEOUT) {
+                return Outcome::Disconnected;
+            }
+            if now >= next_ping {
+                next_ping = now + Duration::from_millis(PING_TIME);
+                // 对方还没有回应时重发事件代替心跳,丢了一帧也不会卡住
+                if self.pending.is_empty() {
+                    self.send(app, Message::Ping).await;
+                } else {
+                    self.send_pending(app).await;
+                }
+            }
+
+            if now >= next_ball {
+                next_ball = now + Duration::from_millis(BALL_TIME);
+                if let Some(outcome) = self.move_ball(app).await {
+                    return outcome;
+                }
+            }
+
+            self.draw(app);
+        }
+    }
+
+    fn move_paddle(&mut self, ad: Ad) {
+        match ad {
+            Ad::Left if self.paddle > 0 => self.paddle -= 1,
+            Ad::Right if self.paddle < 8 - PADDLE_WIDTH => self.paddle += 1,
+            _ => {}
+        }
+    }
+
+    /// 球走一格,在左右两边反弹,碰到球板反弹,从上边出去进入对方的半场
+    async fn move_ball<T: esp_hal::i2c::Instance>(
+        &mut self,
+        app: &mut App<'_, T>,
+    ) -> Option<Outcome> {
+        let mut ball = self.ball?;
+
+        if !(0..8).contains(&(ball.x + ball.dx)) {
+            ball.dx = -ball.dx;
+        }
+        // 球板在第 7 行
+        if ball.dy > 0 && ball.y == 6 {
+            let x = ball.x + ball.dx;
+            if (self.paddle..self.paddle + PADDLE_WIDTH).contains(&x)
+                || (self.paddle..self.paddle + PADDLE_WIDTH).contains(&ball.x)
+            {
+                ball.dy = -1;
+                unsafe { BUZZER.assume_init_mut().play(SoundEvent::PlayBallHit) };
+            }
+        }
+        ball.x += ball.dx;
+        ball.y += ball.dy;
+
+        if ball.y < 0 {
+            // 两个小方面对面,对方看到的左右是反的
+            self.ball = None;
+            let event = Event::Ball {
+                x: (7 - ball.x) as i8,
+                dx: -ball.dx as i8,
+            };
+            self.send_event(app, event).await;
+        } else if ball.y > 7 {
+            // 没有接住,对方得分,自己重新发球
+            self.ball = None;
+            self.peer_score += 1;
+            self.send_event(app, Event::Miss).await;
+            unsafe { BUZZER.assume_init_mut().play(SoundEvent::PlayBallMiss) };
+            if self.peer_score >= WIN_SCORE {
+                return Some(Outcome::Lose);
+            }
+            Timer::after_millis(1000).await;
+            self.serve();
+        } else {
+            self.ball = Some(ball);
+        }
+        None
+    }
+
+    /// 发送一个事件,对方回应之前每次心跳都会重发
+    async fn send_event<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>, event: Event) {
+        self.pending.push(event);
+        self.next_event = self.next_event.wrapping_add(1);
+        self.send_pending(app).await;
+    }
+
+    /// 发送对方还没有回应的所有事件,负载为第一个事件的编号和依次编码的事件
+    async fn send_pending<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
+        let first = self.next_event.wrapping_sub(self.pending.len() as u8);
+        let mut data = vec![first];
+        for event in self.pending.iter() {
+            data.extend(event.encode());
+        }
+        self.send(app, Message::GameState(&data)).await;
+    }
+
+    /// 取出对方发来的新事件,丢弃重发的
+    ///
+    /// 球只在一方的半场,对方产生新的事件说明已经收到了自己所有的事件,不用再重发
+    fn new_events(&mut self, data: &[u8]) -> Vec<Event> {
+        let mut events = Vec::new();
+        let Some((&first, mut rest)) = data.split_first() else {
+            return events;
+        };
+        let mut n = first;
+        while let Some((event, tail)) = Event::decode(rest) {
+            if n.wrapping_sub(self.peer_event) as i8 > 0 {
+                self.peer_event = n;
+                events.push(event);
+            }
+            n = n.wrapping_add(1);
+            rest = tail;
+        }
+        if !events.is_empty() {
+            self.pending.clear();
+        }
+        events
+    }
+
+    /// 发送消息,失败时重试几次
+    async fn send<T: esp_hal::i2c::Instance>(
+        &mut self,
+        app: &mut App<'_, T>,
+        message: Message<'_>,
+    ) {
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let Ok(len) = Packet::new(game::PLAY_BALL, seq, message).encode(&mut buf) else {
+            return;
+        };
+        for _ in 0..3 {
+            if app
+                .esp_now
+                .send_async(&self.peer, &buf[..len])
+                .await
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    /// 解析对方发来的数据,丢弃其他游戏的和重复的帧
+    fn accept<'a>(&mut self, data: &'a [u8]) -> Option<Message<'a>> {
+        let packet = Packet::decode(data).ok()?;
+        if packet.game != game::PLAY_BALL {
+            return None;
+        }
+        if !is_newer(packet.seq, self.peer_seq) {
+            return None;
+        }
+        self.peer_seq = packet.seq;
+        Some(packet.message)
+    }
+
+    fn draw<T: esp_hal::i2c::Instance>(&self, app: &mut App<'_, T>) {
+        let mut pixels = Vec::with_capacity(64);
+        for y in 0..8 {
+            for x in 0..8 {
+                pixels.push(Pixel((x, y).into(), Rgb888::CSS_BLACK));
+            }
+        }
+        for x in self.paddle..self.paddle + PADDLE_WIDTH {
+            pixels[(7 * 8 + x) as usize].1 = Rgb888::CSS_BLUE;
+        }
+        if let Some(ball) = self.ball {
+            pixels[(ball.y * 8 + ball.x) as usize].1 = Rgb888::CSS_RED;
+        }
+        app.ledc.write_pixels(pixels);
+    }
+}
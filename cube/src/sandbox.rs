@@ -0,0 +1,44 @@
+//! 沙盘
+//!
+//! 倾斜小方,沙子沿着重力方向下落,在斜面上滑落堆积,平放退出
+
+use crate::App;
+use embassy_time::Timer;
+use sand::{Gravity, Sand};
+
+/// 沙盘
+#[derive(Debug, Clone)]
+pub struct SandBox {
+    sand: Sand,
+}
+
+impl core::default::Default for SandBox {
+    fn default() -> Self {
+        let mut sand = Sand::new();
+        sand.fill(5..8);
+        Self { sand }
+    }
+}
+
+impl SandBox {
+    pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
+        app.ledc.write_bytes(self.sand.grains);
+
+        loop {
+            Timer::after_millis(80).await;
+
+            app.acc_direction();
+            if app.quit() {
+                break;
+            }
+
+            let accel = app.accel();
+            if self
+                .sand
+                .step(Gravity::from_accel(0.0 - accel.x(), accel.y()))
+            {
+                app.ledc.write_bytes(self.sand.grains);
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! 排行榜
+
+use crate::{ledc::LedControl, mapping, sound::SoundEvent, ui::Ui, Ad, App, BUZZER};
+use cube_net::game;
+use cube_store::scores::Table;
+use embassy_time::Timer;
+use embedded_graphics_core::{
+    pixelcolor::{Rgb888, WebColors},
+    Pixel,
+};
+use heapless::Vec;
+
+/// 游戏的模式,目前每个游戏只有一种模式
+pub const MODE: u8 = 0;
+
+/// 没有输入缩写时使用的缩写
+pub const NO_INITIALS: [u8; 3] = *b"---";
+
+/// 有排行榜的游戏代码,没有排行榜的界面返回 None
+pub fn game_of(ui: Ui) -> Option<u8> {
+    match ui {
+        Ui::Snake => Some(game::SNAKE),
+        Ui::CubeMan => Some(game::CUBE_MAN),
+        Ui::DodgeCube => Some(game::DODGE_CUBE),
+        _ => None,
+    }
+}
+
+/// 绘制正在输入的字母,字母居中,最下面一行的三个点表示输入的位置
+fn draw_picker(ledc: &mut LedControl, initials: &[u8; 3], pos: usize) {
+    let glyph = mapping::char_map(initials[pos]);
+    let pixels = (0..64).map(|i| {
+        let (x, y) = (i % 8, i / 8);
+        let c = if y < 7 {
+            if (glyph[y] >> 2) & (0x80 >> x) > 0 {
+                Rgb888::CSS_WHITE
+            } else {
+                Rgb888::CSS_BLACK
+            }
+        } else {
+            match x {
+                1 | 3 | 5 if x / 2 == pos => Rgb888::CSS_GOLD,
+                1 | 3 | 5 => Rgb888::CSS_DIM_GRAY,
+                _ => Rgb888::CSS_BLACK,
+            }
+        };
+        Pixel((x as i32, y as i32).into(), c)
+    });
+    ledc.write_pixels(pixels);
+}
+
+/// 输入三个字母的缩写
+///
+/// 左右倾斜切换字母,向上确认当前字母,向后倾斜回到上一个字母,平放直接使用当前的缩写
+pub async fn pick_initials<T: esp_hal::i2c::Instance>(app: &mut App<'_, T>) -> [u8; 3] {
+    let buzzer = unsafe { BUZZER.assume_init_mut() };
+    let mut initials = *b"AAA";
+    let mut pos = 0;
+    app.ad = Ad::default();
+    // 回正之后才能确认,避免一次倾斜确认多个字母
+    let mut armed = false;
+
+    loop {
+        draw_picker(&mut app.ledc, &initials, pos);
+        Timer::after_millis(300).await;
+
+        app.acc_direction();
+        match app.ad {
+            Ad::None => armed = true,
+            Ad::Right | Ad::Left => {
+                let c = initials[pos] - b'A';
+                initials[pos] = b'A'
+                    + if app.ad == Ad::Right {
+                        (c + 1) % 26
+                    } else {
+                        (c + 25) % 26
+                    };
+                buzzer.play(SoundEvent::MenuSelect);
+            }
+            Ad::Front if armed => {
+                armed = false;
+                buzzer.play(SoundEvent::MenuConfirm);
+                if pos == initials.len() - 1 {
+                    return initials;
+                }
+                pos += 1;
+            }
+            Ad::Back if armed => {
+                armed = false;
+                pos = pos.saturating_sub(1);
+                buzzer.play(SoundEvent::MenuSelect);
+            }
+            Ad::Down => return initials,
+            _ => {}
+        }
+    }
+}
+
+/// 分数能上榜时输入缩写并记录,返回是否上榜
+pub async fn record<T: esp_hal::i2c::Instance>(
+    app: &mut App<'_, T>,
+    table: &mut Table,
+    score: u16,
+) -> bool {
+    if !table.qualifies(score) {
+        return false;
+    }
+    unsafe { BUZZER.assume_init_mut().victory() };
+    let initials = pick_initials(app).await;
+    if let Some(rank) = table.insert(initials, score) {
+        app.ledc
+            .draw_number(rank as u32 + 1, &[Rgb888::CSS_GOLD])
+            .await;
+        Timer::after_millis(1000).await;
+    }
+    true
+}
+
+/// 排行榜
+#[derive(Debug)]
+pub struct ScoreBoard {
+    table: Table,
+}
+
+impl ScoreBoard {
+    pub fn new(table: Table) -> Self {
+        Self { table }
+    }
+
+    /// 依次显示名次、缩写和分数
+    async fn draw(&self, ledc: &mut LedControl<'_>, idx: usize) {
+        let Some(entry) = self.table.entries().get(idx) else {
+            ledc.draw_text(&NO_INITIALS, &[Rgb888::CSS_DIM_GRAY]).await;
+            return;
+        };
+        let mut text = Vec::<u8, 12>::new();
+        let mut colors = Vec::<Rgb888, 12>::new();
+        text.push(b'1' + idx as u8).ok();
+        colors.push(Rgb888::CSS_GOLD).ok();
+        for c in entry.initials {
+            text.push(c).ok();
+            colors.push(Rgb888::CSS_WHITE).ok();
+        }
+        text.push(b' ').ok();
+        colors.push(Rgb888::CSS_BLACK).ok();
+        let mut digits = Vec::<u8, 5>::new();
+        let mut n = entry.score;
+        loop {
+            digits.push(b'0' + (n % 10) as u8).ok();
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        for d in digits.iter().rev() {
+            text.push(*d).ok();
+            colors.push(Rgb888::CSS_CYAN).ok();
+        }
+        ledc.draw_text(&text, &colors).await;
+    }
+
+    /// 左右倾斜切换名次,平放退出
+    pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
+        let len = self.table.entries().len().max(1);
+        let mut idx = 0;
+        app.ad = Ad::default();
+        self.draw(&mut app.ledc, idx).await;
+
+        loop {
+            Timer::after_millis(300).await;
+
+            app.acc_direction();
+            match app.ad {
+                Ad::Right | Ad::Left => {
+                    idx = if app.ad == Ad::Right {
+                        (idx + 1) % len
+                    } else {
+                        (idx + len - 1) % len
+                    };
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuSelect) };
+                    self.draw(&mut app.ledc, idx).await;
+                }
+                Ad::Down => break,
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! 设置
+
+use crate::{
+    sound::{SoundEvent, Theme, Volume},
+    Ad, App, BUZZER,
+};
+use embassy_time::Timer;
+
+/// 音效主题的图标,依次为方波、正弦波和铃铛
+#[rustfmt::skip]
+const THEME_ICONS: [[u8; 8]; 3] = [
+    [
+        0b00000000,
+        0b00000000,
+        0b11101110,
+        0b10101010,
+        0b10101010,
+        0b10111011,
+        0b00000000,
+        0b00000000,
+    ],
+    [
+        0b00000000,
+        0b00000000,
+        0b01100000,
+        0b10010001,
+        0b00001010,
+        0b00000100,
+        0b00000000,
+        0b00000000,
+    ],
+    [
+        0b00011000,
+        0b00111100,
+        0b00111100,
+        0b00111100,
+        0b01111110,
+        0b11111111,
+        0b00000000,
+        0b00011000,
+    ],
+];
+
+/// 试听音量的频率,单位Hz
+const PREVIEW_FREQUENCY: u32 = 2000;
+
+/// 音量的图标,由低到高的四格,亮起的格数表示音量
+fn volume_icon(level: usize) -> [u8; 8] {
+    let mut icon = [0u8; 8];
+    for bar in 0..Volume::ALL.len() {
+        // 没有亮起的格只显示底部
+        let height = if bar <= level { 2 * (bar + 1) } else { 1 };
+        for row in icon.iter_mut().skip(8 - height) {
+            *row |= 0b1000_0000 >> (2 * bar);
+        }
+    }
+    icon
+}
+
+/// 设置项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Item {
+    /// 音效主题
+    Theme,
+    /// 音量
+    Volume,
+}
+
+/// 设置
+#[derive(Debug, Default)]
+pub struct Settings {
+    /// 音效主题
+    pub theme: Theme,
+    /// 音量
+    pub volume: Volume,
+}
+
+impl Settings {
+    /// 向后倾斜切换设置项,左右倾斜切换选项并试听,向上确认,平放退出
+    ///
+    /// 确认时返回 true,需要保存到flash
+    pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) -> bool {
+        let buzzer = unsafe { BUZZER.assume_init_mut() };
+        let (theme, volume) = (buzzer.theme, buzzer.volume);
+        let mut theme_idx = Theme::ALL
+            .iter()
+            .position(|t| *t == self.theme)
+            .unwrap_or(0);
+        let mut volume_idx = Volume::ALL
+            .iter()
+            .position(|v| *v == self.volume)
+            .unwrap_or(0);
+        let mut item = Item::Theme;
+        app.ad = Ad::default();
+        // 从菜单进入时仍是向上倾斜,回正之后才能确认
+        let mut armed = false;
+
+        loop {
+            match item {
+                Item::Theme => app.ledc.write_bytes(THEME_ICONS[theme_idx]),
+                Item::Volume => app.ledc.write_bytes(volume_icon(volume_idx)),
+            }
+            Timer::after_millis(300).await;
+
+            app.acc_direction();
+            match app.ad {
+                Ad::None => armed = true,
+                Ad::Back if armed => {
+                    item = match item {
+                        Item::Theme => Item::Volume,
+                        Item::Volume => Item::Theme,
+                    };
+                    armed = false;
+                    buzzer.play(SoundEvent::MenuSelect);
+                }
+                Ad::Right | Ad::Left => {
+                    let (idx, len) = match item {
+                        Item::Theme => (&mut theme_idx, Theme::ALL.len()),
+                        Item::Volume => (&mut volume_idx, Volume::ALL.len()),
+                    };
+                    *idx = if app.ad == Ad::Right {
+                        (*idx + 1) % len
+                    } else {
+                        (*idx + len - 1) % len
+                    };
+                    buzzer.theme = Theme::ALL[theme_idx];
+                    buzzer.volume = Volume::ALL[volume_idx];
+                    match item {
+                        // 用新的主题试听
+                        Item::Theme => buzzer.play(SoundEvent::MenuConfirm),
+                        // 只播放提醒的主题没有菜单音效,音量用固定的音试听
+                        Item::Volume => buzzer.tone(PREVIEW_FREQUENCY, 150).await,
+                    }
+                }
+                Ad::Front if armed => {
+                    self.theme = Theme::ALL[theme_idx];
+                    self.volume = Volume::ALL[volume_idx];
+                    buzzer.theme = self.theme;
+                    buzzer.volume = self.volume;
+                    buzzer.play(SoundEvent::MenuConfirm);
+                    return true;
+                }
+                Ad::Down => {
+                    buzzer.theme = theme;
+                    buzzer.volume = volume;
+                    return false;
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 #![doc = include_str!("../../rfcs/003_snake.md")]
 
-use crate::{Ad, App, Direction, BUZZER, RNG};
+use crate::{sound::SoundEvent, Ad, App, Direction, BUZZER, RNG};
 use alloc::collections::LinkedList;
 use cube_rand::CubeRng;
 use embassy_time::Timer;
@@ -19,9 +19,9 @@ pub struct SnakeGame {
     /// ms
     waiting_time: u64,
     /// 得分
-    score: u8,
+    score: u16,
     /// 最高分
-    pub highest: u8,
+    pub highest: u16,
     game_over: bool,
 }
 
@@ -48,6 +48,16 @@ impl SnakeGame {
         }
     }
 
+    /// 本局的得分
+    pub fn score(&self) -> u16 {
+        self.score
+    }
+
+    /// 蛇的长度
+    pub fn snake_len(&self) -> usize {
+        self.snake.body.len()
+    }
+
     pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
         app.ledc.clear();
         app.ad = Ad::default();
@@ -56,8 +66,8 @@ impl SnakeGame {
             Timer::after_millis(self.waiting_time).await;
 
             if self.game_over {
-                unsafe { BUZZER.assume_init_mut().snake_die().await };
-                app.ledc.draw_score(self.score);
+                unsafe { BUZZER.assume_init_mut().play(SoundEvent::SnakeDie) };
+                app.ledc.draw_score(self.score.into()).await;
                 Timer::after_millis(1500).await;
                 if self.score > self.highest {
                     self.highest = self.score;
@@ -85,19 +95,19 @@ impl SnakeGame {
 
         let next_head = self.snake.next_head_pos();
         if self.food.pos.eq(&next_head) {
-            unsafe { BUZZER.assume_init_mut().snake_score().await };
+            unsafe { BUZZER.assume_init_mut().play(SoundEvent::SnakeScore) };
             // TODO: 得分画面效果
 
             self.snake.grow(self.food.clone());
             self.food
                 .create_food(self.width, self.height, &self.snake.body);
             self.calc_score();
-            unsafe { BUZZER.assume_init_mut().snake_move().await };
+            unsafe { BUZZER.assume_init_mut().play(SoundEvent::SnakeMove) };
         } else if self.outside(next_head) || self.snake.overlapping() {
             self.game_over = true;
         } else {
             self.snake.r#move();
-            unsafe { BUZZER.assume_init_mut().snake_move().await };
+            unsafe { BUZZER.assume_init_mut().play(SoundEvent::SnakeMove) };
         }
     }
 
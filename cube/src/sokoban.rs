@@ -3,6 +3,7 @@
 use crate::{
     map::{Map, MapCell, Vision},
     player::Player,
+    sound::SoundEvent,
     Ad, App, Point, BUZZER,
 };
 use alloc::vec::Vec;
@@ -62,6 +63,11 @@ impl Sokoban {
         }
     }
 
+    /// 是否已经通关
+    pub fn cleared(&self) -> bool {
+        self.game_over
+    }
+
     pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
         app.ledc.clear();
         app.ad = Ad::default();
@@ -84,7 +90,7 @@ impl Sokoban {
                 if can_push {
                     let moved = self.player.r#move(app.ad);
                     if moved {
-                        unsafe { BUZZER.assume_init_mut().sokoban_move().await };
+                        unsafe { BUZZER.assume_init_mut().play(SoundEvent::SokobanMove) };
                     }
                     // 玩家移动之后视野数据改变
                     self.vision.update(app.ad, &self.map.map);
@@ -0,0 +1,474 @@
+//! 音效
+//!
+//! 每个音效都是静态的数据:优先级和依次播放的频率、时长,由 [`audio_task`](crate::buzzer::audio_task) 播放.
+//!
+//! 界面和游戏只发出 [`SoundEvent`],由当前的 [`Theme`] 决定播放哪个音效,新增一个音效只需要在这里加一个事件和对应的数据
+
+/// 优先级,高优先级的声音会打断低优先级的声音
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// 菜单、移动等操作的反馈
+    Low,
+    /// 得分、结束等游戏中的音效
+    Normal,
+    /// 开机、休眠、沙漏结束等提醒
+    High,
+}
+
+/// 音效
+#[derive(Debug)]
+pub struct Sound {
+    pub priority: Priority,
+    /// 依次播放的频率和时长,单位分别为Hz和毫秒,频率为 0 时为休止
+    pub tones: &'static [(u32, u64)],
+}
+
+/// 声音事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEvent {
+    /// 菜单选择
+    MenuSelect,
+    /// 菜单确认
+    MenuConfirm,
+    /// 菜单进入
+    MenuAccess,
+    /// 八卦
+    BaGua,
+    /// 骰子
+    Dice,
+    /// 迷宫移动
+    MazeMove,
+    /// 迷宫结束
+    MazeOver,
+    /// 开机
+    PowerOn,
+    /// 唤醒
+    Wakeup,
+    /// 沙漏像素闪烁
+    TimerPixelBlinky,
+    /// 沙漏像素反弹
+    TimerPixelRebound,
+    /// 沙漏结束
+    TimersOver,
+    /// 贪吃蛇移动
+    SnakeMove,
+    /// 贪吃蛇得分
+    SnakeScore,
+    /// 贪吃蛇死亡
+    SnakeDie,
+    /// 躲避方块移动
+    DodgeCubeMove,
+    /// 躲避方块死亡
+    DodgeCubeDie,
+    /// 对打球击球
+    PlayBallHit,
+    /// 对打球得分
+    PlayBallScore,
+    /// 对打球丢球
+    PlayBallMiss,
+    /// 推箱子移动
+    SokobanMove,
+    /// 休眠
+    Sleep,
+    /// 休眠2
+    Sleep2,
+    /// 眨眼
+    Blinky,
+    /// 眨眼2
+    Blinky2,
+}
+
+impl SoundEvent {
+    /// 提醒类的事件,只播放提醒的主题也会播放
+    pub fn is_alert(&self) -> bool {
+        matches!(
+            self,
+            SoundEvent::PowerOn | SoundEvent::Wakeup | SoundEvent::TimersOver
+        )
+    }
+}
+
+/// 音效主题
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    /// 经典的芯片音乐
+    #[default]
+    Classic,
+    /// 柔和
+    Soft,
+    /// 只播放提醒
+    Alerts,
+}
+
+impl Theme {
+    /// 可以选择的主题
+    pub const ALL: [Theme; 3] = [Theme::Classic, Theme::Soft, Theme::Alerts];
+
+    /// 保存到flash中的值
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// 从flash中恢复,无效的值返回 None
+    pub fn from_u8(v: u8) -> Option<Self> {
+        Self::ALL.get(v as usize).copied()
+    }
+
+    /// 事件对应的音效,有多个时随机选择一个,为空时不播放
+    pub fn sounds(&self, event: SoundEvent) -> &'static [Sound] {
+        match self {
+            Theme::Classic => classic(event),
+            Theme::Soft => soft(event),
+            Theme::Alerts if event.is_alert() => classic(event),
+            Theme::Alerts => &[],
+        }
+    }
+}
+
+/// 音量,通过蜂鸣器 PWM 的占空比控制
+///
+/// 无源蜂鸣器在占空比为 50% 时最响,占空比越小声音越轻
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Volume {
+    /// 轻
+    Low,
+    /// 中
+    Medium,
+    /// 高
+    High,
+    /// 最大
+    #[default]
+    Max,
+}
+
+impl Volume {
+    /// 可以选择的音量,从轻到响
+    pub const ALL: [Volume; 4] = [Volume::Low, Volume::Medium, Volume::High, Volume::Max];
+
+    /// 对应的占空比
+    pub fn duty_pct(self) -> u8 {
+        match self {
+            Volume::Low => 3,
+            Volume::Medium => 10,
+            Volume::High => 25,
+            Volume::Max => 50,
+        }
+    }
+
+    /// 保存到flash中的值
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// 从flash中恢复,无效的值返回 None
+    pub fn from_u8(v: u8) -> Option<Self> {
+        Self::ALL.get(v as usize).copied()
+    }
+}
+
+/// 经典主题
+fn classic(event: SoundEvent) -> &'static [Sound] {
+    use core::slice::from_ref;
+    match event {
+        SoundEvent::MenuSelect => from_ref(&MENU_SELECT),
+        SoundEvent::MenuConfirm => from_ref(&MENU_CONFIRM),
+        SoundEvent::MenuAccess => from_ref(&MENU_ACCESS),
+        SoundEvent::BaGua => from_ref(&BAGUA),
+        SoundEvent::Dice => from_ref(&DICE),
+        SoundEvent::MazeMove => from_ref(&MAZE_MOVE),
+        SoundEvent::MazeOver => from_ref(&MAZE_OVER),
+        SoundEvent::PowerOn => from_ref(&POWER_ON),
+        SoundEvent::Wakeup => from_ref(&WAKEUP),
+        SoundEvent::TimerPixelBlinky => from_ref(&TIMER_PIXEL_BLINKY),
+        SoundEvent::TimerPixelRebound => from_ref(&TIMER_PIXEL_REBOUND),
+        SoundEvent::TimersOver => from_ref(&TIMERS_OVER),
+        SoundEvent::SnakeMove => from_ref(&SNAKE_MOVE),
+        SoundEvent::SnakeScore => from_ref(&SNAKE_SCORE),
+        SoundEvent::SnakeDie => from_ref(&SNAKE_DIE),
+        SoundEvent::DodgeCubeMove => from_ref(&DODGE_CUBE_MOVE),
+        SoundEvent::DodgeCubeDie => from_ref(&DODGE_CUBE_DIE),
+        SoundEvent::PlayBallHit => from_ref(&PLAY_BALL_HIT),
+        SoundEvent::PlayBallScore => from_ref(&PLAY_BALL_SCORE),
+        SoundEvent::PlayBallMiss => from_ref(&PLAY_BALL_MISS),
+        SoundEvent::SokobanMove => from_ref(&SOKOBAN_MOVE),
+        SoundEvent::Sleep => from_ref(&SLEEP),
+        SoundEvent::Sleep2 => &SLEEP2,
+        SoundEvent::Blinky => from_ref(&BLINKY),
+        SoundEvent::Blinky2 => from_ref(&BLINKY2),
+    }
+}
+
+/// 柔和主题,频率更低,时间更短
+fn soft(event: SoundEvent) -> &'static [Sound] {
+    use core::slice::from_ref;
+    match event {
+        SoundEvent::MenuSelect
+        | SoundEvent::MazeMove
+        | SoundEvent::TimerPixelBlinky
+        | SoundEvent::TimerPixelRebound
+        | SoundEvent::SnakeMove
+        | SoundEvent::DodgeCubeMove
+        | SoundEvent::SokobanMove
+        | SoundEvent::Sleep
+        | SoundEvent::Sleep2
+        | SoundEvent::Blinky
+        | SoundEvent::Blinky2 => from_ref(&SOFT_CLICK),
+        SoundEvent::MenuConfirm | SoundEvent::MenuAccess => from_ref(&SOFT_CONFIRM),
+        SoundEvent::BaGua | SoundEvent::Dice => from_ref(&SOFT_ROLL),
+        SoundEvent::MazeOver | SoundEvent::SnakeScore | SoundEvent::PlayBallScore => {
+            from_ref(&SOFT_SCORE)
+        }
+        SoundEvent::SnakeDie | SoundEvent::DodgeCubeDie | SoundEvent::PlayBallMiss => {
+            from_ref(&SOFT_FAIL)
+        }
+        SoundEvent::PlayBallHit => from_ref(&SOFT_HIT),
+        SoundEvent::PowerOn => from_ref(&SOFT_POWER_ON),
+        SoundEvent::Wakeup => from_ref(&SOFT_WAKEUP),
+        SoundEvent::TimersOver => from_ref(&SOFT_ALERT),
+    }
+}
+
+/// 菜单选择
+pub static MENU_SELECT: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(1500, 300)],
+};
+
+/// 菜单确认,频率逐渐升高
+#[rustfmt::skip]
+pub static MENU_CONFIRM: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[
+        (400, 50), (500, 50), (600, 50), (700, 50), (800, 50), (900, 50), (1000, 50), (1100, 50),
+        (1200, 50), (1300, 50), (1400, 50), (1500, 50), (1600, 50), (1700, 50), (1800, 50), (1900, 50),
+    ],
+};
+
+/// 菜单进入,频率逐渐降低
+#[rustfmt::skip]
+pub static MENU_ACCESS: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[
+        (3000, 50), (2800, 50), (2600, 50), (2400, 50), (2200, 50), (2000, 50), (1800, 50), (1600, 50),
+        (1400, 50), (1200, 50), (1000, 50), (800, 50), (600, 50), (400, 50), (200, 50),
+    ],
+};
+
+/// 八卦
+#[rustfmt::skip]
+pub static BAGUA: Sound = Sound {
+    priority: Priority::Normal,
+    tones: &[
+        (3000, 50), (2800, 50), (2600, 50), (2400, 50), (2200, 50), (2000, 50), (1800, 50), (1600, 50),
+        (1400, 50), (1200, 50), (1000, 50), (800, 50), (600, 50), (400, 50), (200, 50),
+    ],
+};
+
+/// 骰子
+#[rustfmt::skip]
+pub static DICE: Sound = Sound {
+    priority: Priority::Normal,
+    tones: &[
+        (3000, 50), (2600, 50), (2200, 50), (1800, 50), (1400, 50), (1000, 50), (600, 50), (200, 50),
+    ],
+};
+
+/// 迷宫移动
+pub static MAZE_MOVE: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(5000, 100)],
+};
+
+/// 迷宫结束
+pub static MAZE_OVER: Sound = Sound {
+    priority: Priority::Normal,
+    tones: &[(6000, 100), (6000, 100), (6000, 100), (6000, 150)],
+};
+
+/// 开机
+pub static POWER_ON: Sound = Sound {
+    priority: Priority::High,
+    tones: &[(800, 200), (2500, 100), (8000, 200)],
+};
+
+/// 唤醒
+pub static WAKEUP: Sound = Sound {
+    priority: Priority::High,
+    tones: &[(1500, 200), (8000, 200)],
+};
+
+/// 沙漏像素闪烁
+pub static TIMER_PIXEL_BLINKY: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(8000, 100)],
+};
+
+/// 沙漏像素反弹
+pub static TIMER_PIXEL_REBOUND: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(4000, 100)],
+};
+
+/// 沙漏结束
+pub static TIMERS_OVER: Sound = Sound {
+    priority: Priority::High,
+    tones: &[(6000, 100), (6000, 100), (6000, 100), (6000, 150)],
+};
+
+/// 贪吃蛇移动
+pub static SNAKE_MOVE: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(5000, 100)],
+};
+
+/// 贪吃蛇得分
+pub static SNAKE_SCORE: Sound = Sound {
+    priority: Priority::Normal,
+    tones: &[(2000, 1000), (3000, 1000), (2000, 1000)],
+};
+
+/// 贪吃蛇死亡
+pub static SNAKE_DIE: Sound = Sound {
+    priority: Priority::Normal,
+    tones: &[(500, 1000), (300, 1000), (100, 1000)],
+};
+
+/// 躲避方块移动
+pub static DODGE_CUBE_MOVE: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(5000, 100)],
+};
+
+/// 躲避方块死亡
+pub static DODGE_CUBE_DIE: Sound = Sound {
+    priority: Priority::Normal,
+    tones: &[(500, 1000), (300, 1000), (100, 1000)],
+};
+
+/// 对打球击球
+pub static PLAY_BALL_HIT: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(4000, 100)],
+};
+
+/// 对打球得分
+pub static PLAY_BALL_SCORE: Sound = Sound {
+    priority: Priority::Normal,
+    tones: &[(2000, 200), (3000, 200)],
+};
+
+/// 对打球丢球
+pub static PLAY_BALL_MISS: Sound = Sound {
+    priority: Priority::Normal,
+    tones: &[(500, 300), (300, 300), (100, 300)],
+};
+
+/// 推箱子移动
+pub static SOKOBAN_MOVE: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(5000, 100)],
+};
+
+/// 休眠
+pub static SLEEP: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(6000, 100)],
+};
+
+/// 休眠2,随机选择一个
+pub static SLEEP2: [Sound; 7] = [
+    Sound {
+        priority: Priority::Low,
+        tones: &[(3000, 100)],
+    },
+    Sound {
+        priority: Priority::Low,
+        tones: &[(4000, 100)],
+    },
+    Sound {
+        priority: Priority::Low,
+        tones: &[(5000, 100)],
+    },
+    Sound {
+        priority: Priority::Low,
+        tones: &[(6000, 100)],
+    },
+    Sound {
+        priority: Priority::Low,
+        tones: &[(7000, 100)],
+    },
+    Sound {
+        priority: Priority::Low,
+        tones: &[(8000, 100)],
+    },
+    Sound {
+        priority: Priority::Low,
+        tones: &[(9000, 100)],
+    },
+];
+
+/// 眨眼
+pub static BLINKY: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(8000, 100)],
+};
+
+/// 眨眼2
+pub static BLINKY2: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(5000, 100)],
+};
+
+/// 柔和的点击声
+pub static SOFT_CLICK: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(800, 40)],
+};
+
+/// 柔和的确认声,do mi sol
+pub static SOFT_CONFIRM: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(523, 60), (659, 60), (784, 80)],
+};
+
+/// 柔和的滚动声
+pub static SOFT_ROLL: Sound = Sound {
+    priority: Priority::Normal,
+    tones: &[(600, 40), (500, 40), (400, 40), (300, 40)],
+};
+
+/// 柔和的得分声
+pub static SOFT_SCORE: Sound = Sound {
+    priority: Priority::Normal,
+    tones: &[(784, 120), (1047, 160)],
+};
+
+/// 柔和的失败声
+pub static SOFT_FAIL: Sound = Sound {
+    priority: Priority::Normal,
+    tones: &[(392, 200), (330, 200), (262, 300)],
+};
+
+/// 柔和的击球声
+pub static SOFT_HIT: Sound = Sound {
+    priority: Priority::Low,
+    tones: &[(1000, 50)],
+};
+
+/// 柔和的提醒,两短一长
+pub static SOFT_ALERT: Sound = Sound {
+    priority: Priority::High,
+    tones: &[(1047, 150), (0, 100), (1047, 150), (0, 100), (1319, 300)],
+};
+
+/// 柔和的开机声
+pub static SOFT_POWER_ON: Sound = Sound {
+    priority: Priority::High,
+    tones: &[(523, 120), (784, 120), (1047, 200)],
+};
+
+/// 柔和的唤醒声
+pub static SOFT_WAKEUP: Sound = Sound {
+    priority: Priority::High,
+    tones: &[(784, 120), (1047, 160)],
+};
@@ -0,0 +1,267 @@
+#![doc = include_str!("../../rfcs/009_music_spectrum.md")]
+
+use crate::{face::Face, mic, sound::SoundEvent, Ad, App, BUZZER};
+use alloc::vec::Vec;
+use cube_dsp::{Agc, BeatDetector, Calibration, Calibrator, Frame, Peaks, BANDS, HEIGHT};
+use embassy_futures::select::{select, Either};
+use embassy_time::Timer;
+use embedded_graphics::{
+    pixelcolor::{Rgb888, WebColors},
+    Pixel,
+};
+use smart_leds::hsv::{hsv2rgb, Hsv};
+
+/// 噪声校准采集的帧数,约 1s
+const CALIBRATION_FRAMES: u32 = 60;
+
+/// 根据一帧音频绘制点阵,新增一种可视化只需要加一个绘制函数
+type Render = fn(&mut Visuals, &Frame) -> Vec<Pixel<Rgb888>>;
+
+/// 可视化,左右倾斜切换
+const VISUALIZERS: [Render; 4] = [spectrum, vu_meter, waveform, beat_pulse];
+
+/// 音乐频谱
+#[derive(Debug, Default)]
+pub struct MusicSpectrum {
+    /// 当前可视化在 VISUALIZERS 中的索引
+    idx: usize,
+    /// 噪声校准的结果,从flash中获取,没有校准过时进入界面先校准
+    pub calibration: Option<Calibration>,
+    visuals: Visuals,
+}
+
+impl MusicSpectrum {
+    pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
+        app.ledc.clear();
+        mic::enable();
+        match self.calibration {
+            Some(calibration) => self.visuals.calibration = calibration,
+            None => self.calibrate(app).await,
+        }
+        // 从菜单进入时仍是向上倾斜,回正之后才能切换
+        let mut armed = false;
+
+        loop {
+            // 没有采集到数据时也要检查是否退出
+            let frame = match select(mic::FRAME.wait(), Timer::after_millis(200)).await {
+                Either::First(frame) => Some(frame),
+                Either::Second(_) => None,
+            };
+
+            app.acc_direction();
+            match app.ad {
+                Ad::Down => break,
+                Ad::None => armed = true,
+                Ad::Right if armed => {
+                    armed = false;
+                    self.idx = (self.idx + 1) % VISUALIZERS.len();
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuSelect) };
+                }
+                Ad::Left if armed => {
+                    armed = false;
+                    self.idx = (self.idx + VISUALIZERS.len() - 1) % VISUALIZERS.len();
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuSelect) };
+                }
+                // 向下倾斜重新校准
+                Ad::Back if armed => {
+                    armed = false;
+                    self.calibrate(app).await;
+                }
+                _ => {}
+            }
+
+            if let Some(frame) = frame {
+                let frame = Frame::new(&frame);
+                let pixels = VISUALIZERS[self.idx](&mut self.visuals, &frame);
+                app.ledc.write_pixels(pixels);
+            }
+        }
+
+        mic::disable();
+    }
+
+    /// 保持安静一会儿,估计环境的噪声
+    async fn calibrate<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
+        let mut calibrator = Calibrator::default();
+        mic::FRAME.reset();
+        while calibrator.frames() < CALIBRATION_FRAMES {
+            // 中间一条横线从左往右表示进度
+            let progress = (calibrator.frames() * 8 / CALIBRATION_FRAMES) as usize;
+            let mut data = [0u8; 8];
+            data[3] = !(0xffu8 >> progress);
+            data[4] = data[3];
+            app.ledc.write_bytes(data);
+
+            match select(mic::FRAME.wait(), Timer::after_millis(200)).await {
+                Either::First(frame) => calibrator.add(&Frame::new(&frame)),
+                Either::Second(_) => break,
+            }
+        }
+        // 没有采集到数据时放弃校准,保留原来的结果
+        if calibrator.frames() == 0 {
+            return;
+        }
+
+        let calibration = calibrator.finish();
+        self.calibration = Some(calibration);
+        self.visuals.calibration = calibration;
+        self.visuals.band_agc = Agc::default();
+        self.visuals.sample_agc = Agc::default();
+        unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuConfirm) };
+    }
+}
+
+/// 可视化之间共用的状态
+#[derive(Debug, Default)]
+struct Visuals {
+    calibration: Calibration,
+    /// 频段幅值的自动增益
+    band_agc: Agc,
+    /// 采样幅值的自动增益
+    sample_agc: Agc,
+    /// 频谱的峰值点
+    peaks: Peaks,
+    /// VU 表的峰值点,第 0 个为均方根,第 1 个为峰值
+    vu_peaks: Peaks,
+    /// 示波器每一列的高度,最新的一列在最右边
+    wave: [u8; 8],
+    beat: BeatDetector,
+    /// 节拍脉冲的亮度,每帧衰减
+    pulse: u8,
+}
+
+/// 频谱:彩虹色的柱子从底部升起,白色的峰值点缓慢下落
+fn spectrum(visuals: &mut Visuals, frame: &Frame) -> Vec<Pixel<Rgb888>> {
+    let floor = visuals.calibration.band_floor;
+    let ceil = visuals.band_agc.update(frame.loudest(), floor);
+    let heights = cube_dsp::heights(&frame.bands, floor, ceil);
+    visuals.peaks.update(&heights);
+
+    let mut pixels = Vec::with_capacity(64);
+    for x in 0..BANDS {
+        let color = hsv2rgb(Hsv {
+            hue: (x * 255 / BANDS) as u8,
+            sat: 255,
+            val: 255,
+        });
+        let color = Rgb888::new(color.r, color.g, color.b);
+        let peak = visuals.peaks.heights[x];
+        for h in 1..=HEIGHT {
+            let y = (HEIGHT - h) as i32;
+            let c = if h == peak {
+                Rgb888::CSS_WHITE
+            } else if h <= heights[x] {
+                color
+            } else {
+                Rgb888::CSS_BLACK
+            };
+            pixels.push(Pixel((x as i32, y).into(), c));
+        }
+    }
+    pixels
+}
+
+/// VU 表:上半部分为均方根,下半部分为峰值,从左往右由绿变红
+fn vu_meter(visuals: &mut Visuals, frame: &Frame) -> Vec<Pixel<Rgb888>> {
+    let floor = visuals.calibration.sample_floor;
+    let ceil = visuals.sample_agc.update(frame.peak, floor);
+    let mut levels = [0; BANDS];
+    levels[0] = cube_dsp::level(frame.rms, floor, ceil);
+    levels[1] = cube_dsp::level(frame.peak, floor, ceil);
+    visuals.vu_peaks.update(&levels);
+
+    let mut pixels = Vec::with_capacity(64);
+    for (channel, rows) in [(0, 1..3), (1, 5..7)] {
+        let level = levels[channel];
+        let hold = visuals.vu_peaks.heights[channel];
+        for x in 0..8u8 {
+            let c = if x + 1 == hold {
+                Rgb888::CSS_WHITE
+            } else if x < level {
+                match x {
+                    0..=4 => Rgb888::CSS_GREEN,
+                    5..=6 => Rgb888::CSS_YELLOW,
+                    _ => Rgb888::CSS_RED,
+                }
+            } else {
+                Rgb888::CSS_BLACK
+            };
+            for y in rows.clone() {
+                pixels.push(Pixel((x as i32, y).into(), c));
+            }
+        }
+    }
+    pixels
+}
+
+/// 示波器:每一帧取绝对值最大的采样作为新的一列,从右往左滚动
+fn waveform(visuals: &mut Visuals, frame: &Frame) -> Vec<Pixel<Rgb888>> {
+    let sample = frame
+        .samples
+        .iter()
+        .fold(0.0, |max, s| if abs(*s) > abs(max) { *s } else { max });
+    let scale = visuals
+        .sample_agc
+        .update(frame.peak, visuals.calibration.sample_floor);
+    let y = cube_dsp::map_range(sample, scale, 0.0 - scale, 0.0, 7.0) + 0.5;
+    let y = if y < 0.0 { 0 } else { (y as u8).min(7) };
+    visuals.wave.rotate_left(1);
+    visuals.wave[7] = y;
+
+    let mut pixels = Vec::with_capacity(64);
+    for (x, y) in visuals.wave.iter().enumerate() {
+        for row in 0..8u8 {
+            let c = if row == *y {
+                Rgb888::CSS_CYAN
+            } else {
+                Rgb888::CSS_BLACK
+            };
+            pixels.push(Pixel((x as i32, row as i32).into(), c));
+        }
+    }
+    pixels
+}
+
+/// 节拍:每个节拍点亮笑脸,然后慢慢变暗
+fn beat_pulse(visuals: &mut Visuals, frame: &Frame) -> Vec<Pixel<Rgb888>> {
+    let floor = visuals.calibration.sample_floor;
+    if visuals.beat.update(frame.energy(), floor * floor) {
+        visuals.pulse = 255;
+    } else {
+        visuals.pulse = (visuals.pulse as u16 * 3 / 4) as u8;
+    }
+
+    let mut face = Face::default();
+    face.laugh_eyes();
+    face.laugh_mouth();
+
+    let face_color = hsv2rgb(Hsv {
+        hue: 20,
+        sat: 255,
+        val: visuals.pulse,
+    });
+    let face_color = Rgb888::new(face_color.r, face_color.g, face_color.b);
+    // 整个点阵也跟着微微闪一下
+    let background = Rgb888::new(0, 0, visuals.pulse / 8);
+
+    let mut pixels = Vec::with_capacity(64);
+    for (y, row) in face.data.iter().enumerate() {
+        for x in 0..8 {
+            let c = if row & (1 << (7 - x)) > 0 {
+                face_color
+            } else {
+                background
+            };
+            pixels.push(Pixel((x, y as i32).into(), c));
+        }
+    }
+    pixels
+}
+
+fn abs(x: f32) -> f32 {
+    if x <= 0.0 {
+        0.0 - x
+    } else {
+        x
+    }
+}
@@ -0,0 +1,264 @@
+//! 统计和成就
+
+use crate::{dice::Dice, ledc::LedControl, sound::SoundEvent, ui::Ui, Ad, App, BUZZER};
+use alloc::vec::Vec;
+use cube_store::{Achievement, Stats};
+use embassy_time::Timer;
+use embedded_graphics_core::{
+    pixelcolor::{Rgb888, WebColors},
+    Pixel,
+};
+
+/// 徽章
+#[rustfmt::skip]
+fn badge(a: Achievement) -> [u8; 8] {
+    match a {
+        // 星星
+        Achievement::FirstGame => [
+            0b00011000,
+            0b00011000,
+            0b11111111,
+            0b01111110,
+            0b00111100,
+            0b01111110,
+            0b01100110,
+            0b11000011,
+        ],
+        // 短蛇
+        Achievement::Snake10 => [
+            0b00000000,
+            0b00000000,
+            0b01111000,
+            0b00001000,
+            0b00001000,
+            0b00001110,
+            0b00000000,
+            0b00000000,
+        ],
+        // 盘起来的长蛇
+        Achievement::Snake30 => [
+            0b11111111,
+            0b00000001,
+            0b01111101,
+            0b01000101,
+            0b01011101,
+            0b01000001,
+            0b01111111,
+            0b00000000,
+        ],
+        // 向下的楼梯
+        Achievement::CubeMan20 => [
+            0b11000000,
+            0b01100000,
+            0b00110000,
+            0b00011000,
+            0b00001100,
+            0b00000110,
+            0b00000011,
+            0b00000001,
+        ],
+        // 迷宫的出口
+        Achievement::Maze5 => [
+            0b11111111,
+            0b10000001,
+            0b10111101,
+            0b10100101,
+            0b10100100,
+            0b10111111,
+            0b10000000,
+            0b11111111,
+        ],
+        // 两颗骰子
+        Achievement::AllFaces => [
+            0b11110000,
+            0b10010000,
+            0b10010000,
+            0b11111111,
+            0b00011001,
+            0b00010001,
+            0b00010011,
+            0b00011111,
+        ],
+        // 太极
+        Achievement::Hexagram64 => [
+            0b00111100,
+            0b01000110,
+            0b10001111,
+            0b10001111,
+            0b10011111,
+            0b10101111,
+            0b01000110,
+            0b00111100,
+        ],
+        // 奖杯
+        Achievement::Marathon => [
+            0b11111111,
+            0b11111111,
+            0b01111110,
+            0b00111100,
+            0b00011000,
+            0b00011000,
+            0b00111100,
+            0b01111110,
+        ],
+        // 指南针
+        Achievement::Explorer => [
+            0b00111100,
+            0b01000010,
+            0b10001001,
+            0b10011001,
+            0b10011001,
+            0b10010001,
+            0b01000010,
+            0b00111100,
+        ],
+    }
+}
+
+/// 解锁达到条件的成就,返回新解锁的成就
+pub fn unlock(stats: &mut Stats) -> Vec<Achievement> {
+    let unlocked = stats.unlock();
+    (0..Achievement::ALL.len())
+        .filter(|i| unlocked & (1 << i) > 0)
+        .map(|i| Achievement::ALL[i])
+        .collect()
+}
+
+/// 用指定的颜色绘制图标
+fn draw_icon(ledc: &mut LedControl, icon: [u8; 8], color: Rgb888) {
+    let pixels = (0..64).map(|i| {
+        let (x, y) = (i % 8, i / 8);
+        let c = if icon[y] & (0x80 >> x) > 0 {
+            color
+        } else {
+            Rgb888::CSS_BLACK
+        };
+        Pixel((x as i32, y as i32).into(), c)
+    });
+    ledc.write_pixels(pixels);
+}
+
+/// 庆祝新解锁的成就
+pub async fn celebrate(ledc: &mut LedControl<'_>, achievements: &[Achievement]) {
+    for a in achievements {
+        let buzzer = unsafe { BUZZER.assume_init_mut() };
+        buzzer.victory();
+        for _ in 0..3 {
+            draw_icon(ledc, badge(*a), Rgb888::CSS_GOLD);
+            Timer::after_millis(400).await;
+            ledc.clear();
+            Timer::after_millis(200).await;
+        }
+        draw_icon(ledc, badge(*a), Rgb888::CSS_GOLD);
+        Timer::after_millis(1000).await;
+    }
+}
+
+/// 统计页面中的一页
+#[derive(Debug, Clone, Copy)]
+enum Page {
+    /// 进入界面的次数
+    Played(Ui),
+    /// 总的游玩时间,单位分钟
+    PlayTime,
+    /// 贪吃蛇最长的长度
+    LongestSnake,
+    /// 方块人最深的层数
+    DeepestCubeMan,
+    /// 走出迷宫的次数
+    MazesSolved,
+    /// 骰子掷出某一面的次数
+    Dice(u8),
+    /// 起卦的次数
+    Hexagrams,
+    /// 成就
+    Achievement(usize),
+}
+
+/// 统计
+#[derive(Debug)]
+pub struct StatsView {
+    stats: Stats,
+    pages: Vec<Page>,
+}
+
+impl StatsView {
+    pub fn new(stats: Stats) -> Self {
+        let mut pages: Vec<Page> = Ui::uis()
+            .into_iter()
+            .filter(|ui| Stats::GAMES.contains(&ui.id()))
+            .map(Page::Played)
+            .collect();
+        pages.extend([
+            Page::PlayTime,
+            Page::LongestSnake,
+            Page::DeepestCubeMan,
+            Page::MazesSolved,
+        ]);
+        pages.extend((1..=6).map(Page::Dice));
+        pages.push(Page::Hexagrams);
+        pages.extend((0..Achievement::ALL.len()).map(Page::Achievement));
+        Self { stats, pages }
+    }
+
+    /// 先显示图标,再显示数字;成就只显示徽章,没有解锁时为灰色
+    async fn draw(&self, ledc: &mut LedControl<'_>, page: Page) {
+        let s = &self.stats;
+        let (icon, num, color) = match page {
+            Page::Played(ui) => (ui.ui(), s.played[ui.id()] as u32, Rgb888::CSS_WHITE),
+            Page::PlayTime => (Ui::Timer.ui(), s.play_seconds / 60, Rgb888::CSS_CYAN),
+            Page::LongestSnake => (Ui::Snake.ui(), s.longest_snake as u32, Rgb888::CSS_GOLD),
+            Page::DeepestCubeMan => (
+                Ui::CubeMan.ui(),
+                s.deepest_cube_man as u32,
+                Rgb888::CSS_GOLD,
+            ),
+            Page::MazesSolved => (Ui::Maze.ui(), s.mazes_solved as u32, Rgb888::CSS_GOLD),
+            Page::Dice(face) => (
+                Dice::dice(face),
+                s.dice_rolls[face as usize - 1],
+                Rgb888::CSS_WHITE,
+            ),
+            Page::Hexagrams => (Ui::BaGua.ui(), s.hexagrams, Rgb888::CSS_WHITE),
+            Page::Achievement(i) => {
+                let color = if s.unlocked(i) {
+                    Rgb888::CSS_GOLD
+                } else {
+                    Rgb888::CSS_DIM_GRAY
+                };
+                draw_icon(ledc, badge(Achievement::ALL[i]), color);
+                return;
+            }
+        };
+        draw_icon(ledc, icon, color);
+        Timer::after_millis(800).await;
+        ledc.draw_number(num, &[color]).await;
+    }
+
+    /// 左右倾斜翻页,平放退出
+    pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
+        let mut idx = 0;
+        app.ad = Ad::default();
+        self.draw(&mut app.ledc, self.pages[idx]).await;
+
+        loop {
+            Timer::after_millis(300).await;
+
+            app.acc_direction();
+            match app.ad {
+                Ad::Right | Ad::Left => {
+                    let len = self.pages.len();
+                    idx = if app.ad == Ad::Right {
+                        (idx + 1) % len
+                    } else {
+                        (idx + len - 1) % len
+                    };
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuSelect) };
+                    self.draw(&mut app.ledc, self.pages[idx]).await;
+                }
+                Ad::Down => break,
+                _ => {}
+            }
+        }
+    }
+}
@@ -1,29 +1,66 @@
 #![doc = include_str!("../../rfcs/004_timer.md")]
 
-use crate::{App, CubeRng, BUZZER, RNG};
-use alloc::vec::Vec;
-use embassy_time::Timer;
+use crate::{sound::SoundEvent, Ad, App, CubeRng, BUZZER, RNG};
+use embassy_time::{Duration, Instant, Timer};
 use embedded_graphics::geometry::Point;
-use embedded_graphics_core::{
-    pixelcolor::{BinaryColor, Rgb888},
-    Pixel,
-};
+use embedded_graphics_core::{pixelcolor::BinaryColor, Pixel};
+use sand::{Gravity, Sand};
+
+/// 可选的沙漏时长,单位分钟
+const DURATIONS: [u32; 5] = [1, 3, 5, 10, 25];
+/// 沙粒的数量,填满沙漏的一半
+const GRAINS: u32 = 32;
+/// 隔板所在的行,上下两半各四行
+const DIVIDER: i32 = 4;
+/// 一粒沙子漏下之前闪烁的时长,单位毫秒
+const DROP_TIME: u64 = 300;
+
+/// 沙漏的朝向
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Orientation {
+    /// 正放,沙子从上半部分流向下半部分
+    Upright,
+    /// 倒放,沙子从下半部分流向上半部分
+    Flipped,
+    /// 侧放,暂停计时
+    Side,
+}
+
+impl Orientation {
+    fn from_accel(ax: f32, ay: f32) -> Self {
+        let ax_abs = if ax <= 0.0 { 0.0 - ax } else { ax };
+        let ay_abs = if ay <= 0.0 { 0.0 - ay } else { ay };
+        if ay_abs <= ax_abs {
+            Self::Side
+        } else if ay > 0.5 {
+            Self::Upright
+        } else if ay < -0.5 {
+            Self::Flipped
+        } else {
+            Self::Side
+        }
+    }
+}
 
 /// 沙漏
 #[derive(Debug, Clone)]
 pub struct Timers {
-    pixels: Vec<TimerPixel>,
+    sand: Sand,
+    /// 选中的时长在 DURATIONS 中的索引
+    duration_idx: usize,
+    /// 沙子流动的方向,只会是正放或倒放
+    flow: Orientation,
 }
 
 impl core::default::Default for Timers {
     fn default() -> Self {
-        let mut pixels = Vec::<TimerPixel>::with_capacity(32);
-        for y in 0..4 {
-            for x in 0..8 {
-                pixels.push(TimerPixel::new(Point::new(x, y), 0.3));
-            }
+        let mut sand = Sand::new().with_divider(DIVIDER);
+        sand.fill(0..DIVIDER);
+        Self {
+            sand,
+            duration_idx: 0,
+            flow: Orientation::Upright,
         }
-        Self { pixels }
     }
 }
 
@@ -31,91 +68,162 @@ impl Timers {
     fn init<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<T>) {
         app.ledc.clear();
         app.acc_direction();
-        app.ledc.write_pixels(self.pixels());
-
-        // Timer::after_millis(1000).await;
-        // // 闪烁三次配音效后开始
-        // (0..3).for_each(|_| {
-        //     // TODO: 音效
-        //     app.ledc.set_brightness(0x01);
-        // Timer::after_millis(50).await;
-        //     app.ledc.set_brightness(0x00);
-        // Timer::after_millis(50).await;
-        // });
-        //
-        // Timer::after_millis(1000).await;
+        app.ledc.write_bytes(self.sand.grains);
+    }
+
+    /// 沙子穿过隔板的方向,正放时向下,倒放时向上
+    fn dy(&self) -> i32 {
+        match self.flow {
+            Orientation::Flipped => -1,
+            _ => 1,
+        }
     }
 
-    fn pixels(&mut self) -> Vec<Pixel<Rgb888>> {
-        self.pixels.iter().map(|p| p.pixel).collect::<Vec<_>>()
+    /// 沙子流出的一半是否已经漏空
+    fn drained(&self) -> bool {
+        let rows = match self.flow {
+            Orientation::Flipped => DIVIDER..sand::HEIGHT,
+            _ => 0..DIVIDER,
+        };
+        self.sand.count_rows(rows) == 0
     }
 
-    /// 在某一列找
-    fn last(&self, rx: i32) -> Option<usize> {
-        let last = self
-            .pixels
-            .iter()
-            .filter(|p| p.pixel.0.x == rx)
-            .max_by_key(|p| p.pixel.0.y)?;
-        self.pixels.iter().position(|p| p == last)
+    /// 闪烁一下即将漏下的沙子
+    async fn blink<T: esp_hal::i2c::Instance>(&self, app: &mut App<'_, T>, (x, y): (i32, i32)) {
+        let mut color = BinaryColor::On;
+        for _ in 0..3 {
+            color = color.invert();
+            app.ledc.write_pixel(Pixel(Point::new(x, y), color.into()));
+            Timer::after_millis(100).await;
+            unsafe { BUZZER.assume_init_mut().play(SoundEvent::TimerPixelBlinky) };
+        }
     }
 
+    /// 倾斜选择时长,左右切换,向上确认,平放退出
+    async fn pick<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) -> bool {
+        app.ad = Ad::default();
+        // 从菜单进入时仍是向上倾斜,回正之后才能确认
+        let mut armed = false;
+        loop {
+            app.ledc
+                .draw_number(DURATIONS[self.duration_idx], &[])
+                .await;
+            Timer::after_millis(300).await;
+
+            app.acc_direction();
+            match app.ad {
+                Ad::None => armed = true,
+                Ad::Right => {
+                    self.duration_idx = (self.duration_idx + 1) % DURATIONS.len();
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuSelect) };
+                }
+                Ad::Left => {
+                    self.duration_idx = (self.duration_idx + DURATIONS.len() - 1) % DURATIONS.len();
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuSelect) };
+                }
+                Ad::Front if armed => {
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::MenuConfirm) };
+                    return true;
+                }
+                Ad::Down => return false,
+                _ => {}
+            }
+        }
+    }
+
+    /// 每粒沙子的下落间隔
+    fn interval(&self) -> Duration {
+        Duration::from_secs(DURATIONS[self.duration_idx] as u64 * 60) / GRAINS
+    }
+
+    /// 沙漏正放时从上往下流,倒放时反向流动,侧放时暂停,平放退出
     pub async fn run<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
+        if !self.pick(app).await {
+            return;
+        }
         self.init(app);
 
-        let mut rxs = vec![0, 1, 2, 3, 4, 5, 6, 7];
+        let interval = self.interval();
+        // 下一粒沙子落下的时间
+        let mut next = Instant::now() + interval;
+        // 暂停时当前这粒沙子剩余的时间
+        let mut left = interval;
+        let mut paused = false;
+        let mut finished = false;
 
         loop {
-            if self.pixels.is_empty() {
+            Timer::after_millis(100).await;
+
+            app.acc_direction();
+            if app.quit() {
                 break;
             }
 
-            // 随机一列掉下
-            let rx = unsafe {
-                CubeRng(RNG.assume_init_mut().random() as u64).random(0, rxs.len() as u32)
-            } as usize;
-            let Some(index) = self.last(rxs[rx]) else {
-                rxs.remove(rx);
-                continue;
-            };
-
-            Timer::after_millis(1000).await;
-            let mut pixel = self.pixels.remove(index);
-            pixel.blink(app).await;
-            pixel.r#move(app).await;
-        }
-    }
-}
+            let accel = app.accel();
+            let now = Instant::now();
+            // 沙子沿着重力方向下落堆积,侧放时也会滑向一侧
+            let moved = self
+                .sand
+                .step(Gravity::from_accel(0.0 - accel.x(), accel.y()));
+            if moved {
+                app.ledc.write_bytes(self.sand.grains);
+            }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-struct TimerPixel {
-    pixel: Pixel<Rgb888>,
-    speed: f32,
-}
+            match Orientation::from_accel(accel.x(), accel.y()) {
+                Orientation::Side => {
+                    if !paused {
+                        paused = true;
+                        left = remaining(next, now);
+                    }
+                    continue;
+                }
+                orientation => {
+                    if paused {
+                        paused = false;
+                        next = now + left;
+                    }
+                    if orientation != self.flow {
+                        // 翻转之后已经流过的时间变成剩余的时间
+                        next = now
+                            + interval
+                                .checked_sub(remaining(next, now))
+                                .unwrap_or(Duration::from_ticks(0));
+                        self.flow = orientation;
+                        finished = false;
+                    }
+                }
+            }
 
-impl TimerPixel {
-    fn new(pos: Point, speed: f32) -> Self {
-        Self {
-            pixel: Pixel(pos, BinaryColor::On.into()),
-            speed,
-        }
-    }
+            // 沙子还在滑落时等堆积稳定之后再漏下
+            if finished || moved || now + Duration::from_millis(DROP_TIME) < next {
+                continue;
+            }
 
-    /// 闪烁一下选中的像素,
-    async fn blink<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
-        for _ in 0..3 {
-            self.pixel.1 = BinaryColor::from(self.pixel.1).invert().into();
-            app.ledc.write_pixel(self.pixel);
-            Timer::after_millis(100).await;
-            unsafe { BUZZER.assume_init_mut().timer_pixel_blinky().await };
+            // 从随机一列开始找隔板处能漏下的沙子
+            let hint = unsafe { CubeRng(RNG.assume_init_mut().random() as u64).random(0, 8) };
+            match self.sand.neck(self.dy(), hint as i32) {
+                Some(grain) => {
+                    self.blink(app, grain).await;
+                    self.sand.release(self.dy(), hint as i32);
+                    app.ledc.write_bytes(self.sand.grains);
+                    next += interval;
+                    // 最后一粒漏下时就结束,不用再等一个间隔
+                    if self.drained() {
+                        finished = true;
+                        unsafe { BUZZER.assume_init_mut().play(SoundEvent::TimersOver) };
+                    }
+                }
+                None => {
+                    finished = true;
+                    unsafe { BUZZER.assume_init_mut().play(SoundEvent::TimersOver) };
+                }
+            }
         }
     }
+}
 
-    /// 执行像素的下落过程
-    async fn r#move<T: esp_hal::i2c::Instance>(&mut self, app: &mut App<'_, T>) {
-        self.pixel.1 = BinaryColor::On.into();
-        self.pixel.0.y += 4;
-        Timer::after_millis(500).await;
-        app.ledc.write_pixel(self.pixel);
-    }
+/// 距离某个时间还剩余的时长
+fn remaining(at: Instant, now: Instant) -> Duration {
+    at.checked_duration_since(now)
+        .unwrap_or(Duration::from_ticks(0))
 }
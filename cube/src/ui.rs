@@ -1,5 +1,5 @@
 /// 界面
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum Ui {
     /// 沙漏
     #[default]
@@ -20,12 +20,22 @@ pub enum Ui {
     Sokoban,
     /// 躲避方块
     DodgeCube,
+    /// 沙盘
+    SandBox,
+    /// 对打球
+    PlayBall,
+    /// 棋类
+    BoardGame,
+    /// 设置
+    Settings,
+    /// 统计
+    Stats,
     /// 声音
     Sound,
 }
 
 impl Ui {
-    pub fn uis() -> [Ui; 10] {
+    pub fn uis() -> [Ui; 15] {
         [
             Ui::Timer,
             Ui::MusicSpectrum,
@@ -36,10 +46,36 @@ impl Ui {
             Ui::CubeMan,
             Ui::Sokoban,
             Ui::DodgeCube,
+            Ui::SandBox,
+            Ui::PlayBall,
+            Ui::BoardGame,
+            Ui::Settings,
+            Ui::Stats,
             Ui::Sound,
         ]
     }
 
+    /// 统计中使用的编号,保存在flash中,新增界面时使用新的编号,不能修改已有的编号
+    pub fn id(&self) -> usize {
+        match self {
+            Ui::Timer => 0,
+            Ui::MusicSpectrum => 1,
+            Ui::Dice => 2,
+            Ui::Snake => 3,
+            Ui::BaGua => 4,
+            Ui::Maze => 5,
+            Ui::CubeMan => 6,
+            Ui::Sokoban => 7,
+            Ui::DodgeCube => 8,
+            Ui::SandBox => 9,
+            Ui::PlayBall => 10,
+            Ui::BoardGame => 11,
+            Ui::Settings => 12,
+            Ui::Sound => 13,
+            Ui::Stats => 14,
+        }
+    }
+
     #[rustfmt::skip]
     pub fn ui(&self) -> [u8; 8] {
         match self {
@@ -133,6 +169,56 @@ impl Ui {
                 0b00000000,
                 0b00010000,
             ],
+            Ui::SandBox => [
+                0b00000000,
+                0b00000000,
+                0b00000000,
+                0b00000000,
+                0b00011000,
+                0b00111100,
+                0b01111110,
+                0b11111111,
+            ],
+            Ui::PlayBall => [
+                0b00111100,
+                0b00000000,
+                0b00000000,
+                0b00001000,
+                0b00000000,
+                0b00000000,
+                0b00000000,
+                0b00111100,
+            ],
+            Ui::BoardGame => [
+                0b00000000,
+                0b01010100,
+                0b00101010,
+                0b01010100,
+                0b00101010,
+                0b01010100,
+                0b00101010,
+                0b00000000,
+            ],
+            Ui::Settings => [
+                0b00000000,
+                0b01000100,
+                0b11111111,
+                0b01000100,
+                0b00100010,
+                0b11111111,
+                0b00100010,
+                0b00000000,
+            ],
+            Ui::Stats => [
+                0b00000000,
+                0b00000010,
+                0b00000010,
+                0b00010010,
+                0b00010010,
+                0b01010010,
+                0b01010010,
+                0b00000000,
+            ],
             Ui::Sound => [
                 0b00000000,
                 0b00011000,
@@ -0,0 +1,188 @@
+//! 四子棋
+//!
+//! 棋盘有 8 列 7 行,点阵的第一行留给光标.棋子从上方投入某一列,落到最下面的空位,
+//! 横、竖、斜连成四个的一方获胜,下满时平局.着法为列号
+
+use crate::{decode_cells, encode_cells, Cell};
+use cube_net::turn::{Outcome, Player, Rules};
+
+/// 列数
+pub const COLS: usize = 8;
+/// 行数
+pub const ROWS: usize = 7;
+/// 连成多少个获胜
+const WIN_LEN: i32 = 4;
+
+/// 四子棋
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectFour {
+    /// 按行存储,第 0 行在最上面
+    cells: [Cell; COLS * ROWS],
+}
+
+impl Default for ConnectFour {
+    fn default() -> Self {
+        Self {
+            cells: [None; COLS * ROWS],
+        }
+    }
+}
+
+impl ConnectFour {
+    /// 第 x 列第 y 行的棋子
+    pub fn get(&self, x: usize, y: usize) -> Cell {
+        if x < COLS && y < ROWS {
+            self.cells[y * COLS + x]
+        } else {
+            None
+        }
+    }
+
+    /// 投入第 x 列的棋子会落到哪一行
+    pub fn drop_row(&self, x: usize) -> Option<usize> {
+        if x >= COLS {
+            return None;
+        }
+        (0..ROWS).rev().find(|y| self.get(x, *y).is_none())
+    }
+
+    /// 从该位置沿某个方向连续相同棋子的数量,包括自己
+    fn run(&self, x: usize, y: usize, (dx, dy): (i32, i32)) -> i32 {
+        let Some(player) = self.get(x, y) else {
+            return 0;
+        };
+        let mut n = 0;
+        let (mut cx, mut cy) = (x as i32, y as i32);
+        while (0..COLS as i32).contains(&cx)
+            && (0..ROWS as i32).contains(&cy)
+            && self.get(cx as usize, cy as usize) == Some(player)
+        {
+            n += 1;
+            cx += dx;
+            cy += dy;
+        }
+        n
+    }
+}
+
+impl Rules for ConnectFour {
+    fn is_valid(&self, _player: Player, mv: u8) -> bool {
+        self.drop_row(mv as usize).is_some()
+    }
+
+    fn apply(&mut self, player: Player, mv: u8) {
+        if let Some(y) = self.drop_row(mv as usize) {
+            self.cells[y * COLS + mv as usize] = Some(player);
+        }
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        for y in 0..ROWS {
+            for x in 0..COLS {
+                let Some(player) = self.get(x, y) else {
+                    continue;
+                };
+                // 只需要向右、向下、右下、左下四个方向找
+                if [(1, 0), (0, 1), (1, 1), (-1, 1)]
+                    .into_iter()
+                    .any(|d| self.run(x, y, d) >= WIN_LEN)
+                {
+                    return Some(Outcome::Win(player));
+                }
+            }
+        }
+        if self.cells.iter().all(|c| c.is_some()) {
+            return Some(Outcome::Draw);
+        }
+        None
+    }
+
+    fn encode(&self, buf: &mut [u8]) -> usize {
+        encode_cells(&self.cells, buf)
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        Some(Self {
+            cells: decode_cells(buf)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 从空棋盘开始双方轮流投入
+    fn play(moves: &[u8]) -> ConnectFour {
+        let mut game = ConnectFour::default();
+        let mut player = Player::First;
+        for &mv in moves {
+            assert!(game.is_valid(player, mv), "{mv}");
+            game.apply(player, mv);
+            player = game.next(player);
+        }
+        game
+    }
+
+    #[test]
+    fn pieces_fall_to_the_bottom() {
+        let game = play(&[3, 3]);
+        assert_eq!(game.get(3, ROWS - 1), Some(Player::First));
+        assert_eq!(game.get(3, ROWS - 2), Some(Player::Second));
+        assert_eq!(game.drop_row(3), Some(ROWS - 3));
+        assert_eq!(game.drop_row(0), Some(ROWS - 1));
+    }
+
+    #[test]
+    fn full_and_outside_columns_are_invalid() {
+        let game = play(&[0; ROWS]);
+        assert_eq!(game.drop_row(0), None);
+        assert!(!game.is_valid(Player::Second, 0));
+        assert!(!game.is_valid(Player::Second, COLS as u8));
+        assert!(game.is_valid(Player::Second, 1));
+        // 一列交替下满不会连成四个
+        assert_eq!(game.outcome(), None);
+    }
+
+    #[test]
+    fn wins_in_rows_columns_and_diagonals() {
+        // 横
+        let game = play(&[0, 0, 1, 1, 2, 2, 3]);
+        assert_eq!(game.outcome(), Some(Outcome::Win(Player::First)));
+        // 竖
+        let game = play(&[5, 6, 5, 6, 5, 6, 5]);
+        assert_eq!(game.outcome(), Some(Outcome::Win(Player::First)));
+        // 右上斜
+        let game = play(&[0, 1, 1, 2, 2, 3, 2, 3, 3, 7, 3]);
+        assert_eq!(game.outcome(), Some(Outcome::Win(Player::First)));
+        // 左上斜,后手获胜
+        let game = play(&[0, 7, 6, 6, 5, 5, 4, 5, 4, 4, 0, 4]);
+        assert_eq!(game.outcome(), Some(Outcome::Win(Player::Second)));
+        // 三个不算
+        assert_eq!(play(&[0, 0, 1, 1, 2, 2]).outcome(), None);
+    }
+
+    #[test]
+    fn draw_when_full() {
+        // 每两列交替,每行错开,没有连成四个的
+        let mut buf = [0u8; COLS * ROWS];
+        for y in 0..ROWS {
+            for x in 0..COLS {
+                buf[y * COLS + x] = 1 + ((x / 2 + y) % 2) as u8;
+            }
+        }
+        let game = ConnectFour::decode(&buf).unwrap();
+        assert!((0..COLS as u8).all(|mv| !game.is_valid(Player::First, mv)));
+        assert_eq!(game.outcome(), Some(Outcome::Draw));
+    }
+
+    #[test]
+    fn encode_decode() {
+        let game = play(&[3, 4, 3]);
+        let mut buf = [0u8; COLS * ROWS];
+        let len = game.encode(&mut buf);
+        assert_eq!(len, COLS * ROWS);
+        assert_eq!(ConnectFour::decode(&buf), Some(game));
+        assert_eq!(ConnectFour::decode(&buf[..len - 1]), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! 两个小方对战的棋类游戏
+//!
+//! 每种棋只实现 [`Rules`](cube_net::turn::Rules),轮流走棋、重发和同步都由 [`Session`](cube_net::turn::Session) 负责
+
+#![no_std]
+#![warn(missing_docs)]
+
+pub mod connect_four;
+pub mod reversi;
+pub mod tic_tac_toe;
+
+pub use connect_four::ConnectFour;
+pub use reversi::Reversi;
+pub use tic_tac_toe::TicTacToe;
+
+use cube_net::turn::Player;
+
+/// 棋盘上的一格,没有棋子时为 None
+pub type Cell = Option<Player>;
+
+/// 将棋盘编码,每格一个字节,0 为空,1 为先手,2 为后手
+fn encode_cells(cells: &[Cell], buf: &mut [u8]) -> usize {
+    for (b, c) in buf.iter_mut().zip(cells) {
+        *b = match c {
+            None => 0,
+            Some(Player::First) => 1,
+            Some(Player::Second) => 2,
+        };
+    }
+    cells.len()
+}
+
+/// 从编码中恢复棋盘,长度不一致或者有无效的格子时返回 None
+fn decode_cells<const N: usize>(buf: &[u8]) -> Option<[Cell; N]> {
+    if buf.len() != N {
+        return None;
+    }
+    let mut cells = [None; N];
+    for (c, b) in cells.iter_mut().zip(buf) {
+        *c = match b {
+            0 => None,
+            1 => Some(Player::First),
+            2 => Some(Player::Second),
+            _ => return None,
+        };
+    }
+    Some(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cube_net::{
+        game,
+        lobby::{Address, Peer, Transport},
+        loopback::Loopback,
+        turn::{Outcome, Rules, Session, Status},
+    };
+
+    const A: Address = [0xa; 6];
+    const B: Address = [0xb; 6];
+
+    /// 已经配对的两个小方,A 先手
+    struct Match<R: Rules> {
+        a: Session<R>,
+        ta: Loopback,
+        b: Session<R>,
+        tb: Loopback,
+        now: u64,
+    }
+
+    impl<R: Rules + PartialEq + core::fmt::Debug> Match<R> {
+        fn new(game: u8) -> Self {
+            let (mut ta, mut tb) = Loopback::pair(A, B);
+            ta.add_peer(&B);
+            tb.add_peer(&A);
+            let peer = |address, first, seq, peer_seq| Peer {
+                address,
+                first,
+                seq,
+                peer_seq,
+            };
+            Self {
+                a: Session::new(game, peer(B, true, 10, 19)),
+                ta,
+                b: Session::new(game, peer(A, false, 20, 9)),
+                tb,
+                now: 0,
+            }
+        }
+
+        /// 双方轮流推进一段时间
+        fn run(&mut self, ms: u64) -> (Status, Status) {
+            let end = self.now + ms;
+            let mut status = (Status::Playing, Status::Playing);
+            while self.now <= end {
+                status = (
+                    self.a.poll(self.now, &mut self.ta),
+                    self.b.poll(self.now, &mut self.tb),
+                );
+                self.now += 50;
+            }
+            status
+        }
+
+        /// 轮到的一方走一步,drop 为真时这一步丢失
+        fn play(&mut self, mv: u8, drop: bool) {
+            let (session, transport) = if self.a.my_turn() {
+                (&mut self.a, &mut self.ta)
+            } else {
+                (&mut self.b, &mut self.tb)
+            };
+            if drop {
+                transport.drop_next(1);
+            }
+            assert!(session.play(self.now, transport, mv), "{mv}");
+        }
+
+        fn assert_in_sync(&self) {
+            assert_eq!(self.a.rules(), self.b.rules());
+            assert_eq!(self.a.turn(), self.b.turn());
+            assert_eq!(self.a.status(), self.b.status());
+        }
+
+        /// 轮到的一方按顺序找第一个合法的着法
+        fn first_valid(&self) -> u8 {
+            let session = if self.a.my_turn() { &self.a } else { &self.b };
+            (0..=u8::MAX)
+                .find(|mv| session.rules().is_valid(session.turn(), *mv))
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn tic_tac_toe_over_loopback() {
+        let mut m = Match::<TicTacToe>::new(game::TIC_TAC_TOE);
+        for mv in [4, 0, 2, 6, 3, 5, 1, 7] {
+            m.play(mv, false);
+            m.run(100);
+            m.assert_in_sync();
+        }
+        // 已经被占的格子不能走
+        assert!(!m.a.play(m.now, &mut m.ta, 4));
+        m.play(8, false);
+        let draw = Status::Over(Outcome::Draw);
+        assert_eq!(m.run(100), (draw, draw));
+        m.assert_in_sync();
+
+        // 再来一局,后手先走
+        m.a.request_rematch(&mut m.ta);
+        m.b.request_rematch(&mut m.tb);
+        assert_eq!(m.run(100), (Status::Playing, Status::Playing));
+        assert_eq!(m.a.rules(), &TicTacToe::default());
+        assert!(m.b.my_turn());
+        for mv in [0, 3, 1, 4, 2] {
+            m.play(mv, false);
+            m.run(100);
+        }
+        let win = Status::Over(Outcome::Win(Player::Second));
+        assert_eq!(m.run(100), (win, win));
+        m.assert_in_sync();
+    }
+
+    #[test]
+    fn tic_tac_toe_retries_a_dropped_move() {
+        let mut m = Match::<TicTacToe>::new(game::TIC_TAC_TOE);
+        m.play(4, true);
+        m.run(100);
+        assert_eq!(m.b.rules(), &TicTacToe::default());
+        m.run(Session::<TicTacToe>::RETRY_INTERVAL);
+        m.assert_in_sync();
+        assert!(m.b.my_turn());
+    }
+
+    #[test]
+    fn reversi_over_loopback() {
+        let mut m = Match::<Reversi>::new(game::REVERSI);
+        // 对方没有地方可走时自己连走两步,丢掉第一步之后对方只收到第二步,步数对不上时同步
+        let mut synced = false;
+        while m.a.status() == Status::Playing {
+            let player = m.a.turn();
+            let mv = m.first_valid();
+            let mut next = m.a.rules().clone();
+            next.apply(player, mv);
+            let pass = next.outcome().is_none() && next.next(player) == player;
+            if pass && !synced {
+                synced = true;
+                m.play(mv, true);
+                m.play(m.first_valid(), false);
+                m.run(100);
+            } else {
+                m.play(mv, false);
+                m.run(100);
+            }
+            m.assert_in_sync();
+        }
+        assert!(synced, "no forced pass in this game");
+        let over = Status::Over(m.a.rules().outcome().unwrap());
+        assert_eq!(m.run(100), (over, over));
+
+        m.b.request_rematch(&mut m.tb);
+        m.a.request_rematch(&mut m.ta);
+        assert_eq!(m.run(100), (Status::Playing, Status::Playing));
+        assert_eq!(m.b.rules(), &Reversi::default());
+        assert!(m.b.my_turn());
+    }
+}
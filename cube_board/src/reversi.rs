@@ -0,0 +1,247 @@
+//! 黑白棋
+//!
+//! 8*8 的棋盘,落子必须夹住对方至少一个棋子,被夹住的棋子翻成自己的.
+//! 没有地方可走时跳过,双方都不能走时棋子多的一方获胜.着法为格子的序号 y*8+x
+
+use crate::{decode_cells, encode_cells, Cell};
+use cube_net::turn::{Outcome, Player, Rules};
+
+/// 棋盘的边长
+pub const SIZE: usize = 8;
+
+/// 八个方向
+const DIRS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+/// 黑白棋,先手执黑
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reversi {
+    cells: [Cell; SIZE * SIZE],
+}
+
+impl Default for Reversi {
+    fn default() -> Self {
+        let mut cells = [None; SIZE * SIZE];
+        cells[3 * SIZE + 3] = Some(Player::Second);
+        cells[4 * SIZE + 4] = Some(Player::Second);
+        cells[3 * SIZE + 4] = Some(Player::First);
+        cells[4 * SIZE + 3] = Some(Player::First);
+        Self { cells }
+    }
+}
+
+impl Reversi {
+    /// 第 x 列第 y 行的棋子
+    pub fn get(&self, x: i32, y: i32) -> Cell {
+        if (0..SIZE as i32).contains(&x) && (0..SIZE as i32).contains(&y) {
+            self.cells[y as usize * SIZE + x as usize]
+        } else {
+            None
+        }
+    }
+
+    /// 某一方的棋子数
+    pub fn count(&self, player: Player) -> usize {
+        self.cells.iter().filter(|c| **c == Some(player)).count()
+    }
+
+    /// 是否有地方可走
+    pub fn can_move(&self, player: Player) -> bool {
+        (0..(SIZE * SIZE) as u8).any(|mv| self.is_valid(player, mv))
+    }
+
+    /// 在 (x, y) 落子时沿某个方向能翻转的棋子数
+    fn flips(&self, player: Player, x: i32, y: i32, (dx, dy): (i32, i32)) -> i32 {
+        let mut n = 0;
+        let (mut cx, mut cy) = (x + dx, y + dy);
+        loop {
+            match self.get(cx, cy) {
+                Some(p) if p == player => return n,
+                Some(_) => n += 1,
+                None => return 0,
+            }
+            cx += dx;
+            cy += dy;
+        }
+    }
+}
+
+impl Rules for Reversi {
+    fn is_valid(&self, player: Player, mv: u8) -> bool {
+        let (x, y) = ((mv as usize % SIZE) as i32, (mv as usize / SIZE) as i32);
+        (mv as usize) < SIZE * SIZE
+            && self.get(x, y).is_none()
+            && DIRS.into_iter().any(|d| self.flips(player, x, y, d) > 0)
+    }
+
+    fn apply(&mut self, player: Player, mv: u8) {
+        let (x, y) = ((mv as usize % SIZE) as i32, (mv as usize / SIZE) as i32);
+        for (dx, dy) in DIRS {
+            for i in 1..=self.flips(player, x, y, (dx, dy)) {
+                self.cells[((y + dy * i) as usize) * SIZE + (x + dx * i) as usize] = Some(player);
+            }
+        }
+        self.cells[mv as usize] = Some(player);
+    }
+
+    /// 对方没有地方可走时自己接着走
+    fn next(&self, player: Player) -> Player {
+        if !self.can_move(player.other()) && self.can_move(player) {
+            player
+        } else {
+            player.other()
+        }
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        if self.can_move(Player::First) || self.can_move(Player::Second) {
+            return None;
+        }
+        let first = self.count(Player::First);
+        let second = self.count(Player::Second);
+        Some(match first.cmp(&second) {
+            core::cmp::Ordering::Greater => Outcome::Win(Player::First),
+            core::cmp::Ordering::Less => Outcome::Win(Player::Second),
+            core::cmp::Ordering::Equal => Outcome::Draw,
+        })
+    }
+
+    fn encode(&self, buf: &mut [u8]) -> usize {
+        encode_cells(&self.cells, buf)
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        Some(Self {
+            cells: decode_cells(buf)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按行画出的棋盘,`x` 为先手,`o` 为后手,`.` 为空
+    fn board(rows: [&str; SIZE]) -> Reversi {
+        let mut buf = [0u8; SIZE * SIZE];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.bytes().enumerate() {
+                buf[y * SIZE + x] = match c {
+                    b'x' => 1,
+                    b'o' => 2,
+                    _ => 0,
+                };
+            }
+        }
+        Reversi::decode(&buf).unwrap()
+    }
+
+    fn mv(x: usize, y: usize) -> u8 {
+        (y * SIZE + x) as u8
+    }
+
+    #[test]
+    fn opening_moves() {
+        let game = Reversi::default();
+        let moves: [u8; 4] = [mv(3, 2), mv(2, 3), mv(5, 4), mv(4, 5)];
+        for m in 0..(SIZE * SIZE) as u8 {
+            assert_eq!(game.is_valid(Player::First, m), moves.contains(&m), "{m}");
+        }
+        assert_eq!(game.count(Player::First), 2);
+        assert_eq!(game.count(Player::Second), 2);
+        assert_eq!(game.outcome(), None);
+    }
+
+    #[test]
+    fn flips_the_sandwiched_pieces() {
+        let mut game = Reversi::default();
+        game.apply(Player::First, mv(3, 2));
+        assert_eq!(game.get(3, 3), Some(Player::First));
+        assert_eq!(game.count(Player::First), 4);
+        assert_eq!(game.count(Player::Second), 1);
+        assert_eq!(game.next(Player::First), Player::Second);
+    }
+
+    #[test]
+    fn flips_in_several_directions() {
+        let mut game = board([
+            "x.x.x...", //
+            ".ooo....", //
+            "xo.ox...", //
+            ".ooo....", //
+            "x.x.x...", //
+            "........", //
+            "........", //
+            "........", //
+        ]);
+        game.apply(Player::First, mv(2, 2));
+        assert_eq!(game.count(Player::Second), 0);
+        assert_eq!(game.count(Player::First), 17);
+    }
+
+    #[test]
+    fn occupied_and_non_flipping_cells_are_invalid() {
+        let game = Reversi::default();
+        // 已经有棋子
+        assert!(!game.is_valid(Player::First, mv(3, 3)));
+        // 夹不住对方
+        assert!(!game.is_valid(Player::First, mv(0, 0)));
+        assert!(!game.is_valid(Player::First, mv(2, 2)));
+        assert!(!game.is_valid(Player::First, (SIZE * SIZE) as u8));
+    }
+
+    #[test]
+    fn forced_pass() {
+        let mut game = board([
+            "xo......", //
+            "........", //
+            "xo......", //
+            "........", //
+            "........", //
+            "........", //
+            "........", //
+            "........", //
+        ]);
+        game.apply(Player::First, mv(2, 0));
+        // 后手没有地方可走,先手接着走
+        assert!(!game.can_move(Player::Second));
+        assert!(game.can_move(Player::First));
+        assert_eq!(game.next(Player::First), Player::First);
+        assert_eq!(game.outcome(), None);
+        game.apply(Player::First, mv(2, 2));
+        assert_eq!(game.outcome(), Some(Outcome::Win(Player::First)));
+    }
+
+    #[test]
+    fn game_ends_when_nobody_can_move() {
+        let rows = |a, b| {
+            let mut rows = ["........"; SIZE];
+            rows[0] = a;
+            rows[7] = b;
+            rows
+        };
+        // 双方都不能走,棋子多的获胜
+        let game = board(rows("oo......", ".......x"));
+        assert_eq!(game.next(Player::First), Player::Second);
+        assert_eq!(game.outcome(), Some(Outcome::Win(Player::Second)));
+        let game = board(rows("o.......", ".......x"));
+        assert_eq!(game.outcome(), Some(Outcome::Draw));
+    }
+
+    #[test]
+    fn encode_decode() {
+        let game = Reversi::default();
+        let mut buf = [0u8; SIZE * SIZE];
+        assert_eq!(game.encode(&mut buf), SIZE * SIZE);
+        assert_eq!(Reversi::decode(&buf), Some(game));
+        assert_eq!(Reversi::decode(&buf[1..]), None);
+    }
+}
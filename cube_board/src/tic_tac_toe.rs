@@ -0,0 +1,145 @@
+//! 井字棋
+//!
+//! 3*3 的棋盘,横、竖、斜连成三个的一方获胜,下满时平局.着法为格子的序号 y*3+x
+
+use crate::{decode_cells, encode_cells, Cell};
+use cube_net::turn::{Outcome, Player, Rules};
+
+/// 棋盘的边长
+pub const SIZE: usize = 3;
+
+/// 所有能连成一线的三个格子
+const LINES: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+/// 井字棋
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TicTacToe {
+    cells: [Cell; SIZE * SIZE],
+}
+
+impl TicTacToe {
+    /// 第 x 列第 y 行的棋子
+    pub fn get(&self, x: usize, y: usize) -> Cell {
+        if x < SIZE && y < SIZE {
+            self.cells[y * SIZE + x]
+        } else {
+            None
+        }
+    }
+
+    /// 获胜的一条线
+    pub fn line(&self) -> Option<[usize; 3]> {
+        LINES.into_iter().find(|[a, b, c]| {
+            self.cells[*a].is_some()
+                && self.cells[*a] == self.cells[*b]
+                && self.cells[*a] == self.cells[*c]
+        })
+    }
+}
+
+impl Rules for TicTacToe {
+    fn is_valid(&self, _player: Player, mv: u8) -> bool {
+        self.cells.get(mv as usize).is_some_and(|c| c.is_none())
+    }
+
+    fn apply(&mut self, player: Player, mv: u8) {
+        self.cells[mv as usize] = Some(player);
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        if let Some([a, _, _]) = self.line() {
+            return self.cells[a].map(Outcome::Win);
+        }
+        if self.cells.iter().all(|c| c.is_some()) {
+            return Some(Outcome::Draw);
+        }
+        None
+    }
+
+    fn encode(&self, buf: &mut [u8]) -> usize {
+        encode_cells(&self.cells, buf)
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        Some(Self {
+            cells: decode_cells(buf)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 从空棋盘开始双方轮流走
+    fn play(moves: &[u8]) -> TicTacToe {
+        let mut game = TicTacToe::default();
+        let mut player = Player::First;
+        for &mv in moves {
+            assert!(game.is_valid(player, mv), "{mv}");
+            game.apply(player, mv);
+            player = game.next(player);
+        }
+        game
+    }
+
+    #[test]
+    fn wins_in_rows_columns_and_diagonals() {
+        for (moves, line) in [
+            (&[3, 0, 4, 1, 5][..], [3, 4, 5]),
+            (&[1, 0, 4, 2, 7], [1, 4, 7]),
+            (&[0, 1, 4, 2, 8], [0, 4, 8]),
+            (&[2, 0, 4, 1, 6], [2, 4, 6]),
+        ] {
+            let game = play(moves);
+            assert_eq!(game.line(), Some(line));
+            assert_eq!(game.outcome(), Some(Outcome::Win(Player::First)));
+        }
+        // 后手也能赢
+        let game = play(&[0, 6, 1, 7, 5, 8]);
+        assert_eq!(game.outcome(), Some(Outcome::Win(Player::Second)));
+    }
+
+    #[test]
+    fn draw_when_full() {
+        let game = play(&[0, 1, 2, 4, 3, 5, 7, 6, 8]);
+        assert_eq!(game.line(), None);
+        assert_eq!(game.outcome(), Some(Outcome::Draw));
+    }
+
+    #[test]
+    fn unfinished_game() {
+        assert_eq!(TicTacToe::default().outcome(), None);
+        assert_eq!(play(&[4, 0]).outcome(), None);
+    }
+
+    #[test]
+    fn occupied_and_outside_cells_are_invalid() {
+        let game = play(&[4]);
+        assert!(!game.is_valid(Player::Second, 4));
+        assert!(!game.is_valid(Player::Second, 9));
+        assert!(game.is_valid(Player::Second, 0));
+        assert_eq!(game.get(1, 1), Some(Player::First));
+        assert_eq!(game.get(3, 0), None);
+    }
+
+    #[test]
+    fn encode_decode() {
+        let game = play(&[4, 0, 8]);
+        let mut buf = [0u8; 16];
+        let len = game.encode(&mut buf);
+        assert_eq!(buf[..len], [2, 0, 0, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(TicTacToe::decode(&buf[..len]), Some(game));
+        assert_eq!(TicTacToe::decode(&buf[..len - 1]), None);
+        assert_eq!(TicTacToe::decode(&[3; 9]), None);
+    }
+}
@@ -0,0 +1,596 @@
+//! 音频信号处理
+//!
+//! 麦克风采样的一帧数据经过去直流、加窗、FFT 之后,按对数间隔分成 8 个频段,再映射成点阵上柱子的高度
+
+#![no_std]
+#![warn(missing_docs)]
+
+use libm::{cosf, log10f, sqrtf};
+
+/// 一帧的采样点数
+pub const SAMPLES: usize = 128;
+/// 采样率,单位 Hz
+pub const SAMPLE_RATE: u32 = 8000;
+/// 频段数量,对应点阵的 8 列
+pub const BANDS: usize = 8;
+/// 柱子的最大高度,对应点阵的 8 行
+pub const HEIGHT: u8 = 8;
+
+/// 每个频段的起止 FFT 频点,按 64^(i/8) 对数间隔,每个频点宽 SAMPLE_RATE / SAMPLES = 62.5Hz
+///
+/// 第 0 个频点是直流分量,不参与计算
+const BAND_EDGES: [usize; BANDS + 1] = [1, 2, 3, 5, 8, 13, 23, 38, 64];
+
+/// 映射函数,将 x 从 [in_min, in_max] 线性映射到 [out_min, out_max]
+pub fn map_range(x: f32, in_min: f32, in_max: f32, out_min: f32, out_max: f32) -> f32 {
+    (x - in_min) * (out_max - out_min) / (in_max - in_min) + out_min
+}
+
+/// 去掉直流分量,麦克风的输出偏置在 ADC 量程的中间
+pub fn remove_dc(samples: &mut [f32; SAMPLES]) {
+    let mean = samples.iter().sum::<f32>() / SAMPLES as f32;
+    samples.iter_mut().for_each(|s| *s -= mean);
+}
+
+/// 汉宁窗,减少一帧首尾不连续造成的频谱泄漏
+pub fn hann(samples: &mut [f32; SAMPLES]) {
+    let n = (SAMPLES - 1) as f32;
+    for (i, s) in samples.iter_mut().enumerate() {
+        *s *= 0.5 - 0.5 * cosf(2.0 * core::f32::consts::PI * i as f32 / n);
+    }
+}
+
+/// FFT 之后每个频点的幅值,会覆盖输入的数据
+pub fn magnitudes(samples: &mut [f32; SAMPLES]) -> [f32; SAMPLES / 2] {
+    let spectrum = microfft::real::rfft_128(samples);
+    // 第 0 个频点的虚部存放的是奈奎斯特频率的实部
+    spectrum[0].im = 0.0;
+    let mut mags = [0.0; SAMPLES / 2];
+    for (m, c) in mags.iter_mut().zip(spectrum.iter()) {
+        *m = sqrtf(c.norm_sqr());
+    }
+    mags
+}
+
+/// 按对数间隔将频点分成 8 个频段,每个频段取最大的幅值
+pub fn bands(mags: &[f32; SAMPLES / 2]) -> [f32; BANDS] {
+    let mut bands = [0.0; BANDS];
+    for (i, b) in bands.iter_mut().enumerate() {
+        *b = mags[BAND_EDGES[i]..BAND_EDGES[i + 1]]
+            .iter()
+            .fold(0.0, |max, m| if *m > max { *m } else { max });
+    }
+    bands
+}
+
+/// 分析一帧 ADC 采样,得到 8 个频段的幅值
+pub fn analyze(frame: &[u16; SAMPLES]) -> [f32; BANDS] {
+    Frame::new(frame).bands
+}
+
+/// 将频段的幅值按分贝映射成柱子的高度,低于 floor 为 0,高于 ceil 为最大高度
+pub fn heights(bands: &[f32; BANDS], floor: f32, ceil: f32) -> [u8; BANDS] {
+    let mut heights = [0; BANDS];
+    for (h, b) in heights.iter_mut().zip(bands.iter()) {
+        *h = level(*b, floor, ceil);
+    }
+    heights
+}
+
+/// 将幅值按分贝映射成 0~HEIGHT 的等级
+pub fn level(x: f32, floor: f32, ceil: f32) -> u8 {
+    let v = map_range(db(x), db(floor), db(ceil), 0.0, HEIGHT as f32);
+    if v <= 0.0 {
+        0
+    } else if v >= HEIGHT as f32 {
+        HEIGHT
+    } else {
+        (v + 0.5) as u8
+    }
+}
+
+/// 幅值换算成分贝,避免 0 取对数
+fn db(x: f32) -> f32 {
+    20.0 * log10f(if x < 1e-3 { 1e-3 } else { x })
+}
+
+/// 一帧音频,各种可视化共用
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    /// 去掉直流分量之后的采样
+    pub samples: [f32; SAMPLES],
+    /// 8 个频段的幅值
+    pub bands: [f32; BANDS],
+    /// 均方根
+    pub rms: f32,
+    /// 绝对值最大的采样
+    pub peak: f32,
+}
+
+impl Frame {
+    /// 分析一帧 ADC 采样
+    pub fn new(frame: &[u16; SAMPLES]) -> Self {
+        let mut samples = [0.0; SAMPLES];
+        for (s, v) in samples.iter_mut().zip(frame.iter()) {
+            *s = *v as f32;
+        }
+        remove_dc(&mut samples);
+
+        let mut energy = 0.0;
+        let mut peak = 0.0;
+        for s in samples.iter() {
+            energy += s * s;
+            let abs = if *s < 0.0 { 0.0 - s } else { *s };
+            if abs > peak {
+                peak = abs;
+            }
+        }
+
+        let mut windowed = samples;
+        hann(&mut windowed);
+        Self {
+            samples,
+            bands: bands(&magnitudes(&mut windowed)),
+            rms: sqrtf(energy / SAMPLES as f32),
+            peak,
+        }
+    }
+
+    /// 幅值最大的频段
+    pub fn loudest(&self) -> f32 {
+        self.bands
+            .iter()
+            .fold(0.0, |max, b| if *b > max { *b } else { max })
+    }
+
+    /// 一帧的能量
+    pub fn energy(&self) -> f32 {
+        self.rms * self.rms
+    }
+}
+
+/// 噪声校准的结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    /// 频段幅值的噪声
+    pub band_floor: f32,
+    /// 采样幅值的噪声
+    pub sample_floor: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            band_floor: 300.0,
+            sample_floor: 8.0,
+        }
+    }
+}
+
+impl Calibration {
+    /// 存储时占用的字节数
+    pub const SIZE: usize = 8;
+
+    /// 从 flash 中读取,没有校准过时 flash 中的数据无效,返回 None
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Option<Self> {
+        let band_floor = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let sample_floor = f32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let valid = |x: f32| x.is_finite() && x > 0.0;
+        if valid(band_floor) && valid(sample_floor) {
+            Some(Self {
+                band_floor,
+                sample_floor,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// 写入 flash 的字节
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..4].copy_from_slice(&self.band_floor.to_le_bytes());
+        bytes[4..].copy_from_slice(&self.sample_floor.to_le_bytes());
+        bytes
+    }
+}
+
+/// 噪声校准,保持安静时采集若干帧,取平均值再留一些余量作为噪声
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Calibrator {
+    frames: u32,
+    band_sum: f32,
+    sample_sum: f32,
+}
+
+impl Calibrator {
+    /// 噪声的余量
+    const MARGIN: f32 = 1.5;
+
+    /// 加入一帧
+    pub fn add(&mut self, frame: &Frame) {
+        self.frames += 1;
+        self.band_sum += frame.loudest();
+        self.sample_sum += frame.rms;
+    }
+
+    /// 已经采集的帧数
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// 校准的结果,没有采集过时使用默认值
+    pub fn finish(&self) -> Calibration {
+        if self.frames == 0 {
+            return Calibration::default();
+        }
+        let default = Calibration::default();
+        let mean = |sum: f32, min: f32| {
+            let v = sum / self.frames as f32 * Self::MARGIN;
+            if v < min {
+                min
+            } else {
+                v
+            }
+        };
+        Calibration {
+            // 完全安静时也留一个最小值,避免把细微的噪声放大
+            band_floor: mean(self.band_sum, default.band_floor / 4.0),
+            sample_floor: mean(self.sample_sum, default.sample_floor / 4.0),
+        }
+    }
+}
+
+/// 自动增益控制,跟踪信号的包络,声音变大时快速跟上,变小时慢慢回落
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Agc {
+    /// 信号的包络
+    envelope: f32,
+}
+
+impl Agc {
+    /// 包络上升的系数
+    const ATTACK: f32 = 0.5;
+    /// 包络下降的系数,约 2s 回落到一半
+    const RELEASE: f32 = 0.005;
+    /// 满量程至少是噪声的倍数,安静时不会把噪声放大到满屏
+    const MIN_RANGE: f32 = 8.0;
+
+    /// 输入新一帧的最大幅值,返回满量程对应的幅值
+    pub fn update(&mut self, x: f32, floor: f32) -> f32 {
+        let k = if x > self.envelope {
+            Self::ATTACK
+        } else {
+            Self::RELEASE
+        };
+        self.envelope += (x - self.envelope) * k;
+        let min = floor * Self::MIN_RANGE;
+        if self.envelope < min {
+            min
+        } else {
+            self.envelope
+        }
+    }
+}
+
+/// 节拍检测,能量突然增加时认为是一个节拍
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeatDetector {
+    /// 最近若干帧能量的增量
+    flux: [f32; Self::HISTORY],
+    idx: usize,
+    /// 上一帧的能量
+    last: f32,
+    /// 距离上一个节拍还要冷却的帧数
+    cooldown: u8,
+}
+
+impl Default for BeatDetector {
+    fn default() -> Self {
+        Self {
+            flux: [0.0; Self::HISTORY],
+            idx: 0,
+            last: 0.0,
+            cooldown: 0,
+        }
+    }
+}
+
+impl BeatDetector {
+    /// 参与计算平均增量的帧数,8kHz 采样时约 0.5s
+    const HISTORY: usize = 32;
+    /// 增量超过平均增量的倍数时认为是节拍
+    const SENSITIVITY: f32 = 2.5;
+    /// 两个节拍之间至少间隔的帧数
+    const COOLDOWN: u8 = 8;
+
+    /// 输入新一帧的能量,返回是否是一个节拍
+    ///
+    /// min_energy 以下的能量视为安静,不会触发节拍
+    pub fn update(&mut self, energy: f32, min_energy: f32) -> bool {
+        let flux = if energy > self.last {
+            energy - self.last
+        } else {
+            0.0
+        };
+        self.last = energy;
+
+        let mean = self.flux.iter().sum::<f32>() / Self::HISTORY as f32;
+        self.flux[self.idx] = flux;
+        self.idx = (self.idx + 1) % Self::HISTORY;
+
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+            return false;
+        }
+        let beat = energy > min_energy && flux > mean * Self::SENSITIVITY;
+        if beat {
+            self.cooldown = Self::COOLDOWN;
+        }
+        beat
+    }
+}
+
+/// 峰值点,柱子下降时峰值点停留一会儿再慢慢下落
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Peaks {
+    /// 每个频段峰值点的高度
+    pub heights: [u8; BANDS],
+    /// 峰值点还要停留的帧数
+    hold: [u8; BANDS],
+}
+
+impl Peaks {
+    /// 峰值点停留的帧数
+    const HOLD: u8 = 6;
+
+    /// 根据新一帧柱子的高度更新峰值点
+    pub fn update(&mut self, heights: &[u8; BANDS]) {
+        for i in 0..BANDS {
+            if heights[i] >= self.heights[i] {
+                self.heights[i] = heights[i];
+                self.hold[i] = Self::HOLD;
+            } else if self.hold[i] > 0 {
+                self.hold[i] -= 1;
+            } else {
+                self.heights[i] -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libm::sinf;
+
+    /// 直流偏置在 ADC 量程中间
+    const BIAS: f32 = 2048.0;
+
+    /// 第 bin 个频点中心频率的正弦波
+    fn sine(bin: usize, amplitude: f32) -> [u16; SAMPLES] {
+        let mut frame = [0; SAMPLES];
+        for (i, s) in frame.iter_mut().enumerate() {
+            let phase = 2.0 * core::f32::consts::PI * (bin * i) as f32 / SAMPLES as f32;
+            *s = (BIAS + amplitude * sinf(phase) + 0.5) as u16;
+        }
+        frame
+    }
+
+    /// 频点所在的频段
+    fn band_of(bin: usize) -> Option<usize> {
+        (0..BANDS).find(|&b| (BAND_EDGES[b]..BAND_EDGES[b + 1]).contains(&bin))
+    }
+
+    #[test]
+    fn band_edges_cover_the_spectrum() {
+        assert_eq!(BAND_EDGES[0], 1);
+        assert_eq!(BAND_EDGES[BANDS], SAMPLES / 2);
+        assert!(BAND_EDGES.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn sine_lands_in_its_band() {
+        for bin in 1..SAMPLES / 2 {
+            let bands = analyze(&sine(bin, 1000.0));
+            let expected = band_of(bin).unwrap();
+            let loudest = (0..BANDS)
+                .max_by(|&a, &b| bands[a].total_cmp(&bands[b]))
+                .unwrap();
+            assert_eq!(loudest, expected, "bin {bin}: {bands:?}");
+            // 汉宁窗的主瓣会漏到相邻的频点,相邻频点所在的频段不超过一半
+            let neighbours = [band_of(bin - 1), band_of(bin + 1)];
+            for (b, v) in bands.iter().enumerate() {
+                if b == expected {
+                    continue;
+                }
+                let limit = if neighbours.contains(&Some(b)) {
+                    0.55
+                } else {
+                    0.02
+                };
+                assert!(
+                    *v < bands[expected] * limit,
+                    "bin {bin} band {b}: {bands:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn louder_sine_is_louder() {
+        let quiet = analyze(&sine(10, 100.0));
+        let loud = analyze(&sine(10, 1000.0));
+        let band = band_of(10).unwrap();
+        let ratio = loud[band] / quiet[band];
+        assert!((9.5..10.5).contains(&ratio), "{ratio}");
+    }
+
+    #[test]
+    fn silence_is_flat() {
+        let frame = Frame::new(&[2048; SAMPLES]);
+        assert!(frame.bands.iter().all(|b| *b < 1e-3));
+        assert_eq!(frame.rms, 0.0);
+        assert_eq!(frame.peak, 0.0);
+        assert_eq!(heights(&frame.bands, 300.0, 30_000.0), [0; BANDS]);
+    }
+
+    #[test]
+    fn rms_and_peak_of_a_sine() {
+        let frame = Frame::new(&sine(8, 1000.0));
+        let rms = 1000.0 / sqrtf(2.0);
+        assert!((frame.rms - rms).abs() < 5.0, "{}", frame.rms);
+        assert!((frame.peak - 1000.0).abs() < 2.0, "{}", frame.peak);
+        assert_eq!(frame.energy(), frame.rms * frame.rms);
+        assert_eq!(frame.loudest(), frame.bands[band_of(8).unwrap()]);
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!(level(0.0, 10.0, 1000.0), 0);
+        assert_eq!(level(10.0, 10.0, 1000.0), 0);
+        assert_eq!(level(100.0, 10.0, 1000.0), HEIGHT / 2);
+        assert_eq!(level(1000.0, 10.0, 1000.0), HEIGHT);
+        assert_eq!(level(1e6, 10.0, 1000.0), HEIGHT);
+    }
+
+    #[test]
+    fn calibration_bytes() {
+        let calibration = Calibration {
+            band_floor: 123.5,
+            sample_floor: 4.25,
+        };
+        let bytes = calibration.to_bytes();
+        assert_eq!(Calibration::from_bytes(&bytes), Some(calibration));
+        // 没有校准过的 flash
+        assert_eq!(Calibration::from_bytes(&[0xff; Calibration::SIZE]), None);
+        assert_eq!(Calibration::from_bytes(&[0; Calibration::SIZE]), None);
+    }
+
+    #[test]
+    fn agc_attacks_fast_and_releases_slowly() {
+        let mut agc = Agc::default();
+        assert_eq!(agc.update(1000.0, 1.0), 500.0);
+        assert_eq!(agc.update(1000.0, 1.0), 750.0);
+        for _ in 0..20 {
+            agc.update(1000.0, 1.0);
+        }
+        let full = agc.update(1000.0, 1.0);
+        assert!(full > 999.0, "{full}");
+
+        // 安静之后约 2s 回落到一半,每帧 16ms
+        let mut range = full;
+        for _ in 0..100 {
+            range = agc.update(0.0, 1.0);
+        }
+        assert!(range > full / 2.0, "{range}");
+        for _ in 0..40 {
+            range = agc.update(0.0, 1.0);
+        }
+        assert!(range < full / 2.0, "{range}");
+    }
+
+    #[test]
+    fn agc_keeps_a_minimum_range() {
+        let mut agc = Agc::default();
+        assert_eq!(agc.update(20.0, 10.0), 80.0);
+        for _ in 0..100 {
+            assert_eq!(agc.update(0.0, 10.0), 80.0);
+        }
+        // 包络还留着一点之前的信号
+        let range = agc.update(2000.0, 10.0);
+        assert!((1000.0..1010.0).contains(&range), "{range}");
+    }
+
+    #[test]
+    fn calibrator_without_frames_uses_the_default() {
+        let calibrator = Calibrator::default();
+        assert_eq!(calibrator.frames(), 0);
+        assert_eq!(calibrator.finish(), Calibration::default());
+    }
+
+    #[test]
+    fn calibrator_adds_a_margin_to_the_noise() {
+        let mut calibrator = Calibrator::default();
+        let quiet = Frame::new(&sine(10, 200.0));
+        let noisy = Frame::new(&sine(10, 400.0));
+        for _ in 0..5 {
+            calibrator.add(&quiet);
+            calibrator.add(&noisy);
+        }
+        assert_eq!(calibrator.frames(), 10);
+
+        let calibration = calibrator.finish();
+        let band = (quiet.loudest() + noisy.loudest()) / 2.0 * 1.5;
+        let sample = (quiet.rms + noisy.rms) / 2.0 * 1.5;
+        assert!(
+            (calibration.band_floor - band).abs() < 1.0,
+            "{calibration:?}"
+        );
+        assert!(
+            (calibration.sample_floor - sample).abs() < 0.1,
+            "{calibration:?}"
+        );
+    }
+
+    #[test]
+    fn calibrator_keeps_a_minimum_floor() {
+        let mut calibrator = Calibrator::default();
+        for _ in 0..10 {
+            calibrator.add(&Frame::new(&[2048; SAMPLES]));
+        }
+        let default = Calibration::default();
+        assert_eq!(
+            calibrator.finish(),
+            Calibration {
+                band_floor: default.band_floor / 4.0,
+                sample_floor: default.sample_floor / 4.0,
+            }
+        );
+    }
+
+    #[test]
+    fn beats_on_a_sudden_onset() {
+        let quiet = Frame::new(&sine(4, 50.0)).energy();
+        let loud = Frame::new(&sine(4, 1000.0)).energy();
+        let min = quiet * 2.0;
+        let mut beats = BeatDetector::default();
+
+        // 平稳的声音没有节拍
+        for _ in 0..40 {
+            assert!(!beats.update(quiet, min));
+        }
+        assert!(beats.update(loud, min));
+
+        // 冷却期间不会再触发
+        for i in 0..8 {
+            let energy = if i % 2 == 0 { quiet } else { loud };
+            assert!(!beats.update(energy, min), "frame {i}");
+        }
+        assert!(!beats.update(quiet, min));
+        assert!(beats.update(loud, min));
+    }
+
+    #[test]
+    fn no_beats_below_the_minimum_energy() {
+        let quiet = Frame::new(&sine(4, 10.0)).energy();
+        let louder = Frame::new(&sine(4, 40.0)).energy();
+        let mut beats = BeatDetector::default();
+        for i in 0..100 {
+            let energy = if i % 20 == 19 { louder } else { quiet };
+            assert!(!beats.update(energy, louder * 2.0), "frame {i}");
+        }
+    }
+
+    #[test]
+    fn steady_beats_raise_the_threshold() {
+        let quiet = Frame::new(&sine(4, 50.0)).energy();
+        let loud = Frame::new(&sine(4, 1000.0)).energy();
+        let mut beats = BeatDetector::default();
+        // 每隔一帧就有一次同样的增量,平均增量变高之后不再算节拍
+        let detected = (0..200)
+            .filter(|i| beats.update(if i % 2 == 0 { quiet } else { loud }, 0.0))
+            .count();
+        assert!((1..10).contains(&detected), "{detected}");
+    }
+}
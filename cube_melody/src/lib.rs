@@ -0,0 +1,424 @@
+//! 旋律
+//!
+//! 音符由音名、八度和时值组成,旋律可以从 RTTTL(诺基亚铃声)格式解析:
+//!
+//! ```text
+//! 名称:d=4,o=5,b=120:8c,8e,g,2c6,p,8g#.4
+//! ```
+//!
+//! 第二段为默认的时值、八度和每分钟的拍数,第三段为逗号分隔的音符,每个音符的格式为
+//! `[时值]音名[#][.][八度][.]`,音名为 `c d e f g a b h p`,其中 `h` 同 `b`,`p` 为休止符
+
+#![no_std]
+#![warn(missing_docs)]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+/// 八度的范围
+pub const OCTAVES: core::ops::RangeInclusive<u8> = 3..=8;
+
+/// 第 8 个八度的频率,单位Hz,其他八度依次减半
+const OCTAVE_8: [u32; 12] = [
+    4186, 4435, 4699, 4978, 5274, 5588, 5920, 6272, 6645, 7040, 7459, 7902,
+];
+
+/// 音名,一个八度里的 12 个半音
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pitch {
+    /// do
+    C,
+    /// 升 do
+    Cs,
+    /// re
+    D,
+    /// 升 re
+    Ds,
+    /// mi
+    E,
+    /// fa
+    F,
+    /// 升 fa
+    Fs,
+    /// sol
+    G,
+    /// 升 sol
+    Gs,
+    /// la
+    A,
+    /// 升 la
+    As,
+    /// si
+    B,
+}
+
+impl Pitch {
+    /// 由音名和是否升半音得到,e 和 b 没有升半音
+    fn new(name: u8, sharp: bool) -> Option<Self> {
+        Some(match (name, sharp) {
+            (b'c', false) => Pitch::C,
+            (b'c', true) => Pitch::Cs,
+            (b'd', false) => Pitch::D,
+            (b'd', true) => Pitch::Ds,
+            (b'e', false) => Pitch::E,
+            (b'f', false) => Pitch::F,
+            (b'f', true) => Pitch::Fs,
+            (b'g', false) => Pitch::G,
+            (b'g', true) => Pitch::Gs,
+            (b'a', false) => Pitch::A,
+            (b'a', true) => Pitch::As,
+            (b'b' | b'h', false) => Pitch::B,
+            _ => return None,
+        })
+    }
+}
+
+/// 时值,几分音符,附点时延长一半
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Duration {
+    /// 1、2、4、8、16、32 分音符
+    pub division: u8,
+    /// 是否附点
+    pub dotted: bool,
+}
+
+impl Duration {
+    /// 时长,单位毫秒,bpm 为每分钟四分音符的个数
+    pub fn millis(&self, bpm: u16) -> u32 {
+        let ms = 240_000 / (bpm.max(1) as u32 * self.division as u32);
+        if self.dotted {
+            ms * 3 / 2
+        } else {
+            ms
+        }
+    }
+}
+
+/// 音符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    /// 音名,休止符为 None
+    pub pitch: Option<Pitch>,
+    /// 八度,中央 C 在第 4 个八度
+    pub octave: u8,
+    /// 时值
+    pub duration: Duration,
+}
+
+impl Note {
+    /// 频率,单位Hz,休止符为 0
+    pub fn frequency(&self) -> u32 {
+        let Some(pitch) = self.pitch else {
+            return 0;
+        };
+        let shift = 8 - self.octave.clamp(*OCTAVES.start(), *OCTAVES.end());
+        // 四舍五入
+        (OCTAVE_8[pitch as usize] + (1 << shift >> 1)) >> shift
+    }
+}
+
+/// 旋律
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Melody {
+    /// 名称
+    pub name: String,
+    /// 每分钟四分音符的个数
+    pub bpm: u16,
+    /// 音符
+    pub notes: Vec<Note>,
+}
+
+impl Melody {
+    /// 依次每个音符的频率和时长,频率为 0 时为休止
+    pub fn tones(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.notes
+            .iter()
+            .map(|n| (n.frequency(), n.duration.millis(self.bpm)))
+    }
+
+    /// 总时长,单位毫秒
+    pub fn millis(&self) -> u32 {
+        self.tones().map(|(_, ms)| ms).sum()
+    }
+}
+
+/// 解析的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// 没有用冒号分成三段
+    Sections,
+    /// 无效的默认值
+    Default,
+    /// 第几个音符无效
+    Note(usize),
+}
+
+/// 解析 RTTTL 格式的旋律
+pub fn parse(rtttl: &str) -> Result<Melody, Error> {
+    let mut sections = rtttl.splitn(3, ':');
+    let (Some(name), Some(defaults), Some(notes)) =
+        (sections.next(), sections.next(), sections.next())
+    else {
+        return Err(Error::Sections);
+    };
+
+    // 没有指定时的默认值
+    let mut duration = 4;
+    let mut octave = 6;
+    let mut bpm = 63;
+    for item in defaults.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (key, value) = item.split_once('=').ok_or(Error::Default)?;
+        let value: u16 = value.trim().parse().map_err(|_| Error::Default)?;
+        match key.trim() {
+            "d" if is_division(value) => duration = value as u8,
+            "o" if OCTAVES.contains(&value.try_into().unwrap_or(0)) => octave = value as u8,
+            "b" if value > 0 => bpm = value,
+            _ => return Err(Error::Default),
+        }
+    }
+
+    let notes = notes
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .enumerate()
+        .map(|(i, s)| parse_note(s, duration, octave).ok_or(Error::Note(i)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Melody {
+        name: name.trim().into(),
+        bpm,
+        notes,
+    })
+}
+
+/// 是否为有效的几分音符
+fn is_division(v: u16) -> bool {
+    matches!(v, 1 | 2 | 4 | 8 | 16 | 32)
+}
+
+/// 解析一个音符,格式为 `[时值]音名[#][.][八度][.]`
+fn parse_note(s: &str, default_duration: u8, default_octave: u8) -> Option<Note> {
+    let s = s.as_bytes();
+    let mut i = 0;
+    let number = |i: &mut usize| {
+        let start = *i;
+        while *i < s.len() && s[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        // 没有数字时为 None,太大的数按无效的处理
+        (*i > start).then(|| {
+            core::str::from_utf8(&s[start..*i])
+                .ok()
+                .and_then(|n| n.parse::<u16>().ok())
+                .unwrap_or(u16::MAX)
+        })
+    };
+
+    let division = match number(&mut i) {
+        Some(d) if is_division(d) => d as u8,
+        Some(_) => return None,
+        None => default_duration,
+    };
+
+    let name = s.get(i)?.to_ascii_lowercase();
+    i += 1;
+    let sharp = s.get(i) == Some(&b'#');
+    if sharp {
+        i += 1;
+    }
+    // 附点可以在八度的前面或者后面
+    let mut dotted = false;
+    if s.get(i) == Some(&b'.') {
+        dotted = true;
+        i += 1;
+    }
+    let octave = match number(&mut i) {
+        Some(o) if o <= 8 && OCTAVES.contains(&(o as u8)) => o as u8,
+        Some(_) => return None,
+        None => default_octave,
+    };
+    if !dotted && s.get(i) == Some(&b'.') {
+        dotted = true;
+        i += 1;
+    }
+    if i != s.len() {
+        return None;
+    }
+
+    let pitch = if name == b'p' {
+        None
+    } else {
+        Some(Pitch::new(name, sharp)?)
+    };
+    Some(Note {
+        pitch,
+        octave,
+        duration: Duration { division, dotted },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(pitch: Option<Pitch>, octave: u8, division: u8, dotted: bool) -> Note {
+        Note {
+            pitch,
+            octave,
+            duration: Duration { division, dotted },
+        }
+    }
+
+    #[test]
+    fn defaults_without_header() {
+        let melody = parse("empty::c").unwrap();
+        assert_eq!(melody.name, "empty");
+        assert_eq!(melody.bpm, 63);
+        assert_eq!(melody.notes, [note(Some(Pitch::C), 6, 4, false)]);
+    }
+
+    #[test]
+    fn defaults_from_header() {
+        let melody = parse(" tune : d=8, o=5, b=120 : c, e ,g").unwrap();
+        assert_eq!(melody.name, "tune");
+        assert_eq!(melody.bpm, 120);
+        assert_eq!(
+            melody.notes,
+            [
+                note(Some(Pitch::C), 5, 8, false),
+                note(Some(Pitch::E), 5, 8, false),
+                note(Some(Pitch::G), 5, 8, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn overrides() {
+        let melody = parse("t:d=4,o=5,b=100:16a#7,2c,f#,32d4").unwrap();
+        assert_eq!(
+            melody.notes,
+            [
+                note(Some(Pitch::As), 7, 16, false),
+                note(Some(Pitch::C), 5, 2, false),
+                note(Some(Pitch::Fs), 5, 4, false),
+                note(Some(Pitch::D), 4, 32, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn dotted_notes() {
+        // 附点在八度的前面或者后面都可以
+        let melody = parse("t:d=4,o=5,b=100:c.,8e.6,g6.,8g#.4").unwrap();
+        assert_eq!(
+            melody.notes,
+            [
+                note(Some(Pitch::C), 5, 4, true),
+                note(Some(Pitch::E), 6, 8, true),
+                note(Some(Pitch::G), 6, 4, true),
+                note(Some(Pitch::Gs), 4, 8, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn pauses() {
+        let melody = parse("t:d=8,o=5,b=120:p,2p,p.").unwrap();
+        assert_eq!(
+            melody.notes,
+            [
+                note(None, 5, 8, false),
+                note(None, 5, 2, false),
+                note(None, 5, 8, true),
+            ]
+        );
+        assert!(melody.tones().all(|(f, _)| f == 0));
+    }
+
+    #[test]
+    fn note_names() {
+        // h 同 b,大写也可以
+        let melody = parse("t:d=4,o=5,b=120:h,B,C#").unwrap();
+        let pitches: Vec<_> = melody.notes.iter().map(|n| n.pitch).collect();
+        assert_eq!(pitches, [Some(Pitch::B), Some(Pitch::B), Some(Pitch::Cs)]);
+    }
+
+    #[test]
+    fn frequencies() {
+        let a4 = note(Some(Pitch::A), 4, 4, false);
+        assert_eq!(a4.frequency(), 440);
+        assert_eq!(note(Some(Pitch::A), 8, 4, false).frequency(), 7040);
+        assert_eq!(note(Some(Pitch::C), 8, 4, false).frequency(), 4186);
+        // 第 4 个八度的 c 四舍五入
+        assert_eq!(note(Some(Pitch::C), 4, 4, false).frequency(), 262);
+        assert_eq!(note(None, 4, 4, false).frequency(), 0);
+    }
+
+    #[test]
+    fn durations() {
+        let quarter = Duration {
+            division: 4,
+            dotted: false,
+        };
+        assert_eq!(quarter.millis(120), 500);
+        let dotted_eighth = Duration {
+            division: 8,
+            dotted: true,
+        };
+        assert_eq!(dotted_eighth.millis(120), 375);
+        let melody = parse("t:d=4,o=5,b=120:c,8p,2e.").unwrap();
+        let tones: Vec<_> = melody.tones().collect();
+        assert_eq!(tones, [(523, 500), (0, 250), (659, 1500)]);
+        assert_eq!(melody.millis(), 2250);
+    }
+
+    #[test]
+    fn empty_notes_are_skipped() {
+        let melody = parse("t:d=4,o=5,b=120:c,,e,").unwrap();
+        assert_eq!(melody.notes.len(), 2);
+        assert!(parse("t:d=4:").unwrap().notes.is_empty());
+    }
+
+    #[test]
+    fn malformed_sections() {
+        assert_eq!(parse(""), Err(Error::Sections));
+        assert_eq!(parse("t:d=4,o=5,b=120"), Err(Error::Sections));
+    }
+
+    #[test]
+    fn malformed_defaults() {
+        for rtttl in [
+            "t:d=3:c",
+            "t:o=9:c",
+            "t:o=2:c",
+            "t:b=0:c",
+            "t:x=1:c",
+            "t:d:c",
+            "t:d=four:c",
+            "t:o=300:c",
+        ] {
+            assert_eq!(parse(rtttl), Err(Error::Default), "{rtttl}");
+        }
+    }
+
+    #[test]
+    fn malformed_notes() {
+        for (rtttl, i) in [
+            ("t::x", 0),
+            ("t::c,e#", 1),
+            ("t::c,b#", 1),
+            ("t::3c", 0),
+            ("t::c9", 0),
+            ("t::c2", 0),
+            ("t::c5x", 0),
+            ("t::c,e,8", 2),
+            ("t::c..", 0),
+            ("t::99999c", 0),
+            ("t::c99999", 0),
+        ] {
+            assert_eq!(parse(rtttl), Err(Error::Note(i)), "{rtttl}");
+        }
+    }
+}
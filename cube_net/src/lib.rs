@@ -0,0 +1,412 @@
+//! 联机游戏的通信协议
+//!
+//! 每一帧的格式如下,多字节的字段都是小端存储:
+//!
+//! ```text
+//! 01  01  01  03  0700  02  0301  xxxx
+//! ^   ^   ^   ^   ^     ^   ^     ^
+//! |   |   |   |   |     |   |     CRC-16,校验前面所有的字节
+//! |   |   |   |   |     |   负载
+//! |   |   |   |   |     负载的长度
+//! |   |   |   |   序号
+//! |   |   |   消息类型
+//! |   |   协议版本
+//! |   00表示结束,01表示游戏中
+//! 表示哪款游戏
+//! ```
+
+#![no_std]
+#![warn(missing_docs)]
+
+extern crate alloc;
+
+pub mod lobby;
+pub mod lockstep;
+#[cfg(any(test, feature = "loopback"))]
+pub mod loopback;
+pub mod turn;
+
+/// 协议版本,格式不兼容时加一
+pub const VERSION: u8 = 1;
+/// 帧头的长度
+pub const HEADER_LEN: usize = 7;
+/// 校验码的长度
+pub const CRC_LEN: usize = 2;
+/// ESP-NOW 一次最多发送的字节数
+pub const MAX_FRAME_LEN: usize = 250;
+/// 负载的最大长度
+pub const MAX_PAYLOAD_LEN: usize = MAX_FRAME_LEN - HEADER_LEN - CRC_LEN;
+
+/// 游戏结束
+pub const STATE_END: u8 = 0x00;
+/// 游戏中
+pub const STATE_PLAYING: u8 = 0x01;
+
+/// 游戏代码
+pub mod game {
+    /// 无
+    pub const NONE: u8 = 0x00;
+    /// 对打球
+    pub const PLAY_BALL: u8 = 0x01;
+    /// 井字棋
+    pub const TIC_TAC_TOE: u8 = 0x02;
+    /// 四子棋
+    pub const CONNECT_FOUR: u8 = 0x03;
+    /// 黑白棋
+    pub const REVERSI: u8 = 0x04;
+    /// 贪吃蛇,单机游戏,只用来分享排行榜
+    pub const SNAKE: u8 = 0x10;
+    /// 方块人,单机游戏,只用来分享排行榜
+    pub const CUBE_MAN: u8 = 0x11;
+    /// 躲避方块,单机游戏,只用来分享排行榜
+    pub const DODGE_CUBE: u8 = 0x12;
+}
+
+/// 编解码的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// 缓冲区放不下整个帧
+    BufferTooSmall,
+    /// 负载超过最大长度
+    PayloadTooLong,
+    /// 数据不完整
+    Truncated,
+    /// 协议版本不一致
+    Version(u8),
+    /// 未知的消息类型
+    Kind(u8),
+    /// 校验失败
+    Checksum,
+}
+
+/// 消息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message<'a> {
+    /// 广播寻找对手
+    Discover {
+        /// 随机数,用来决定先后手
+        nonce: u32,
+    },
+    /// 接受对方的邀请
+    Accept {
+        /// 随机数,用来决定先后手
+        nonce: u32,
+    },
+    /// 游戏的状态,格式由游戏自己决定
+    GameState(&'a [u8]),
+    /// 玩家的输入,格式由游戏自己决定
+    Input(&'a [u8]),
+    /// 游戏结束
+    End,
+    /// 心跳
+    Ping,
+}
+
+impl<'a> Message<'a> {
+    fn kind(&self) -> u8 {
+        match self {
+            Message::Discover { .. } => 0,
+            Message::Accept { .. } => 1,
+            Message::GameState(_) => 2,
+            Message::Input(_) => 3,
+            Message::End => 4,
+            Message::Ping => 5,
+        }
+    }
+
+    /// 负载的长度
+    fn payload_len(&self) -> usize {
+        match self {
+            Message::Discover { .. } | Message::Accept { .. } => 4,
+            Message::GameState(data) | Message::Input(data) => data.len(),
+            Message::End | Message::Ping => 0,
+        }
+    }
+
+    fn write_payload(&self, buf: &mut [u8]) {
+        match self {
+            Message::Discover { nonce } | Message::Accept { nonce } => {
+                buf.copy_from_slice(&nonce.to_le_bytes())
+            }
+            Message::GameState(data) | Message::Input(data) => buf.copy_from_slice(data),
+            Message::End | Message::Ping => {}
+        }
+    }
+
+    fn read(kind: u8, payload: &'a [u8]) -> Result<Self, Error> {
+        let nonce = || match payload {
+            [a, b, c, d] => Ok(u32::from_le_bytes([*a, *b, *c, *d])),
+            _ => Err(Error::Truncated),
+        };
+        match kind {
+            0 => Ok(Message::Discover { nonce: nonce()? }),
+            1 => Ok(Message::Accept { nonce: nonce()? }),
+            2 => Ok(Message::GameState(payload)),
+            3 => Ok(Message::Input(payload)),
+            4 => Ok(Message::End),
+            5 => Ok(Message::Ping),
+            _ => Err(Error::Kind(kind)),
+        }
+    }
+}
+
+/// 一帧数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet<'a> {
+    /// 游戏代码
+    pub game: u8,
+    /// 序号,每发送一帧加一,用来丢弃重复的帧
+    pub seq: u16,
+    /// 消息
+    pub message: Message<'a>,
+}
+
+impl<'a> Packet<'a> {
+    /// 新建一帧
+    pub fn new(game: u8, seq: u16, message: Message<'a>) -> Self {
+        Self { game, seq, message }
+    }
+
+    /// 游戏状态,结束时为 00,否则为 01
+    pub fn state(&self) -> u8 {
+        match self.message {
+            Message::End => STATE_END,
+            _ => STATE_PLAYING,
+        }
+    }
+
+    /// 编码后的长度
+    pub fn encoded_len(&self) -> usize {
+        HEADER_LEN + self.message.payload_len() + CRC_LEN
+    }
+
+    /// 编码,返回写入的字节数
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let payload_len = self.message.payload_len();
+        if payload_len > MAX_PAYLOAD_LEN {
+            return Err(Error::PayloadTooLong);
+        }
+        let len = self.encoded_len();
+        if buf.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+
+        buf[0] = self.game;
+        buf[1] = self.state();
+        buf[2] = VERSION;
+        buf[3] = self.message.kind();
+        buf[4..6].copy_from_slice(&self.seq.to_le_bytes());
+        buf[6] = payload_len as u8;
+        self.message
+            .write_payload(&mut buf[HEADER_LEN..HEADER_LEN + payload_len]);
+        let crc = crc16(&buf[..HEADER_LEN + payload_len]);
+        buf[HEADER_LEN + payload_len..len].copy_from_slice(&crc.to_le_bytes());
+        Ok(len)
+    }
+
+    /// 解码,负载借用输入的数据
+    pub fn decode(buf: &'a [u8]) -> Result<Self, Error> {
+        if buf.len() < HEADER_LEN + CRC_LEN {
+            return Err(Error::Truncated);
+        }
+        let payload_len = buf[6] as usize;
+        if payload_len > MAX_PAYLOAD_LEN {
+            return Err(Error::PayloadTooLong);
+        }
+        let len = HEADER_LEN + payload_len + CRC_LEN;
+        if buf.len() < len {
+            return Err(Error::Truncated);
+        }
+        let crc = u16::from_le_bytes([buf[len - 2], buf[len - 1]]);
+        if crc != crc16(&buf[..len - CRC_LEN]) {
+            return Err(Error::Checksum);
+        }
+        if buf[2] != VERSION {
+            return Err(Error::Version(buf[2]));
+        }
+
+        let message = Message::read(buf[3], &buf[HEADER_LEN..HEADER_LEN + payload_len])?;
+        Ok(Self {
+            game: buf[0],
+            seq: u16::from_le_bytes([buf[4], buf[5]]),
+            message,
+        })
+    }
+}
+
+/// 序号是否比上一个新,序号会回绕
+pub fn is_newer(seq: u16, last: u16) -> bool {
+    let diff = seq.wrapping_sub(last);
+    diff != 0 && diff < 0x8000
+}
+
+/// CRC-16/CCITT-FALSE
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 > 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 所有种类的消息,包括空的和最长的负载
+    fn messages(long: &[u8]) -> [Message<'_>; 8] {
+        [
+            Message::Discover { nonce: 0x1234_5678 },
+            Message::Accept { nonce: u32::MAX },
+            Message::GameState(&[]),
+            Message::GameState(&[1, 2, 3]),
+            Message::Input(long),
+            Message::Input(&[0xff]),
+            Message::End,
+            Message::Ping,
+        ]
+    }
+
+    /// 按照帧的格式拼出一帧,校验码重新计算
+    fn frame(header: [u8; HEADER_LEN], payload: &[u8]) -> ([u8; MAX_FRAME_LEN + 16], usize) {
+        let mut buf = [0u8; MAX_FRAME_LEN + 16];
+        let len = HEADER_LEN + payload.len();
+        buf[..HEADER_LEN].copy_from_slice(&header);
+        buf[HEADER_LEN..len].copy_from_slice(payload);
+        let crc = crc16(&buf[..len]);
+        buf[len..len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        (buf, len + CRC_LEN)
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+    }
+
+    #[test]
+    fn documented_frame() {
+        let (buf, len) = frame([0x01, 0x01, 0x01, 0x03, 0x07, 0x00, 0x02], &[0x03, 0x01]);
+        let packet = Packet::decode(&buf[..len]).unwrap();
+        assert_eq!(packet, Packet::new(0x01, 7, Message::Input(&[0x03, 0x01])));
+    }
+
+    #[test]
+    fn round_trip() {
+        let long = [0x5a; MAX_PAYLOAD_LEN];
+        for (i, message) in messages(&long).into_iter().enumerate() {
+            let packet = Packet::new(game::REVERSI, 0xff00 + i as u16, message);
+            let mut buf = [0u8; MAX_FRAME_LEN];
+            let len = packet.encode(&mut buf).unwrap();
+            assert_eq!(len, packet.encoded_len());
+            assert!(len <= MAX_FRAME_LEN);
+            assert_eq!(buf[1], packet.state());
+            assert_eq!(Packet::decode(&buf[..len]), Ok(packet));
+        }
+    }
+
+    #[test]
+    fn encode_errors() {
+        let long = [0u8; MAX_PAYLOAD_LEN + 1];
+        let mut buf = [0u8; MAX_FRAME_LEN + 16];
+        let packet = Packet::new(0, 0, Message::GameState(&long));
+        assert_eq!(packet.encode(&mut buf), Err(Error::PayloadTooLong));
+        let packet = Packet::new(0, 0, Message::Discover { nonce: 1 });
+        let len = packet.encoded_len();
+        assert_eq!(
+            packet.encode(&mut buf[..len - 1]),
+            Err(Error::BufferTooSmall)
+        );
+        assert_eq!(packet.encode(&mut buf[..len]), Ok(len));
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected() {
+        let long = [0xa5; MAX_PAYLOAD_LEN];
+        for message in messages(&long) {
+            let mut buf = [0u8; MAX_FRAME_LEN];
+            let len = Packet::new(1, 2, message).encode(&mut buf).unwrap();
+            for end in 0..len {
+                assert!(Packet::decode(&buf[..end]).is_err(), "{message:?} {end}");
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_frames_are_rejected() {
+        for message in messages(&[0x3c; 40]) {
+            let mut buf = [0u8; MAX_FRAME_LEN];
+            let len = Packet::new(1, 2, message).encode(&mut buf).unwrap();
+            // 任意一位出错都能发现,包括长度和校验码本身
+            for bit in 0..len * 8 {
+                let mut bad = buf;
+                bad[bit / 8] ^= 1 << (bit % 8);
+                assert!(Packet::decode(&bad[..len]).is_err(), "{message:?} {bit}");
+            }
+        }
+    }
+
+    #[test]
+    fn bad_payload_length() {
+        // 校验码正确但是长度和消息不符
+        let (buf, len) = frame([1, 1, VERSION, 0, 0, 0, 3], &[1, 2, 3]);
+        assert_eq!(Packet::decode(&buf[..len]), Err(Error::Truncated));
+        let (buf, len) = frame([1, 1, VERSION, 1, 0, 0, 5], &[1, 2, 3, 4, 5]);
+        assert_eq!(Packet::decode(&buf[..len]), Err(Error::Truncated));
+        // 超过最大长度
+        let long = [0u8; MAX_PAYLOAD_LEN + 1];
+        let mut header = [1, 1, VERSION, 2, 0, 0, 0];
+        header[6] = long.len() as u8;
+        let (buf, len) = frame(header, &long);
+        assert_eq!(Packet::decode(&buf[..len]), Err(Error::PayloadTooLong));
+        // 长度字段比实际的数据长
+        let (mut buf, len) = frame([1, 1, VERSION, 2, 0, 0, 2], &[1, 2]);
+        buf[6] = 200;
+        assert_eq!(Packet::decode(&buf[..len]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn bad_version_and_kind() {
+        let (buf, len) = frame([1, 1, VERSION + 1, 5, 0, 0, 0], &[]);
+        assert_eq!(
+            Packet::decode(&buf[..len]),
+            Err(Error::Version(VERSION + 1))
+        );
+        let (buf, len) = frame([1, 1, VERSION, 6, 0, 0, 0], &[]);
+        assert_eq!(Packet::decode(&buf[..len]), Err(Error::Kind(6)));
+    }
+
+    #[test]
+    fn random_data_never_panics() {
+        let mut rng = 0x1234_5678u32;
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        for _ in 0..10_000 {
+            for b in buf.iter_mut() {
+                rng ^= rng << 13;
+                rng ^= rng >> 17;
+                rng ^= rng << 5;
+                *b = rng as u8;
+            }
+            // 一部分帧的长度字段落在范围内,能走到后面的检查
+            buf[6] %= 16;
+            let len = rng as usize % buf.len();
+            let _ = Packet::decode(&buf[..len]);
+        }
+    }
+
+    #[test]
+    fn sequence_wraps() {
+        assert!(is_newer(1, 0));
+        assert!(is_newer(0, u16::MAX));
+        assert!(!is_newer(5, 5));
+        assert!(!is_newer(4, 5));
+        assert!(!is_newer(0x8000, 0));
+        assert!(is_newer(0x7fff, 0));
+    }
+}
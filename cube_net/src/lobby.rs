@@ -0,0 +1,296 @@
+//! 配对大厅
+//!
+//! A 定时广播 Discover,B 收到后单播 Accept 确认,A 收到确认后停止广播,双方只和对方通信.
+//! 两边同时广播时,先收到对方广播的一方作为 B.
+//!
+//! 大厅不依赖具体的传输方式和时钟,由调用方定时调用 [`Lobby::poll`] 推进.
+
+use crate::{is_newer, Message, Packet, MAX_FRAME_LEN};
+
+/// 设备地址
+pub type Address = [u8; 6];
+
+/// 广播地址
+pub const BROADCAST_ADDRESS: Address = [0xff; 6];
+
+/// 传输层,ESP-NOW 或者测试用的回环
+pub trait Transport {
+    /// 发送数据,对方确认收到时返回 true,广播时只表示发送成功
+    fn send(&mut self, to: &Address, data: &[u8]) -> bool;
+
+    /// 取出一帧收到的数据,返回发送方的地址和长度
+    fn receive(&mut self, buf: &mut [u8]) -> Option<(Address, usize)>;
+
+    /// 添加对方,单播之前需要先添加
+    fn add_peer(&mut self, peer: &Address) -> bool;
+
+    /// 移除对方
+    fn remove_peer(&mut self, peer: &Address);
+}
+
+/// 配对成功的对方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peer {
+    /// 对方的地址
+    pub address: Address,
+    /// 自己是否先手,随机数大的一方先手
+    pub first: bool,
+    /// 自己下一帧使用的序号
+    pub seq: u16,
+    /// 最后一次收到对方的序号
+    pub peer_seq: u16,
+}
+
+/// 大厅的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// 寻找对手中
+    Searching,
+    /// 配对成功
+    Paired(Peer),
+    /// 超时没有找到对手
+    TimedOut,
+}
+
+/// 配对大厅
+#[derive(Debug, Clone)]
+pub struct Lobby {
+    /// 游戏代码,只和同一款游戏配对
+    game: u8,
+    /// 随机数,用来决定先后手
+    nonce: u32,
+    seq: u16,
+    /// 开始寻找的时间,单位毫秒
+    started: Option<u64>,
+    /// 下一次广播的时间,单位毫秒
+    next_broadcast: u64,
+    status: Status,
+}
+
+impl Lobby {
+    /// 广播的间隔,单位毫秒
+    pub const BROADCAST_INTERVAL: u64 = 300;
+    /// 寻找对手的超时时间,单位毫秒
+    pub const TIMEOUT: u64 = 30_000;
+    /// 单播确认失败时的重试次数
+    pub const RETRIES: usize = 3;
+
+    /// 新建大厅
+    pub fn new(game: u8, nonce: u32) -> Self {
+        Self {
+            game,
+            nonce,
+            seq: 0,
+            started: None,
+            next_broadcast: 0,
+            status: Status::Searching,
+        }
+    }
+
+    /// 当前的状态
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// 推进一步:处理收到的数据,到时间时广播,now 为当前时间,单位毫秒
+    pub fn poll<T: Transport>(&mut self, now: u64, transport: &mut T) -> Status {
+        if self.status != Status::Searching {
+            return self.status;
+        }
+        let started = *self.started.get_or_insert(now);
+
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        while let Some((from, len)) = transport.receive(&mut buf) {
+            let Ok(packet) = Packet::decode(&buf[..len]) else {
+                continue;
+            };
+            if packet.game != self.game {
+                continue;
+            }
+            let (peer_nonce, accept) = match packet.message {
+                Message::Discover { nonce } => (nonce, true),
+                Message::Accept { nonce } => (nonce, false),
+                _ => continue,
+            };
+            // 收到自己的广播,或者两边碰巧相同
+            if peer_nonce == self.nonce {
+                continue;
+            }
+            if !transport.add_peer(&from) {
+                continue;
+            }
+            // 作为 B 单播确认,对方没有收到就继续寻找
+            if accept && !self.send(transport, &from, Message::Accept { nonce: self.nonce }) {
+                transport.remove_peer(&from);
+                continue;
+            }
+            self.status = Status::Paired(Peer {
+                address: from,
+                first: self.nonce > peer_nonce,
+                seq: self.seq.wrapping_add(1),
+                peer_seq: packet.seq,
+            });
+            return self.status;
+        }
+
+        if now >= started + Self::TIMEOUT {
+            self.status = Status::TimedOut;
+            return self.status;
+        }
+        if now >= self.next_broadcast {
+            self.next_broadcast = now + Self::BROADCAST_INTERVAL;
+            self.send(
+                transport,
+                &BROADCAST_ADDRESS,
+                Message::Discover { nonce: self.nonce },
+            );
+        }
+        self.status
+    }
+
+    /// 发送一帧,失败时重试
+    fn send<T: Transport>(&mut self, transport: &mut T, to: &Address, message: Message) -> bool {
+        self.seq = self.seq.wrapping_add(1);
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let Ok(len) = Packet::new(self.game, self.seq, message).encode(&mut buf) else {
+            return false;
+        };
+        (0..Self::RETRIES).any(|_| transport.send(to, &buf[..len]))
+    }
+}
+
+/// 对方发来的帧是否需要处理,丢弃重复的和过期的帧
+pub fn accept(peer: &mut Peer, seq: u16) -> bool {
+    if !is_newer(seq, peer.peer_seq) {
+        return false;
+    }
+    peer.peer_seq = seq;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loopback::Loopback;
+
+    const A: Address = [0xa; 6];
+    const B: Address = [0xb; 6];
+    const GAME: u8 = 0x01;
+
+    /// 两个小方轮流推进,直到都不在寻找对手
+    fn run(a: &mut Lobby, ta: &mut Loopback, b: &mut Lobby, tb: &mut Loopback) -> (Status, Status) {
+        let mut now = 0;
+        while now < Lobby::TIMEOUT * 2 {
+            let (sa, sb) = (a.poll(now, ta), b.poll(now, tb));
+            if sa != Status::Searching && sb != Status::Searching {
+                return (sa, sb);
+            }
+            now += 100;
+        }
+        panic!("still searching")
+    }
+
+    fn paired(status: Status) -> Peer {
+        match status {
+            Status::Paired(peer) => peer,
+            s => panic!("not paired: {s:?}"),
+        }
+    }
+
+    /// 配对之后双方的序号衔接:自己下一帧的序号比对方记录的新
+    fn assert_linked(a: &Peer, b: &Peer) {
+        assert!(is_newer(a.seq, b.peer_seq));
+        assert!(is_newer(b.seq, a.peer_seq));
+        assert_ne!(a.first, b.first);
+    }
+
+    #[test]
+    fn discover_and_accept() {
+        let (mut ta, mut tb) = Loopback::pair(A, B);
+        let (mut a, mut b) = (Lobby::new(GAME, 1), Lobby::new(GAME, 2));
+        // A 先广播,B 后加入
+        assert_eq!(a.poll(0, &mut ta), Status::Searching);
+        let sb = paired(b.poll(0, &mut tb));
+        assert_eq!(sb.address, A);
+        assert!(sb.first);
+        let sa = paired(a.poll(100, &mut ta));
+        assert_eq!(sa.address, B);
+        assert!(!sa.first);
+        assert_linked(&sa, &sb);
+    }
+
+    #[test]
+    fn simultaneous_broadcast() {
+        let (mut ta, mut tb) = Loopback::pair(A, B);
+        let (mut a, mut b) = (Lobby::new(GAME, 7), Lobby::new(GAME, 3));
+        // 双方都还没有收到对方的广播,都在广播
+        ta.hold(true);
+        assert_eq!(a.poll(0, &mut ta), Status::Searching);
+        assert_eq!(b.poll(0, &mut tb), Status::Searching);
+        ta.hold(false);
+        // 双方都先收到对方的广播,都作为 B 确认
+        let sa = paired(a.poll(100, &mut ta));
+        let sb = paired(b.poll(100, &mut tb));
+        assert_eq!((sa.address, sb.address), (B, A));
+        // 随机数大的一方先手
+        assert!(sa.first);
+        assert_linked(&sa, &sb);
+    }
+
+    #[test]
+    fn accept_is_retried() {
+        let (mut ta, mut tb) = Loopback::pair(A, B);
+        let (mut a, mut b) = (Lobby::new(GAME, 1), Lobby::new(GAME, 2));
+        a.poll(0, &mut ta);
+        // 前两次单播确认丢失,第三次成功
+        tb.drop_next(Lobby::RETRIES - 1);
+        let sb = paired(b.poll(0, &mut tb));
+        let sa = paired(a.poll(100, &mut ta));
+        assert_linked(&sa, &sb);
+    }
+
+    #[test]
+    fn failed_accept_keeps_searching() {
+        let (mut ta, mut tb) = Loopback::pair(A, B);
+        let (mut a, mut b) = (Lobby::new(GAME, 1), Lobby::new(GAME, 2));
+        a.poll(0, &mut ta);
+        // 所有的单播确认都丢失,B 继续寻找,之后再配对
+        tb.drop_next(Lobby::RETRIES);
+        assert_eq!(b.poll(0, &mut tb), Status::Searching);
+        let (sa, sb) = run(&mut a, &mut ta, &mut b, &mut tb);
+        assert_linked(&paired(sa), &paired(sb));
+    }
+
+    #[test]
+    fn other_games_are_ignored() {
+        let (mut ta, mut tb) = Loopback::pair(A, B);
+        let (mut a, mut b) = (Lobby::new(GAME, 1), Lobby::new(GAME + 1, 2));
+        let (sa, sb) = run(&mut a, &mut ta, &mut b, &mut tb);
+        assert_eq!((sa, sb), (Status::TimedOut, Status::TimedOut));
+    }
+
+    #[test]
+    fn same_nonce_is_ignored() {
+        let (mut ta, mut tb) = Loopback::pair(A, B);
+        let (mut a, mut b) = (Lobby::new(GAME, 5), Lobby::new(GAME, 5));
+        let (sa, sb) = run(&mut a, &mut ta, &mut b, &mut tb);
+        assert_eq!((sa, sb), (Status::TimedOut, Status::TimedOut));
+    }
+
+    #[test]
+    fn duplicate_frames_are_dropped() {
+        let mut peer = Peer {
+            address: A,
+            first: true,
+            seq: 0,
+            peer_seq: 10,
+        };
+        assert!(!accept(&mut peer, 10));
+        assert!(!accept(&mut peer, 9));
+        assert!(accept(&mut peer, 11));
+        assert_eq!(peer.peer_seq, 11);
+        // 序号回绕
+        peer.peer_seq = u16::MAX;
+        assert!(accept(&mut peer, 0));
+    }
+}
@@ -0,0 +1,605 @@
+//! 帧同步
+//!
+//! 双方以固定的间隔推进同一个确定性的游戏,每一帧只交换双方的输入,不交换游戏的状态.
+//!
+//! - 输入延迟:第 t 帧采集的输入在第 t+delay 帧才生效,对方的输入有 delay 帧的时间送达;
+//! - 每次发送都带上对方还没有确认的所有输入,丢了一帧下一帧还会再发;
+//! - 不回滚时,对方的输入没有到就等待;
+//! - 回滚时,对方的输入没有到就沿用对方上一次的输入先走,最多领先若干帧.
+//!   对方的输入到了之后,如果和猜测的不一样,从最后一个确认的状态重新模拟到当前帧.
+//!
+//! 和 [`Session`](crate::turn::Session) 一样不依赖具体的传输方式和时钟,由调用方定时调用 [`Lockstep::poll`] 推进.
+
+use crate::{
+    is_newer,
+    lobby::{Peer, Transport},
+    turn::Player,
+    Message, Packet, MAX_FRAME_LEN,
+};
+
+/// 输入缓冲区的大小,对方最多领先两倍的输入延迟和回滚的帧数,要能放得下
+const BUFFER_LEN: usize = 64;
+/// 一帧最多带上的输入数
+const MAX_INPUTS: usize = 32;
+
+/// 玩家的输入,编码成一个字节
+pub trait Input: Copy + Default + PartialEq {
+    /// 编码
+    fn to_u8(self) -> u8;
+
+    /// 解码,无效的编码返回默认的输入
+    fn from_u8(v: u8) -> Self;
+}
+
+/// 帧同步的游戏,同样的状态和输入必须得到同样的结果
+pub trait Game: Clone {
+    /// 玩家的输入
+    type Input: Input;
+
+    /// 推进一帧,inputs 依次为先手和后手的输入
+    fn step(&mut self, inputs: [Self::Input; 2]);
+}
+
+/// 同步的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// 正常推进
+    Running,
+    /// 等待对方的输入
+    Waiting,
+    /// 对方断开
+    Disconnected,
+}
+
+/// 帧同步
+#[derive(Debug, Clone)]
+pub struct Lockstep<G: Game> {
+    /// 游戏代码
+    game: u8,
+    peer: Peer,
+    /// 自己是先手还是后手
+    me: Player,
+    /// 当前的状态,可能用了猜测的输入
+    state: G,
+    /// 最后一个确认的状态,只在回滚时使用
+    confirmed_state: G,
+    /// 下一个要模拟的帧
+    tick: u32,
+    /// confirmed 之前的帧双方的输入都已经确认
+    confirmed: u32,
+    /// 输入延迟的帧数
+    delay: u32,
+    /// 最多领先确认的帧数,为 0 时不回滚
+    rollback: u32,
+    /// 每一帧的间隔,单位毫秒
+    interval: u64,
+    /// 自己的输入,按帧号取模存储
+    local: [G::Input; BUFFER_LEN],
+    /// 对方的输入,还没有收到时为 None
+    remote: [Option<G::Input>; BUFFER_LEN],
+    /// 模拟时使用的对方的输入,用来判断是否猜错
+    used: [G::Input; BUFFER_LEN],
+    /// 最新采集的输入
+    input: G::Input,
+    /// 自己这一帧之前的输入已经记录,记录之后就不能再改
+    recorded: u32,
+    /// 对方最后一个确认的输入,用来猜测
+    last_remote: G::Input,
+    /// 对方已经收到了自己这一帧之前的输入
+    peer_ack: u32,
+    /// 下一帧的时间,单位毫秒
+    next_tick: Option<u64>,
+    /// 最后一次收到对方数据的时间,单位毫秒
+    last_seen: Option<u64>,
+    disconnected: bool,
+}
+
+impl<G: Game> Lockstep<G> {
+    /// 默认每一帧的间隔,单位毫秒
+    pub const INTERVAL: u64 = 50;
+    /// 默认的输入延迟
+    pub const DELAY: u32 = 2;
+    /// 超过该时间没有收到对方的数据视为断开,单位毫秒
+    pub const TIMEOUT: u64 = 3000;
+
+    /// 配对成功之后开始同步,双方的初始状态必须相同
+    pub fn new(game: u8, peer: Peer, state: G) -> Self {
+        let me = if peer.first {
+            Player::First
+        } else {
+            Player::Second
+        };
+        Self {
+            game,
+            peer,
+            me,
+            confirmed_state: state.clone(),
+            state,
+            tick: 0,
+            confirmed: 0,
+            delay: 0,
+            rollback: 0,
+            interval: Self::INTERVAL,
+            local: [G::Input::default(); BUFFER_LEN],
+            remote: [None; BUFFER_LEN],
+            used: [G::Input::default(); BUFFER_LEN],
+            input: G::Input::default(),
+            recorded: 0,
+            last_remote: G::Input::default(),
+            peer_ack: 0,
+            next_tick: None,
+            last_seen: None,
+            disconnected: false,
+        }
+        .with_delay(Self::DELAY)
+    }
+
+    /// 设置输入延迟的帧数
+    pub fn with_delay(mut self, delay: u32) -> Self {
+        self.delay = delay.min(Self::max_ahead() - self.rollback);
+        self.recorded = self.delay;
+        // 前面 delay 帧没有采集输入,双方都用默认的输入
+        for (t, remote) in self.remote.iter_mut().enumerate() {
+            *remote = (t < self.delay as usize).then(G::Input::default);
+        }
+        self
+    }
+
+    /// 开启回滚,最多领先确认 ticks 帧
+    pub fn with_rollback(mut self, ticks: u32) -> Self {
+        self.rollback = ticks.min(Self::max_ahead() - self.delay);
+        self
+    }
+
+    /// 设置每一帧的间隔,单位毫秒
+    pub fn with_interval(mut self, interval: u64) -> Self {
+        self.interval = interval.max(1);
+        self
+    }
+
+    /// 输入延迟和回滚的帧数之和的上限
+    const fn max_ahead() -> u32 {
+        (BUFFER_LEN / 4) as u32
+    }
+
+    /// 当前的状态
+    pub fn state(&self) -> &G {
+        &self.state
+    }
+
+    /// 自己
+    pub fn me(&self) -> Player {
+        self.me
+    }
+
+    /// 下一个要模拟的帧
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    /// 双方的输入都已经确认的帧数
+    pub fn confirmed(&self) -> u32 {
+        self.confirmed
+    }
+
+    /// 对方的地址
+    pub fn peer(&self) -> &Peer {
+        &self.peer
+    }
+
+    /// 采集自己的输入,下一帧推进时记录,delay 帧之后生效
+    pub fn input(&mut self, input: G::Input) {
+        self.input = input;
+    }
+
+    /// 推进:处理收到的输入,到时间时模拟一帧并发送自己的输入
+    pub fn poll<T: Transport>(&mut self, now: u64, transport: &mut T) -> Status {
+        let last_seen = *self.last_seen.get_or_insert(now);
+        let next_tick = *self.next_tick.get_or_insert(now);
+        if self.disconnected {
+            return Status::Disconnected;
+        }
+
+        self.receive(now, transport);
+        if self.disconnected {
+            return Status::Disconnected;
+        }
+        if self.last_seen.unwrap_or(last_seen) + Self::TIMEOUT <= now {
+            self.disconnected = true;
+            return Status::Disconnected;
+        }
+        self.confirm();
+
+        if now < next_tick {
+            return Status::Running;
+        }
+        let status = self.advance();
+        self.next_tick = Some(match status {
+            // 等待之后重新计时,不要一下子追赶很多帧
+            Status::Waiting => now + self.interval,
+            _ => next_tick + self.interval,
+        });
+        // 等待时也要发送,对方可能在等自己的输入
+        self.send_inputs(transport);
+        status
+    }
+
+    /// 离开,通知对方
+    pub fn leave<T: Transport>(&mut self, transport: &mut T) {
+        self.send(transport, Message::End);
+        transport.remove_peer(&self.peer.address);
+    }
+
+    /// 处理对方发来的输入,负载为确认的帧号 u32、第一个输入的帧号 u32 和若干个输入
+    fn receive<T: Transport>(&mut self, now: u64, transport: &mut T) {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        while let Some((from, len)) = transport.receive(&mut buf) {
+            if from != self.peer.address {
+                continue;
+            }
+            let Ok(packet) = Packet::decode(&buf[..len]) else {
+                continue;
+            };
+            if packet.game != self.game || !is_newer(packet.seq, self.peer.peer_seq) {
+                continue;
+            }
+            self.peer.peer_seq = packet.seq;
+            self.last_seen = Some(now);
+            match packet.message {
+                Message::Input(&[a0, a1, a2, a3, s0, s1, s2, s3, ref inputs @ ..]) => {
+                    let ack = u32::from_le_bytes([a0, a1, a2, a3]);
+                    let start = u32::from_le_bytes([s0, s1, s2, s3]);
+                    if ack > self.peer_ack {
+                        self.peer_ack = ack;
+                    }
+                    for (i, v) in inputs.iter().enumerate() {
+                        let t = start.wrapping_add(i as u32);
+                        // 只保存还没有确认并且缓冲区放得下的
+                        if t >= self.confirmed && t < self.confirmed + BUFFER_LEN as u32 {
+                            self.remote[t as usize % BUFFER_LEN] = Some(G::Input::from_u8(*v));
+                        }
+                    }
+                }
+                Message::End => self.disconnected = true,
+                _ => {}
+            }
+        }
+    }
+
+    /// 双方的输入都到了的帧变成确认的帧,猜错时回滚
+    fn confirm(&mut self) {
+        let mut mispredicted = false;
+        while self.confirmed < self.tick {
+            let i = self.confirmed as usize % BUFFER_LEN;
+            let Some(remote) = self.remote[i] else {
+                break;
+            };
+            if self.rollback > 0 {
+                self.confirmed_state
+                    .step(self.inputs(self.local[i], remote));
+            }
+            mispredicted |= remote != self.used[i];
+            // 腾出位置给后面的帧
+            self.remote[i] = None;
+            self.last_remote = remote;
+            self.confirmed += 1;
+        }
+
+        if mispredicted {
+            self.state = self.confirmed_state.clone();
+            for t in self.confirmed..self.tick {
+                self.simulate(t);
+            }
+        }
+    }
+
+    /// 模拟一帧,对方的输入没有到时等待或者猜测
+    fn advance(&mut self) -> Status {
+        // 记录 delay 帧之后的输入,等待时也要记录,不然没有输入延迟时双方会互相等待
+        if self.recorded <= self.tick + self.delay {
+            self.local[self.recorded as usize % BUFFER_LEN] = self.input;
+            self.recorded += 1;
+        }
+
+        let i = self.tick as usize % BUFFER_LEN;
+        if self.remote[i].is_none() && self.tick - self.confirmed >= self.rollback {
+            return Status::Waiting;
+        }
+
+        self.simulate(self.tick);
+        self.tick += 1;
+        self.confirm();
+        Status::Running
+    }
+
+    /// 用自己的输入和对方确认的或猜测的输入模拟第 t 帧
+    fn simulate(&mut self, t: u32) {
+        let i = t as usize % BUFFER_LEN;
+        // 猜测对方沿用最后一个确认的输入
+        let remote = self.remote[i].unwrap_or(self.last_remote);
+        self.used[i] = remote;
+        self.state.step(self.inputs(self.local[i], remote));
+    }
+
+    /// 按先手、后手排列双方的输入
+    fn inputs(&self, local: G::Input, remote: G::Input) -> [G::Input; 2] {
+        match self.me {
+            Player::First => [local, remote],
+            Player::Second => [remote, local],
+        }
+    }
+
+    /// 发送对方还没有确认的所有输入
+    fn send_inputs<T: Transport>(&mut self, transport: &mut T) {
+        let end = self.recorded;
+        let start = self
+            .peer_ack
+            .max(end.saturating_sub(MAX_INPUTS as u32))
+            .max(self.delay);
+        let mut payload = [0u8; 8 + MAX_INPUTS];
+        payload[..4].copy_from_slice(&self.confirmed.to_le_bytes());
+        payload[4..8].copy_from_slice(&start.to_le_bytes());
+        let mut len = 8;
+        for t in start..end {
+            payload[len] = self.local[t as usize % BUFFER_LEN].to_u8();
+            len += 1;
+        }
+        self.send(transport, Message::Input(&payload[..len]));
+    }
+
+    fn send<T: Transport>(&mut self, transport: &mut T, message: Message) {
+        let seq = self.peer.seq;
+        self.peer.seq = self.peer.seq.wrapping_add(1);
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        if let Ok(len) = Packet::new(self.game, seq, message).encode(&mut buf) {
+            transport.send(&self.peer.address, &buf[..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lobby::Address, loopback::Loopback};
+    use alloc::vec::Vec;
+
+    const A: Address = [0xa; 6];
+    const B: Address = [0xb; 6];
+    const GAME: u8 = 0x01;
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    struct Key(u8);
+
+    impl Input for Key {
+        fn to_u8(self) -> u8 {
+            self.0
+        }
+
+        fn from_u8(v: u8) -> Self {
+            Self(v)
+        }
+    }
+
+    /// 记录每一帧双方的输入,双方模拟的结果一样时记录也一样
+    #[derive(Debug, Clone, Default)]
+    struct Log(Vec<[u8; 2]>);
+
+    impl Game for Log {
+        type Input = Key;
+
+        fn step(&mut self, inputs: [Key; 2]) {
+            self.0.push([inputs[0].0, inputs[1].0]);
+        }
+    }
+
+    /// 会丢包和乱序的传输,收到的帧先放进收件箱,随机丢弃一些,再随机取出一帧
+    struct Lossy {
+        link: Loopback,
+        inbox: Vec<(Address, Vec<u8>)>,
+        rng: u32,
+        /// 丢弃的概率,单位百分之一
+        loss: u32,
+    }
+
+    impl Lossy {
+        fn new(link: Loopback, seed: u32, loss: u32) -> Self {
+            Self {
+                link,
+                inbox: Vec::new(),
+                rng: seed,
+                loss,
+            }
+        }
+
+        /// xorshift32
+        fn next(&mut self) -> u32 {
+            self.rng ^= self.rng << 13;
+            self.rng ^= self.rng >> 17;
+            self.rng ^= self.rng << 5;
+            self.rng
+        }
+    }
+
+    impl Transport for Lossy {
+        fn send(&mut self, to: &Address, data: &[u8]) -> bool {
+            self.link.send(to, data)
+        }
+
+        fn receive(&mut self, buf: &mut [u8]) -> Option<(Address, usize)> {
+            let mut frame = [0u8; MAX_FRAME_LEN];
+            while let Some((from, len)) = self.link.receive(&mut frame) {
+                if self.next() % 100 >= self.loss {
+                    self.inbox.push((from, frame[..len].to_vec()));
+                }
+            }
+            // 有时候这一次不投递,留到下一次,和后面的帧打乱顺序
+            if self.inbox.is_empty() || self.next() % 4 == 0 {
+                return None;
+            }
+            let i = self.next() as usize % self.inbox.len();
+            let (from, data) = self.inbox.swap_remove(i);
+            buf[..data.len()].copy_from_slice(&data);
+            Some((from, data.len()))
+        }
+
+        fn add_peer(&mut self, peer: &Address) -> bool {
+            self.link.add_peer(peer)
+        }
+
+        fn remove_peer(&mut self, peer: &Address) {
+            self.link.remove_peer(peer)
+        }
+    }
+
+    /// 已经配对的两端,A 先手
+    fn pair() -> (Loopback, Loopback, Peer, Peer) {
+        let (mut ta, mut tb) = Loopback::pair(A, B);
+        ta.add_peer(&B);
+        tb.add_peer(&A);
+        let peer = |address, first, seq, peer_seq| Peer {
+            address,
+            first,
+            seq,
+            peer_seq,
+        };
+        (ta, tb, peer(B, true, 100, 199), peer(A, false, 200, 99))
+    }
+
+    /// 每一方的输入随帧号变化,猜测时经常猜错
+    fn key(player: Player, tick: u32) -> Key {
+        let offset = match player {
+            Player::First => 0,
+            Player::Second => 2,
+        };
+        Key((tick / 3 + offset) as u8 % 4)
+    }
+
+    /// 双方每 10ms 推进一次,直到时间用完
+    fn run<T: Transport>(
+        a: &mut Lockstep<Log>,
+        ta: &mut T,
+        b: &mut Lockstep<Log>,
+        tb: &mut T,
+        from: u64,
+        to: u64,
+    ) {
+        let mut now = from;
+        while now < to {
+            a.input(key(a.me(), a.tick()));
+            b.input(key(b.me(), b.tick()));
+            assert_ne!(a.poll(now, ta), Status::Disconnected);
+            assert_ne!(b.poll(now, tb), Status::Disconnected);
+            now += 10;
+        }
+    }
+
+    /// 双方确认的帧完全一样
+    fn assert_agree(a: &Lockstep<Log>, b: &Lockstep<Log>) {
+        let confirmed = a.confirmed().min(b.confirmed()) as usize;
+        assert_eq!(a.state().0[..confirmed], b.state().0[..confirmed]);
+    }
+
+    #[test]
+    fn in_step_on_a_clean_link() {
+        let (mut ta, mut tb, pa, pb) = pair();
+        let mut a = Lockstep::new(GAME, pa, Log::default());
+        let mut b = Lockstep::new(GAME, pb, Log::default());
+        run(&mut a, &mut ta, &mut b, &mut tb, 0, 5000);
+        // 5s 每帧 50ms,最多落后一两帧
+        assert!(a.tick() >= 98 && b.tick() >= 98);
+        assert!(a.tick().abs_diff(b.tick()) <= 1);
+        assert_agree(&a, &b);
+        // 前面 delay 帧双方都用默认的输入
+        assert_eq!(a.state().0[..2], [[0, 0], [0, 0]]);
+        // 之后用的是双方各自采集的输入
+        assert!(a.state().0.iter().any(|&[x, y]| x != y));
+    }
+
+    #[test]
+    fn waits_for_late_input() {
+        let (mut ta, mut tb, pa, pb) = pair();
+        let mut a = Lockstep::new(GAME, pa, Log::default());
+        let mut b = Lockstep::new(GAME, pb, Log::default());
+        // B 的输入一直没有送达,A 走完输入延迟的帧之后等待
+        ta.hold(true);
+        for now in (0..500).step_by(50) {
+            a.poll(now, &mut ta);
+            b.poll(now, &mut tb);
+        }
+        assert_eq!(a.tick(), Lockstep::<Log>::DELAY);
+        assert_eq!(a.poll(500, &mut ta), Status::Waiting);
+        ta.hold(false);
+        run(&mut a, &mut ta, &mut b, &mut tb, 550, 1000);
+        assert!(a.tick() > Lockstep::<Log>::DELAY);
+        assert_agree(&a, &b);
+    }
+
+    #[test]
+    fn rollback_resimulates_mispredicted_input() {
+        let (mut ta, mut tb, pa, pb) = pair();
+        let mut a = Lockstep::new(GAME, pa, Log::default())
+            .with_delay(0)
+            .with_rollback(8);
+        let mut b = Lockstep::new(GAME, pb, Log::default())
+            .with_delay(0)
+            .with_rollback(8);
+        // B 一直按着右,A 还没有收到,猜测 B 没有输入
+        ta.hold(true);
+        for now in (0..300).step_by(50) {
+            b.input(Key(2));
+            assert_eq!(a.poll(now, &mut ta), Status::Running);
+            b.poll(now, &mut tb);
+        }
+        assert_eq!(a.confirmed(), 0);
+        assert!(a.tick() > 0);
+        assert!(a.state().0.iter().all(|&[_, y]| y == 0));
+
+        // B 的输入到了之后 A 从确认的状态重新模拟
+        ta.hold(false);
+        a.poll(300, &mut ta);
+        b.poll(300, &mut tb);
+        a.poll(300, &mut ta);
+        assert!(a.confirmed() >= 6);
+        assert!(a.state().0[..6].iter().all(|&[_, y]| y == 2));
+        assert_agree(&a, &b);
+    }
+
+    #[test]
+    fn rollback_is_bounded() {
+        let (mut ta, _tb, pa, _pb) = pair();
+        let mut a = Lockstep::new(GAME, pa, Log::default())
+            .with_delay(1)
+            .with_rollback(4);
+        ta.hold(true);
+        for now in (0..1000).step_by(50) {
+            a.poll(now, &mut ta);
+        }
+        // 最多领先确认的帧 rollback 帧,前面 delay 帧的输入是确定的
+        assert_eq!(a.confirmed(), 1);
+        assert_eq!(a.tick(), 5);
+        assert_eq!(a.poll(1000, &mut ta), Status::Waiting);
+    }
+
+    #[test]
+    fn survives_a_lossy_link() {
+        for (seed, rollback) in [(1, 0), (7, 0), (3, 6), (11, 6)] {
+            let (ta, tb, pa, pb) = pair();
+            let (mut ta, mut tb) = (Lossy::new(ta, seed, 30), Lossy::new(tb, seed * 31, 30));
+            let mut a = Lockstep::new(GAME, pa, Log::default()).with_rollback(rollback);
+            let mut b = Lockstep::new(GAME, pb, Log::default()).with_rollback(rollback);
+            run(&mut a, &mut ta, &mut b, &mut tb, 0, 10_000);
+            assert!(a.confirmed() > 50 && b.confirmed() > 50);
+            assert_agree(&a, &b);
+        }
+    }
+
+    #[test]
+    fn silent_peer_disconnects() {
+        let (mut ta, _tb, pa, _pb) = pair();
+        let mut a = Lockstep::new(GAME, pa, Log::default());
+        a.poll(0, &mut ta);
+        let timeout = Lockstep::<Log>::TIMEOUT;
+        assert_ne!(a.poll(timeout - 1, &mut ta), Status::Disconnected);
+        assert_eq!(a.poll(timeout, &mut ta), Status::Disconnected);
+    }
+}
@@ -0,0 +1,93 @@
+//! 回环传输,在同一个进程里模拟两个小方,用来在电脑上测试联机的流程
+
+use crate::lobby::{Address, Transport, BROADCAST_ADDRESS};
+use alloc::{collections::VecDeque, rc::Rc, vec::Vec};
+use core::cell::RefCell;
+
+/// 空中的数据帧
+#[derive(Debug, Default)]
+struct Air {
+    /// 每一帧的发送方、接收方和数据
+    frames: VecDeque<(Address, Address, Vec<u8>)>,
+    /// 接下来要丢弃的帧数,模拟丢包
+    drop: usize,
+    /// 暂停投递,发送的帧留在空中
+    hold: bool,
+}
+
+/// 回环传输的一端
+#[derive(Debug, Clone)]
+pub struct Loopback {
+    address: Address,
+    peers: Vec<Address>,
+    air: Rc<RefCell<Air>>,
+}
+
+impl Loopback {
+    /// 新建互相连通的两端
+    pub fn pair(a: Address, b: Address) -> (Self, Self) {
+        let air = Rc::new(RefCell::new(Air::default()));
+        let new = |address| Self {
+            address,
+            peers: Vec::new(),
+            air: air.clone(),
+        };
+        (new(a), new(b))
+    }
+
+    /// 自己的地址
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// 丢弃接下来发送的若干帧
+    pub fn drop_next(&self, n: usize) {
+        self.air.borrow_mut().drop = n;
+    }
+
+    /// 暂停或者恢复投递,暂停时双方发送的帧都留在空中,模拟同时发送
+    pub fn hold(&self, hold: bool) {
+        self.air.borrow_mut().hold = hold;
+    }
+}
+
+impl Transport for Loopback {
+    fn send(&mut self, to: &Address, data: &[u8]) -> bool {
+        if *to != BROADCAST_ADDRESS && !self.peers.contains(to) {
+            return false;
+        }
+        let mut air = self.air.borrow_mut();
+        if air.drop > 0 {
+            air.drop -= 1;
+            // 广播没有确认,丢了也不知道
+            return *to == BROADCAST_ADDRESS;
+        }
+        air.frames.push_back((self.address, *to, data.to_vec()));
+        true
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Option<(Address, usize)> {
+        let mut air = self.air.borrow_mut();
+        if air.hold {
+            return None;
+        }
+        let i = air.frames.iter().position(|(from, to, _)| {
+            *from != self.address && (*to == self.address || *to == BROADCAST_ADDRESS)
+        })?;
+        let (from, _, data) = air.frames.remove(i)?;
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Some((from, len))
+    }
+
+    fn add_peer(&mut self, peer: &Address) -> bool {
+        if !self.peers.contains(peer) {
+            self.peers.push(*peer);
+        }
+        true
+    }
+
+    fn remove_peer(&mut self, peer: &Address) {
+        self.peers.retain(|p| p != peer);
+    }
+}
@@ -0,0 +1,706 @@
+//! 回合制对战
+//!
+//! 双方轮流走棋,每一步都带着步数和走完之后局面的校验码,对方确认之前定时重发.
+//! 对方发现步数对不上或者局面不一致时请求同步,收到完整的局面后以步数多的一方为准.
+//! 一局结束后双方都请求再来一局时重新开始,上一局后手的一方先手.
+//! 每开始新的一局局数加一,除心跳和断开之外的消息都带着局数,收到其他局的消息直接丢弃,
+//! 避免上一局迟到的着法或者局面被当成这一局的.
+//!
+//! 和 [`Lobby`](crate::lobby::Lobby) 一样不依赖具体的传输方式和时钟,由调用方定时调用 [`Session::poll`] 推进.
+
+use crate::{
+    crc16, is_newer,
+    lobby::{Peer, Transport},
+    Message, Packet, MAX_FRAME_LEN, MAX_PAYLOAD_LEN,
+};
+
+/// 玩家
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Player {
+    /// 先手
+    First,
+    /// 后手
+    Second,
+}
+
+impl Player {
+    /// 对方
+    pub fn other(&self) -> Self {
+        match self {
+            Player::First => Player::Second,
+            Player::Second => Player::First,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Player::First => 0,
+            Player::Second => 1,
+        }
+    }
+
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Player::First),
+            1 => Some(Player::Second),
+            _ => None,
+        }
+    }
+}
+
+/// 一局的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// 某一方获胜
+    Win(Player),
+    /// 平局
+    Draw,
+}
+
+/// 棋类游戏的规则
+pub trait Rules: Clone + Default {
+    /// 这一步是否合法
+    fn is_valid(&self, player: Player, mv: u8) -> bool;
+
+    /// 走一步,调用之前已经检查过是否合法
+    fn apply(&mut self, player: Player, mv: u8);
+
+    /// 走完之后轮到谁,默认轮到对方,不能走时可以跳过
+    fn next(&self, player: Player) -> Player {
+        player.other()
+    }
+
+    /// 一局是否结束
+    fn outcome(&self) -> Option<Outcome>;
+
+    /// 将局面编码,返回写入的字节数
+    fn encode(&self, buf: &mut [u8]) -> usize;
+
+    /// 从编码中恢复局面
+    fn decode(buf: &[u8]) -> Option<Self>;
+}
+
+/// 对局的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// 对局中
+    Playing,
+    /// 一局结束
+    Over(Outcome),
+    /// 对方断开
+    Disconnected,
+}
+
+/// 走一步,负载为局数、步数、着法和走完之后局面的校验码
+const MOVE: u8 = 0;
+/// 确认收到某一步,负载为局数和步数
+const ACK: u8 = 1;
+/// 请求同步局面,负载为局数
+const SYNC_REQUEST: u8 = 2;
+/// 完整的局面,负载为局数、步数、轮到谁、先手和局面
+const SYNC: u8 = 3;
+/// 请求再来一局,负载为要结束的局数
+const REMATCH: u8 = 4;
+
+/// 回合制对局
+#[derive(Debug, Clone)]
+pub struct Session<R: Rules> {
+    /// 游戏代码
+    game: u8,
+    peer: Peer,
+    /// 自己是先手还是后手
+    me: Player,
+    rules: R,
+    /// 这一局谁先走
+    starter: Player,
+    /// 第几局,再来一局时加一
+    round: u8,
+    /// 轮到谁
+    turn: Player,
+    /// 已经走了多少步
+    moves: u16,
+    /// 对方还没有确认的一步:步数、着法和走完之后局面的校验码
+    pending: Option<(u16, u8, u16)>,
+    /// 下一次重发的时间,单位毫秒
+    next_retry: u64,
+    /// 下一次心跳的时间,单位毫秒
+    next_ping: u64,
+    /// 最后一次收到对方数据的时间,单位毫秒
+    last_seen: Option<u64>,
+    /// 自己和对方是否想再来一局
+    rematch: (bool, bool),
+    disconnected: bool,
+}
+
+impl<R: Rules> Session<R> {
+    /// 重发的间隔,单位毫秒
+    pub const RETRY_INTERVAL: u64 = 300;
+    /// 心跳的间隔,单位毫秒
+    pub const PING_INTERVAL: u64 = 1000;
+    /// 超过该时间没有收到对方的数据视为断开,单位毫秒
+    pub const TIMEOUT: u64 = 5000;
+
+    /// 配对成功之后开始对局,配对时先手的一方先走
+    pub fn new(game: u8, peer: Peer) -> Self {
+        let me = if peer.first {
+            Player::First
+        } else {
+            Player::Second
+        };
+        Self {
+            game,
+            peer,
+            me,
+            rules: R::default(),
+            starter: Player::First,
+            round: 0,
+            turn: Player::First,
+            moves: 0,
+            pending: None,
+            next_retry: 0,
+            next_ping: 0,
+            last_seen: None,
+            rematch: (false, false),
+            disconnected: false,
+        }
+    }
+
+    /// 当前的局面
+    pub fn rules(&self) -> &R {
+        &self.rules
+    }
+
+    /// 自己
+    pub fn me(&self) -> Player {
+        self.me
+    }
+
+    /// 轮到谁
+    pub fn turn(&self) -> Player {
+        self.turn
+    }
+
+    /// 是否轮到自己
+    pub fn my_turn(&self) -> bool {
+        self.status() == Status::Playing && self.turn == self.me
+    }
+
+    /// 对局的状态
+    pub fn status(&self) -> Status {
+        if self.disconnected {
+            return Status::Disconnected;
+        }
+        match self.rules.outcome() {
+            Some(outcome) => Status::Over(outcome),
+            None => Status::Playing,
+        }
+    }
+
+    /// 对方的地址
+    pub fn peer(&self) -> &Peer {
+        &self.peer
+    }
+
+    /// 自己走一步,不合法或者没有轮到自己时返回 false
+    pub fn play<T: Transport>(&mut self, now: u64, transport: &mut T, mv: u8) -> bool {
+        if !self.my_turn() || !self.rules.is_valid(self.me, mv) {
+            return false;
+        }
+        self.apply(self.me, mv);
+        self.pending = Some((self.moves, mv, self.hash()));
+        self.next_retry = now;
+        self.flush(now, transport);
+        true
+    }
+
+    /// 请求再来一局,双方都请求之后重新开始
+    pub fn request_rematch<T: Transport>(&mut self, transport: &mut T) {
+        self.rematch.0 = true;
+        self.send(transport, Message::GameState(&[REMATCH, self.round]));
+        self.try_rematch();
+    }
+
+    /// 推进一步:处理收到的数据,重发没有确认的一步,定时发送心跳
+    pub fn poll<T: Transport>(&mut self, now: u64, transport: &mut T) -> Status {
+        let last_seen = *self.last_seen.get_or_insert(now);
+        if self.disconnected {
+            return Status::Disconnected;
+        }
+
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        while let Some((from, len)) = transport.receive(&mut buf) {
+            if from != self.peer.address {
+                continue;
+            }
+            let Ok(packet) = Packet::decode(&buf[..len]) else {
+                continue;
+            };
+            if packet.game != self.game || !is_newer(packet.seq, self.peer.peer_seq) {
+                continue;
+            }
+            self.peer.peer_seq = packet.seq;
+            self.last_seen = Some(now);
+            match packet.message {
+                Message::Input(data) => self.on_move(transport, data),
+                Message::GameState(data) => self.on_state(transport, data),
+                Message::End => self.disconnected = true,
+                _ => {}
+            }
+        }
+
+        if self.last_seen.unwrap_or(last_seen) + Self::TIMEOUT <= now {
+            self.disconnected = true;
+            return Status::Disconnected;
+        }
+        self.flush(now, transport);
+        if now >= self.next_ping {
+            self.next_ping = now + Self::PING_INTERVAL;
+            // 对方还没有同意再来一局时一直请求
+            if self.rematch.0 {
+                self.send(transport, Message::GameState(&[REMATCH, self.round]));
+            } else {
+                self.send(transport, Message::Ping);
+            }
+        }
+        self.status()
+    }
+
+    /// 离开对局,通知对方
+    pub fn leave<T: Transport>(&mut self, transport: &mut T) {
+        self.send(transport, Message::End);
+        transport.remove_peer(&self.peer.address);
+    }
+
+    /// 走完一步之后更新步数和轮到谁
+    fn apply(&mut self, player: Player, mv: u8) {
+        self.rules.apply(player, mv);
+        self.moves = self.moves.wrapping_add(1);
+        self.turn = self.rules.next(player);
+    }
+
+    /// 到时间时重发没有确认的一步
+    fn flush<T: Transport>(&mut self, now: u64, transport: &mut T) {
+        let Some((no, mv, hash)) = self.pending else {
+            return;
+        };
+        if now < self.next_retry {
+            return;
+        }
+        self.next_retry = now + Self::RETRY_INTERVAL;
+        let [lo, hi] = no.to_le_bytes();
+        let [h0, h1] = hash.to_le_bytes();
+        let round = self.round;
+        self.send(
+            transport,
+            Message::Input(&[MOVE, round, lo, hi, mv, h0, h1]),
+        );
+    }
+
+    fn on_move<T: Transport>(&mut self, transport: &mut T, data: &[u8]) {
+        let &[MOVE, round, lo, hi, mv, h0, h1] = data else {
+            return;
+        };
+        if !self.same_round(round) {
+            return;
+        }
+        let no = u16::from_le_bytes([lo, hi]);
+        let expected = self.moves.wrapping_add(1);
+        if no == expected {
+            let player = self.me.other();
+            if self.turn != player || !self.rules.is_valid(player, mv) {
+                // 双方的局面已经不一致
+                self.send(transport, Message::GameState(&[SYNC_REQUEST, self.round]));
+                return;
+            }
+            // 对方已经走了下一步,说明收到了自己的上一步
+            self.pending = None;
+            self.apply(player, mv);
+            if self.hash() != u16::from_le_bytes([h0, h1]) {
+                self.send(transport, Message::GameState(&[SYNC_REQUEST, self.round]));
+            }
+        } else if is_newer(no, expected) {
+            // 中间漏掉了几步
+            self.send(transport, Message::GameState(&[SYNC_REQUEST, self.round]));
+            return;
+        }
+        // 重复收到的也要确认,可能是对方没有收到确认
+        self.send(transport, Message::GameState(&[ACK, round, lo, hi]));
+    }
+
+    fn on_state<T: Transport>(&mut self, transport: &mut T, data: &[u8]) {
+        match data {
+            [REMATCH, round] => self.on_rematch(transport, *round),
+            // 其他局的消息
+            [_, round, ..] if !self.same_round(*round) => {}
+            [ACK, _, lo, hi] => {
+                let no = u16::from_le_bytes([*lo, *hi]);
+                if self.pending.is_some_and(|(p, _, _)| !is_newer(p, no)) {
+                    self.pending = None;
+                }
+            }
+            [SYNC_REQUEST, _] => self.sync(transport),
+            [SYNC, _, lo, hi, turn, starter, state @ ..] => {
+                let no = u16::from_le_bytes([*lo, *hi]);
+                // 以步数多的一方为准
+                if is_newer(self.moves, no) {
+                    return;
+                }
+                let (Some(turn), Some(starter), Some(rules)) = (
+                    Player::from_u8(*turn),
+                    Player::from_u8(*starter),
+                    R::decode(state),
+                ) else {
+                    return;
+                };
+                self.rules = rules;
+                self.moves = no;
+                self.turn = turn;
+                self.starter = starter;
+                if self.pending.is_some_and(|(p, _, _)| !is_newer(p, no)) {
+                    self.pending = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 对方请求结束某一局,再来一局
+    fn on_rematch<T: Transport>(&mut self, transport: &mut T, round: u8) {
+        if round == self.round {
+            if self.status() != Status::Playing {
+                self.rematch.1 = true;
+                self.try_rematch();
+            }
+        } else if round == self.round.wrapping_sub(1) {
+            // 自己已经开始了新的一局,对方没有收到同意,发送这一局的局面让对方跟上
+            self.sync(transport);
+        }
+    }
+
+    /// 消息是否属于这一局
+    ///
+    /// 自己在等再来一局时收到下一局的消息,说明对方已经同意并且开始了新的一局
+    fn same_round(&mut self, round: u8) -> bool {
+        if round == self.round.wrapping_add(1) && self.rematch.0 {
+            self.rematch.1 = true;
+            self.try_rematch();
+        }
+        round == self.round
+    }
+
+    /// 发送完整的局面
+    fn sync<T: Transport>(&mut self, transport: &mut T) {
+        let mut buf = [0u8; MAX_PAYLOAD_LEN];
+        let [lo, hi] = self.moves.to_le_bytes();
+        buf[..6].copy_from_slice(&[
+            SYNC,
+            self.round,
+            lo,
+            hi,
+            self.turn.to_u8(),
+            self.starter.to_u8(),
+        ]);
+        let len = 6 + self.rules.encode(&mut buf[6..]);
+        self.send(transport, Message::GameState(&buf[..len]));
+    }
+
+    /// 双方都想再来一局时重新开始,换对方先走
+    fn try_rematch(&mut self) {
+        if self.rematch != (true, true) || self.status() == Status::Playing {
+            return;
+        }
+        self.rematch = (false, false);
+        self.rules = R::default();
+        self.starter = self.starter.other();
+        self.round = self.round.wrapping_add(1);
+        self.turn = self.starter;
+        self.moves = 0;
+        self.pending = None;
+    }
+
+    /// 当前局面的校验码
+    fn hash(&self) -> u16 {
+        let mut buf = [0u8; MAX_PAYLOAD_LEN];
+        buf[0] = self.turn.to_u8();
+        let len = 1 + self.rules.encode(&mut buf[1..]);
+        crc16(&buf[..len])
+    }
+
+    fn send<T: Transport>(&mut self, transport: &mut T, message: Message) {
+        let seq = self.peer.seq;
+        self.peer.seq = self.peer.seq.wrapping_add(1);
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        if let Ok(len) = Packet::new(self.game, seq, message).encode(&mut buf) {
+            transport.send(&self.peer.address, &buf[..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lobby::Address, loopback::Loopback};
+
+    const A: Address = [0xa; 6];
+    const B: Address = [0xb; 6];
+    const GAME: u8 = 0x02;
+
+    /// 取石子:轮流取走一到三颗,取走最后一颗的一方获胜
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    struct Nim {
+        taken: u8,
+        last: Option<Player>,
+    }
+
+    impl Nim {
+        const STONES: u8 = 7;
+    }
+
+    impl Rules for Nim {
+        fn is_valid(&self, _player: Player, mv: u8) -> bool {
+            (1..=3).contains(&mv) && self.taken + mv <= Self::STONES
+        }
+
+        fn apply(&mut self, player: Player, mv: u8) {
+            self.taken += mv;
+            self.last = Some(player);
+        }
+
+        fn outcome(&self) -> Option<Outcome> {
+            (self.taken == Self::STONES).then(|| Outcome::Win(self.last.unwrap()))
+        }
+
+        fn encode(&self, buf: &mut [u8]) -> usize {
+            buf[0] = self.taken;
+            buf[1] = self.last.map_or(2, Player::to_u8);
+            2
+        }
+
+        fn decode(buf: &[u8]) -> Option<Self> {
+            let &[taken, last] = buf else {
+                return None;
+            };
+            let last = match last {
+                2 => None,
+                v => Some(Player::from_u8(v)?),
+            };
+            Some(Self { taken, last })
+        }
+    }
+
+    struct Table {
+        a: Session<Nim>,
+        ta: Loopback,
+        b: Session<Nim>,
+        tb: Loopback,
+        now: u64,
+    }
+
+    impl Table {
+        /// 已经配对的两个小方,A 先手
+        fn new() -> Self {
+            let (mut ta, mut tb) = Loopback::pair(A, B);
+            ta.add_peer(&B);
+            tb.add_peer(&A);
+            let peer = |address, first, seq: u16, peer_seq| Peer {
+                address,
+                first,
+                seq,
+                peer_seq,
+            };
+            Self {
+                a: Session::new(GAME, peer(B, true, 100, 199)),
+                ta,
+                b: Session::new(GAME, peer(A, false, 200, 99)),
+                tb,
+                now: 0,
+            }
+        }
+
+        /// 双方轮流推进一段时间
+        fn run(&mut self, ms: u64) -> (Status, Status) {
+            let end = self.now + ms;
+            let mut status = (Status::Playing, Status::Playing);
+            while self.now <= end {
+                status = (
+                    self.a.poll(self.now, &mut self.ta),
+                    self.b.poll(self.now, &mut self.tb),
+                );
+                self.now += 50;
+            }
+            status
+        }
+
+        /// 轮到的一方走一步,然后推进到对方收到
+        fn play(&mut self, mv: u8) {
+            let played = if self.a.my_turn() {
+                self.a.play(self.now, &mut self.ta, mv)
+            } else {
+                self.b.play(self.now, &mut self.tb, mv)
+            };
+            assert!(played);
+            self.run(100);
+        }
+
+        fn assert_in_sync(&self) {
+            assert_eq!(self.a.rules(), self.b.rules());
+            assert_eq!(self.a.turn(), self.b.turn());
+            assert_eq!(self.a.moves, self.b.moves);
+            assert_eq!(self.a.round, self.b.round);
+        }
+
+        /// 下完一局,A 获胜
+        fn finish(&mut self) {
+            for mv in [1, 1, 1, 1, 3] {
+                self.play(mv);
+            }
+            let win = Status::Over(Outcome::Win(Player::First));
+            assert_eq!(self.run(100), (win, win));
+        }
+
+        /// 双方都请求再来一局
+        fn rematch(&mut self) {
+            self.a.request_rematch(&mut self.ta);
+            self.b.request_rematch(&mut self.tb);
+            assert_eq!(self.run(100), (Status::Playing, Status::Playing));
+        }
+    }
+
+    #[test]
+    fn play_a_game() {
+        let mut t = Table::new();
+        assert!(t.a.my_turn());
+        assert!(!t.b.my_turn());
+        // 没有轮到自己不能走,不合法的着法也不能走
+        assert!(!t.b.play(0, &mut t.tb, 1));
+        assert!(!t.a.play(0, &mut t.ta, 4));
+        t.play(3);
+        t.assert_in_sync();
+        assert!(t.b.my_turn());
+        t.play(3);
+        t.play(1);
+        t.assert_in_sync();
+        let win = Status::Over(Outcome::Win(Player::First));
+        assert_eq!(t.run(100), (win, win));
+        assert!(!t.a.my_turn() && !t.b.my_turn());
+    }
+
+    #[test]
+    fn lost_move_is_retried() {
+        let mut t = Table::new();
+        t.ta.drop_next(1);
+        assert!(t.a.play(0, &mut t.ta, 2));
+        t.b.poll(0, &mut t.tb);
+        assert_eq!(t.b.rules(), &Nim::default());
+        t.run(Session::<Nim>::RETRY_INTERVAL + 100);
+        t.assert_in_sync();
+        assert!(t.b.my_turn());
+        assert_eq!(t.a.pending, None);
+    }
+
+    #[test]
+    fn lost_ack_is_retried() {
+        let mut t = Table::new();
+        assert!(t.a.play(0, &mut t.ta, 2));
+        // B 收到之后的确认丢了
+        t.tb.drop_next(1);
+        t.b.poll(0, &mut t.tb);
+        t.a.poll(0, &mut t.ta);
+        assert!(t.a.pending.is_some());
+        // 重复收到的一步不会再走一次
+        t.run(Session::<Nim>::RETRY_INTERVAL + 100);
+        t.assert_in_sync();
+        assert_eq!(t.b.rules().taken, 2);
+        assert_eq!(t.a.pending, None);
+    }
+
+    #[test]
+    fn diverged_state_is_synced() {
+        let mut t = Table::new();
+        t.play(1);
+        // B 的局面出错,下一步的校验码对不上
+        t.b.rules.taken = 5;
+        t.b.play(t.now, &mut t.tb, 1);
+        t.run(100);
+        t.assert_in_sync();
+        // 步数相同时请求同步的一方以对方的局面为准
+        assert_eq!(t.a.rules().taken, 6);
+    }
+
+    #[test]
+    fn rematch_swaps_starter() {
+        let mut t = Table::new();
+        t.finish();
+        // 只有一方请求时不会开始
+        t.a.request_rematch(&mut t.ta);
+        let win = Status::Over(Outcome::Win(Player::First));
+        assert_eq!(t.run(100), (win, win));
+        t.b.request_rematch(&mut t.tb);
+        assert_eq!(t.run(100), (Status::Playing, Status::Playing));
+        t.assert_in_sync();
+        assert_eq!(t.a.round, 1);
+        assert!(t.b.my_turn());
+    }
+
+    #[test]
+    fn lost_rematch_is_retried() {
+        let mut t = Table::new();
+        t.finish();
+        t.a.request_rematch(&mut t.ta);
+        t.run(100);
+        // B 同意之后开始了新的一局并且走了一步,A 没有收到同意
+        t.tb.drop_next(1);
+        t.b.request_rematch(&mut t.tb);
+        assert!(t.b.play(t.now, &mut t.tb, 2));
+        t.run(Session::<Nim>::PING_INTERVAL + Session::<Nim>::RETRY_INTERVAL * 2);
+        t.assert_in_sync();
+        assert_eq!(t.a.rules().taken, 2);
+        assert!(t.a.my_turn());
+    }
+
+    #[test]
+    fn stale_frames_after_rematch_are_dropped() {
+        let mut t = Table::new();
+        t.finish();
+        let mut state = [0u8; 2];
+        t.a.rules().encode(&mut state);
+        t.rematch();
+        t.play(1);
+        t.assert_in_sync();
+        assert!(t.a.my_turn());
+
+        // 上一局迟到的一步,步数正好是这一局 B 等着的下一步
+        let hash = t.a.hash().to_le_bytes();
+        t.a.send(
+            &mut t.ta,
+            Message::Input(&[MOVE, 0, 2, 0, 3, hash[0], hash[1]]),
+        );
+        // 上一局迟到的完整局面,步数比这一局多
+        let [s0, s1] = state;
+        t.a.send(
+            &mut t.ta,
+            Message::GameState(&[SYNC, 0, 5, 0, 1, 0, s0, s1]),
+        );
+        // 上一局迟到的再来一局请求
+        t.a.send(&mut t.ta, Message::GameState(&[REMATCH, 0]));
+        t.b.poll(t.now, &mut t.tb);
+
+        assert_eq!(t.b.status(), Status::Playing);
+        assert_eq!(t.b.rules().taken, 1);
+        assert_eq!(t.b.moves, 1);
+        assert!(!t.b.my_turn());
+        assert!(!t.b.rematch.1);
+        t.run(100);
+        t.assert_in_sync();
+        assert_eq!(t.b.rules().taken, 1);
+    }
+
+    #[test]
+    fn silent_peer_disconnects() {
+        let mut t = Table::new();
+        t.a.poll(0, &mut t.ta);
+        let timeout = Session::<Nim>::TIMEOUT;
+        assert_eq!(t.a.poll(timeout - 1, &mut t.ta), Status::Playing);
+        assert_eq!(t.a.poll(timeout, &mut t.ta), Status::Disconnected);
+    }
+}
@@ -0,0 +1,504 @@
+//! 设置和记录的存储
+//!
+//! 需要掉电保存的数据分成几条 [`Record`],按下面的格式编码之后由 [`Store`] 写入 [`log`],多字节的字段都是小端存储:
+//!
+//! ```text
+//! 58464342  0100  1300  xx..xx  xxxxxxxx
+//! ^         ^     ^     ^       ^
+//! |         |     |     |       CRC-32,校验前面所有的字节
+//! |         |     |     负载
+//! |         |     负载的长度
+//! |         版本
+//! 魔数 "XFCB"
+//! ```
+//!
+//! 以前的固件直接把 [`Profile`] 写在 flash 固定的地址,日志中还没有数据时从这个地址读取,按旧的格式迁移.
+//! 格式变化时版本加一,在 [`Record::decode`] 中把旧版本的负载迁移到新的版本.
+
+#![no_std]
+#![warn(missing_docs)]
+
+pub mod log;
+pub mod mem;
+pub mod scores;
+mod stats;
+
+pub use scores::Scores;
+pub use stats::{Achievement, Stats};
+
+use embedded_storage::nor_flash::NorFlash;
+use log::Log;
+
+/// 魔数,用来区分旧的格式和空白的 flash
+pub const MAGIC: [u8; 4] = *b"XFCB";
+/// 头的长度
+pub const HEADER_LEN: usize = 8;
+/// 校验码的长度
+pub const CRC_LEN: usize = 4;
+/// 占用的最大字节数,负载不能超过 MAX_LEN - HEADER_LEN - CRC_LEN
+pub const MAX_LEN: usize = log::MAX_VALUE_LEN;
+
+/// 最早的格式占用的字节数
+const LEGACY_LEN: usize = 8;
+
+/// 日志中的键
+pub mod key {
+    /// 设置和记录
+    pub const PROFILE: u8 = 0x00;
+    /// 统计和成就
+    pub const STATS: u8 = 0x01;
+    /// 排行榜
+    pub const SCORES: u8 = 0x02;
+}
+
+/// 读写的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<E> {
+    /// 存储读写失败
+    Storage(E),
+    /// 魔数不对,不是 [`Store::save`] 写入的数据
+    Magic,
+    /// 校验失败,数据损坏
+    Crc,
+    /// 长度不对
+    Length,
+    /// 不认识的版本,可能是更新的固件写入的
+    Version(u16),
+    /// 无效的键
+    Key,
+    /// 日志的一个扇区放不下所有的值
+    Full,
+}
+
+/// 保存在日志中的一条记录
+pub trait Record: Default {
+    /// 日志中的键
+    const KEY: u8;
+    /// 当前的版本
+    const VERSION: u16;
+
+    /// 编码成当前版本的负载
+    fn encode(&self, w: &mut Writer);
+
+    /// 按版本解码负载,旧版本的负载在这里迁移到当前版本,长度不对时返回 None
+    fn decode(version: u16, r: &mut Reader) -> Option<Self>;
+
+    /// 日志中没有这条记录时,从以前的固件写入的数据迁移,没有时返回 None
+    fn migrate(_legacy: &[u8; MAX_LEN]) -> Option<Self> {
+        None
+    }
+}
+
+/// 设置和记录
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    /// 声音是否打开
+    pub sound: bool,
+    /// 音效主题,0xff 表示没有设置过
+    pub theme: u8,
+    /// 音量,0xff 表示没有设置过
+    pub volume: u8,
+    /// 点阵的亮度
+    pub brightness: u8,
+    /// 贪吃蛇的最高分,只用来迁移到 [`Scores`]
+    pub snake_highest: u16,
+    /// 方块人的最高分,只用来迁移到 [`Scores`]
+    pub cube_man_highest: u16,
+    /// 躲避方块的最高分,只用来迁移到 [`Scores`]
+    pub dodge_cube_highest: u16,
+    /// 麦克风噪声校准,没有校准过时全为 0xff
+    pub spectrum_calibration: [u8; 8],
+    /// 推箱子已经解锁的关卡数
+    pub sokoban_levels: u8,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            sound: true,
+            theme: 0xff,
+            volume: 0xff,
+            brightness: 1,
+            snake_highest: 0,
+            cube_man_highest: 0,
+            dodge_cube_highest: 0,
+            spectrum_calibration: [0xff; 8],
+            sokoban_levels: 0,
+        }
+    }
+}
+
+impl Profile {
+    /// 从最早的格式迁移
+    ///
+    /// 最早的固件读写 8 个字节,用一个字节存放最高分:0x00 贪吃蛇、0x01 方块人,其他的字节没有使用.
+    /// 没有写过的最高分为 0xff,当作 0
+    fn from_legacy(bytes: &[u8; LEGACY_LEN]) -> Self {
+        let highest = |offset: usize| match bytes[offset] {
+            u8::MAX => 0,
+            v => v as u16,
+        };
+        Self {
+            snake_highest: highest(0x00),
+            cube_man_highest: highest(0x01),
+            ..Self::default()
+        }
+    }
+}
+
+impl Record for Profile {
+    const KEY: u8 = key::PROFILE;
+    const VERSION: u16 = 1;
+
+    fn encode(&self, w: &mut Writer) {
+        w.u8(self.sound as u8);
+        w.u8(self.theme);
+        w.u8(self.volume);
+        w.u8(self.brightness);
+        w.u16(self.snake_highest);
+        w.u16(self.cube_man_highest);
+        w.u16(self.dodge_cube_highest);
+        w.bytes(&self.spectrum_calibration);
+        w.u8(self.sokoban_levels);
+    }
+
+    fn decode(version: u16, r: &mut Reader) -> Option<Self> {
+        match version {
+            1 => Some(Profile {
+                sound: r.u8()? != 0,
+                theme: r.u8()?,
+                volume: r.u8()?,
+                brightness: r.u8()?,
+                snake_highest: r.u16()?,
+                cube_man_highest: r.u16()?,
+                dodge_cube_highest: r.u16()?,
+                spectrum_calibration: r.array()?,
+                sokoban_levels: r.u8()?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// 以前的固件写在固定的地址,可能是带魔数的格式,也可能是最早的格式
+    fn migrate(legacy: &[u8; MAX_LEN]) -> Option<Self> {
+        if legacy[..4] == MAGIC {
+            return parse::<Self, ()>(legacy).ok();
+        }
+        let legacy: &[u8; LEGACY_LEN] = legacy[..LEGACY_LEN].try_into().unwrap();
+        if legacy.iter().all(|b| *b == 0xff) {
+            return None;
+        }
+        Some(Self::from_legacy(legacy))
+    }
+}
+
+/// 按顺序读取负载
+pub struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl Reader<'_> {
+    /// 读取若干个字节
+    pub fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, tail) = self.buf.split_first_chunk()?;
+        self.buf = tail;
+        Some(*head)
+    }
+
+    /// 读取 u8
+    pub fn u8(&mut self) -> Option<u8> {
+        self.array::<1>().map(|[v]| v)
+    }
+
+    /// 读取 u16
+    pub fn u16(&mut self) -> Option<u16> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    /// 读取 u32
+    pub fn u32(&mut self) -> Option<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+}
+
+/// 按顺序写入负载
+pub struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    /// 写入若干个字节,超出负载的最大长度时 panic
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    /// 写入 u8
+    pub fn u8(&mut self, v: u8) {
+        self.bytes(&[v]);
+    }
+
+    /// 写入 u16
+    pub fn u16(&mut self, v: u16) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    /// 写入 u32
+    pub fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+}
+
+/// 解码 [`Store::save`] 写入的数据
+fn parse<R: Record, E>(buf: &[u8]) -> Result<R, Error<E>> {
+    if buf.len() < HEADER_LEN {
+        return Err(Error::Length);
+    }
+    if buf[..4] != MAGIC {
+        return Err(Error::Magic);
+    }
+    let version = u16::from_le_bytes([buf[4], buf[5]]);
+    let len = u16::from_le_bytes([buf[6], buf[7]]) as usize;
+    let end = HEADER_LEN + len;
+    if end + CRC_LEN > buf.len() {
+        return Err(Error::Length);
+    }
+    let crc = u32::from_le_bytes(buf[end..end + CRC_LEN].try_into().unwrap());
+    if crc32(&buf[..end]) != crc {
+        return Err(Error::Crc);
+    }
+    if version == 0 || version > R::VERSION {
+        return Err(Error::Version(version));
+    }
+    let mut r = Reader {
+        buf: &buf[HEADER_LEN..end],
+    };
+    match R::decode(version, &mut r) {
+        Some(record) if r.buf.is_empty() => Ok(record),
+        _ => Err(Error::Length),
+    }
+}
+
+/// 通过 [`Log`] 读写 [`Record`]
+#[derive(Debug)]
+pub struct Store<F> {
+    log: Log<F>,
+    /// 以前的固件写入的地址
+    legacy: u32,
+}
+
+impl<F: NorFlash> Store<F> {
+    /// 日志使用从 base 开始的 sectors 个扇区,legacy 为以前的固件写入的地址
+    pub fn new(flash: F, base: u32, sectors: u32, legacy: u32) -> Result<Self, Error<F::Error>> {
+        Ok(Self {
+            log: Log::new(flash, base, sectors)?,
+            legacy,
+        })
+    }
+
+    /// 读取,日志中没有时从以前的地址迁移,都没有时返回默认值
+    pub fn load<R: Record>(&mut self) -> Result<R, Error<F::Error>> {
+        let mut buf = [0u8; MAX_LEN];
+        if let Some(len) = self.log.get(R::KEY, &mut buf)? {
+            return parse(&buf[..len]);
+        }
+
+        self.log
+            .flash()
+            .read(self.legacy, &mut buf)
+            .map_err(Error::Storage)?;
+        Ok(R::migrate(&buf).unwrap_or_default())
+    }
+
+    /// 按当前版本写入日志
+    pub fn save<R: Record>(&mut self, record: &R) -> Result<(), Error<F::Error>> {
+        let mut buf = [0u8; MAX_LEN];
+        let mut w = Writer {
+            buf: &mut buf[HEADER_LEN..MAX_LEN - CRC_LEN],
+            len: 0,
+        };
+        record.encode(&mut w);
+        let len = w.len;
+        buf[..4].copy_from_slice(&MAGIC);
+        buf[4..6].copy_from_slice(&R::VERSION.to_le_bytes());
+        buf[6..8].copy_from_slice(&(len as u16).to_le_bytes());
+        let end = HEADER_LEN + len;
+        let crc = crc32(&buf[..end]);
+        buf[end..end + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        self.log.set(R::KEY, &buf[..end + CRC_LEN])
+    }
+
+    /// 取出 flash
+    pub fn into_inner(self) -> F {
+        self.log.into_inner()
+    }
+}
+
+/// CRC-32/ISO-HDLC,和 zip 使用的一样
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xffff_ffff;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 > 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mem::MemFlash;
+
+    type Flash = MemFlash<{ 5 * 4096 }>;
+
+    /// 以前的固件写入的地址
+    const LEGACY: u32 = 0x100;
+
+    fn store(flash: Flash) -> Store<Flash> {
+        Store::new(flash, 0x1000, 4, LEGACY).unwrap()
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            sound: false,
+            theme: 2,
+            volume: 1,
+            brightness: 5,
+            snake_highest: 300,
+            cube_man_highest: 42,
+            dodge_cube_highest: 7,
+            spectrum_calibration: [1, 2, 3, 4, 5, 6, 7, 8],
+            sokoban_levels: 1,
+        }
+    }
+
+    /// 保存之后修改日志中的原始数据
+    fn tamper(store: &mut Store<Flash>, f: impl Fn(&mut [u8])) {
+        store.save(&profile()).unwrap();
+        let mut buf = [0u8; MAX_LEN];
+        let len = store.log.get(key::PROFILE, &mut buf).unwrap().unwrap();
+        f(&mut buf[..len]);
+        store.log.set(key::PROFILE, &buf[..len]).unwrap();
+    }
+
+    /// 在以前的地址写入最早的固件的 8 个字节
+    fn legacy(bytes: [u8; LEGACY_LEN]) -> Profile {
+        let mut flash = Flash::new();
+        flash.write(LEGACY, &bytes).unwrap();
+        store(flash).load().unwrap()
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn empty_flash_loads_defaults() {
+        let mut store = store(Flash::new());
+        assert_eq!(store.load::<Profile>(), Ok(Profile::default()));
+        assert_eq!(store.load::<Stats>(), Ok(Stats::default()));
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let mut store = store(Flash::new());
+        let stats = Stats {
+            play_seconds: 3600,
+            achievements: 0b101,
+            ..Stats::default()
+        };
+        store.save(&profile()).unwrap();
+        store.save(&stats).unwrap();
+        assert_eq!(store.load::<Profile>(), Ok(profile()));
+
+        let mut store = self::store(store.into_inner());
+        assert_eq!(store.load::<Profile>(), Ok(profile()));
+        assert_eq!(store.load::<Stats>(), Ok(stats));
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut store = store(Flash::new());
+        tamper(&mut store, |buf| buf[0] = b'Y');
+        assert_eq!(store.load::<Profile>(), Err(Error::Magic));
+    }
+
+    #[test]
+    fn flipped_crc() {
+        let mut store = store(Flash::new());
+        tamper(&mut store, |buf| buf[buf.len() - 1] ^= 0x01);
+        assert_eq!(store.load::<Profile>(), Err(Error::Crc));
+        tamper(&mut store, |buf| buf[HEADER_LEN] ^= 0x80);
+        assert_eq!(store.load::<Profile>(), Err(Error::Crc));
+    }
+
+    #[test]
+    fn newer_version() {
+        let mut store = store(Flash::new());
+        tamper(&mut store, |buf| {
+            buf[4..6].copy_from_slice(&(Profile::VERSION + 1).to_le_bytes());
+            let end = buf.len() - CRC_LEN;
+            let crc = crc32(&buf[..end]);
+            buf[end..].copy_from_slice(&crc.to_le_bytes());
+        });
+        assert_eq!(
+            store.load::<Profile>(),
+            Err(Error::Version(Profile::VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn wrong_length() {
+        let mut store = store(Flash::new());
+        tamper(&mut store, |buf| {
+            buf[6..8].copy_from_slice(&200u16.to_le_bytes());
+        });
+        assert_eq!(store.load::<Profile>(), Err(Error::Length));
+    }
+
+    #[test]
+    fn migrate_baseline_image() {
+        // 只玩过贪吃蛇
+        let p = legacy([0x17, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!((p.snake_highest, p.cube_man_highest), (0x17, 0));
+        // 两个游戏都玩过
+        let p = legacy([0x05, 0x2a, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!((p.snake_highest, p.cube_man_highest), (5, 42));
+        assert_eq!(p.dodge_cube_highest, 0);
+        assert_eq!(p.theme, Profile::default().theme);
+        assert_eq!(p.spectrum_calibration, [0xff; 8]);
+        // 没有写过
+        assert_eq!(legacy([0xff; LEGACY_LEN]), Profile::default());
+    }
+
+    #[test]
+    fn migrate_magic_format() {
+        let mut old = store(Flash::new());
+        old.save(&profile()).unwrap();
+        let mut buf = [0u8; MAX_LEN];
+        let len = old.log.get(key::PROFILE, &mut buf).unwrap().unwrap();
+
+        let mut flash = Flash::new();
+        flash
+            .write(LEGACY, &buf[..len.next_multiple_of(4)])
+            .unwrap();
+        assert_eq!(store(flash).load::<Profile>(), Ok(profile()));
+    }
+
+    #[test]
+    fn log_wins_over_legacy() {
+        let mut flash = Flash::new();
+        flash.write(LEGACY, &[0x05, 0x2a, 0xff, 0xff]).unwrap();
+        let mut store = store(flash);
+        store.save(&profile()).unwrap();
+        assert_eq!(store.load::<Profile>(), Ok(profile()));
+    }
+}
@@ -0,0 +1,508 @@
+//! 只追加的键值日志
+//!
+//! 数据分布在若干个扇区中,同一时间只有一个扇区在使用,每次写入都追加在后面,不覆盖原来的数据.
+//! 扇区写满时把每个键最新的值复制到下一个扇区,依次轮换,每个扇区擦除的次数大致相同.
+//!
+//! 扇区的格式:
+//!
+//! ```text
+//! 5846434c  05000000  xxxxxxxx  记录  记录  ...  ffff..
+//! ^         ^         ^
+//! |         |         CRC-32,校验魔数和序号
+//! |         序号,每次换扇区加一,序号最大的扇区在使用
+//! 魔数 "XFCL"
+//! ```
+//!
+//! 记录的格式,长度按 flash 的写入单位对齐,不足的部分为 0xff:
+//!
+//! ```text
+//! 00  13  xx..xx  xxxxxxxx  ffff
+//! ^   ^   ^       ^         ^
+//! |   |   |       |         对齐
+//! |   |   |       CRC-32,校验前面所有的字节
+//! |   |   值
+//! |   值的长度
+//! 键,0xff 表示后面没有记录了
+//! ```
+//!
+//! 掉电时最多有一次写入不完整:
+//!
+//! - 写记录时掉电,记录的校验失败,这个扇区不再追加,下次写入时换扇区;
+//! - 换扇区时先复制所有的记录,最后才写扇区的头,掉电时新的扇区没有头,仍然使用原来的扇区.
+
+use crate::{crc32, Error};
+use embedded_storage::nor_flash::NorFlash;
+
+/// 扇区的魔数
+const MAGIC: [u8; 4] = *b"XFCL";
+/// 扇区头的长度
+const SECTOR_HEADER_LEN: usize = 12;
+/// 记录头的长度
+const RECORD_HEADER_LEN: usize = 2;
+/// 校验码的长度
+const CRC_LEN: usize = 4;
+/// 值的最大长度
+pub const MAX_VALUE_LEN: usize = 128;
+/// 最多可以使用的键,键为 0..MAX_KEYS
+pub const MAX_KEYS: usize = 32;
+/// 记录对齐后的最大长度,flash 的写入单位不超过 16 字节
+const MAX_RECORD_LEN: usize = RECORD_HEADER_LEN + MAX_VALUE_LEN + CRC_LEN + 16;
+/// 索引中表示没有这个键
+const NONE: u32 = u32::MAX;
+
+/// 记录的键和值
+type Record<'a> = (u8, &'a [u8]);
+
+/// 只追加的键值日志
+#[derive(Debug)]
+pub struct Log<F> {
+    flash: F,
+    /// 第一个扇区的地址
+    base: u32,
+    /// 扇区数
+    sectors: u32,
+    /// 正在使用的扇区
+    active: u32,
+    /// 正在使用的扇区的序号
+    seq: u32,
+    /// 下一条记录的地址
+    pos: u32,
+    /// 每个键最新的记录的地址
+    index: [u32; MAX_KEYS],
+}
+
+impl<F: NorFlash> Log<F> {
+    /// 挂载从 base 开始的 sectors 个扇区,没有可用的扇区时格式化第一个扇区
+    pub fn new(flash: F, base: u32, sectors: u32) -> Result<Self, Error<F::Error>> {
+        assert!(sectors >= 2, "至少需要两个扇区");
+        assert!(F::WRITE_SIZE <= 16 && SECTOR_HEADER_LEN.is_multiple_of(F::WRITE_SIZE));
+        let mut log = Self {
+            flash,
+            base,
+            sectors,
+            active: 0,
+            seq: 0,
+            pos: 0,
+            index: [NONE; MAX_KEYS],
+        };
+
+        // 序号最大的扇区在使用
+        let mut active = None;
+        for sector in 0..sectors {
+            if let Some(seq) = log.sector_seq(sector)? {
+                if active.is_none_or(|(_, s)| seq > s) {
+                    active = Some((sector, seq));
+                }
+            }
+        }
+        match active {
+            Some((sector, seq)) => {
+                log.active = sector;
+                log.seq = seq;
+                log.scan()?;
+            }
+            None => {
+                log.erase(0)?;
+                log.write_sector_header(0, 1)?;
+                log.active = 0;
+                log.seq = 1;
+                log.pos = log.sector_addr(0) + SECTOR_HEADER_LEN as u32;
+            }
+        }
+        Ok(log)
+    }
+
+    /// 读取键最新的值,返回值的长度,没有这个键时返回 None
+    pub fn get(&mut self, key: u8, buf: &mut [u8]) -> Result<Option<usize>, Error<F::Error>> {
+        let addr = *self.index.get(key as usize).ok_or(Error::Key)?;
+        if addr == NONE {
+            return Ok(None);
+        }
+        let mut record = [0u8; MAX_RECORD_LEN];
+        let (_, value) = self.read_record(addr, &mut record)?.ok_or(Error::Crc)?;
+        let len = value.len();
+        buf.get_mut(..len)
+            .ok_or(Error::Length)?
+            .copy_from_slice(value);
+        Ok(Some(len))
+    }
+
+    /// 写入键的值,和原来的值一样时不写入
+    pub fn set(&mut self, key: u8, value: &[u8]) -> Result<(), Error<F::Error>> {
+        if key as usize >= MAX_KEYS {
+            return Err(Error::Key);
+        }
+        if value.len() > MAX_VALUE_LEN {
+            return Err(Error::Length);
+        }
+        let mut old = [0u8; MAX_VALUE_LEN];
+        if let Some(len) = self.get(key, &mut old)? {
+            if old[..len] == *value {
+                return Ok(());
+            }
+        }
+
+        if self.pos + Self::record_len(value.len()) > self.sector_end(self.active) {
+            self.compact(key, value)
+        } else {
+            self.append(key, value)
+        }
+    }
+
+    /// 直接访问 flash
+    pub fn flash(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    /// 取出 flash
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    /// 扇区的地址
+    fn sector_addr(&self, sector: u32) -> u32 {
+        self.base + sector * F::ERASE_SIZE as u32
+    }
+
+    /// 扇区结束的地址
+    fn sector_end(&self, sector: u32) -> u32 {
+        self.sector_addr(sector) + F::ERASE_SIZE as u32
+    }
+
+    /// 记录对齐后的长度
+    fn record_len(len: usize) -> u32 {
+        let len = RECORD_HEADER_LEN + len + CRC_LEN;
+        len.next_multiple_of(F::WRITE_SIZE) as u32
+    }
+
+    /// 读取扇区头,没有头或者头不完整时返回 None
+    fn sector_seq(&mut self, sector: u32) -> Result<Option<u32>, Error<F::Error>> {
+        let mut header = [0u8; SECTOR_HEADER_LEN];
+        self.flash
+            .read(self.sector_addr(sector), &mut header)
+            .map_err(Error::Storage)?;
+        let crc = u32::from_le_bytes(header[8..].try_into().unwrap());
+        if header[..4] != MAGIC || crc32(&header[..8]) != crc {
+            return Ok(None);
+        }
+        Ok(Some(u32::from_le_bytes(header[4..8].try_into().unwrap())))
+    }
+
+    fn write_sector_header(&mut self, sector: u32, seq: u32) -> Result<(), Error<F::Error>> {
+        let mut header = [0u8; SECTOR_HEADER_LEN];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..8].copy_from_slice(&seq.to_le_bytes());
+        let crc = crc32(&header[..8]);
+        header[8..].copy_from_slice(&crc.to_le_bytes());
+        self.flash
+            .write(self.sector_addr(sector), &header)
+            .map_err(Error::Storage)
+    }
+
+    fn erase(&mut self, sector: u32) -> Result<(), Error<F::Error>> {
+        let (from, to) = (self.sector_addr(sector), self.sector_end(sector));
+        self.flash.erase(from, to).map_err(Error::Storage)
+    }
+
+    /// 读取一条记录,返回键和值;后面没有记录时返回 None,记录不完整时返回校验错误
+    fn read_record<'a>(
+        &mut self,
+        addr: u32,
+        buf: &'a mut [u8; MAX_RECORD_LEN],
+    ) -> Result<Option<Record<'a>>, Error<F::Error>> {
+        let end = self.sector_end((addr - self.base) / F::ERASE_SIZE as u32);
+        // 对齐之后至少有一个写入单位,读取时也要按读取单位对齐
+        let head_len = F::WRITE_SIZE
+            .max(RECORD_HEADER_LEN)
+            .next_multiple_of(F::READ_SIZE);
+        if addr + head_len as u32 > end {
+            return Ok(None);
+        }
+        self.flash
+            .read(addr, &mut buf[..head_len])
+            .map_err(Error::Storage)?;
+        let (key, len) = (buf[0], buf[1] as usize);
+        if key == 0xff {
+            return Ok(None);
+        }
+        let record_len = Self::record_len(len);
+        if len > MAX_VALUE_LEN || addr + record_len > end {
+            return Err(Error::Crc);
+        }
+        let record_len = (record_len as usize).next_multiple_of(F::READ_SIZE);
+        self.flash
+            .read(addr, &mut buf[..record_len])
+            .map_err(Error::Storage)?;
+        let data_len = RECORD_HEADER_LEN + len;
+        let crc = u32::from_le_bytes(buf[data_len..data_len + CRC_LEN].try_into().unwrap());
+        if crc32(&buf[..data_len]) != crc {
+            return Err(Error::Crc);
+        }
+        Ok(Some((key, &buf[RECORD_HEADER_LEN..data_len])))
+    }
+
+    /// 扫描正在使用的扇区,建立索引,找到下一条记录的地址
+    fn scan(&mut self) -> Result<(), Error<F::Error>> {
+        self.index = [NONE; MAX_KEYS];
+        let end = self.sector_end(self.active);
+        let mut addr = self.sector_addr(self.active) + SECTOR_HEADER_LEN as u32;
+        let mut record = [0u8; MAX_RECORD_LEN];
+        loop {
+            match self.read_record(addr, &mut record) {
+                Ok(Some((key, value))) => {
+                    if let Some(i) = self.index.get_mut(key as usize) {
+                        *i = addr;
+                    }
+                    addr += Self::record_len(value.len());
+                }
+                Ok(None) => break,
+                // 写入时掉电,后面的空间不能再写,下次写入时换扇区
+                Err(Error::Crc) => {
+                    addr = end;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.pos = addr;
+        Ok(())
+    }
+
+    /// 在 addr 写入一条记录,返回记录的长度
+    fn write_record(&mut self, addr: u32, key: u8, value: &[u8]) -> Result<u32, Error<F::Error>> {
+        let mut record = [0xffu8; MAX_RECORD_LEN];
+        let data_len = RECORD_HEADER_LEN + value.len();
+        record[0] = key;
+        record[1] = value.len() as u8;
+        record[RECORD_HEADER_LEN..data_len].copy_from_slice(value);
+        let crc = crc32(&record[..data_len]);
+        record[data_len..data_len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        let len = Self::record_len(value.len());
+        self.flash
+            .write(addr, &record[..len as usize])
+            .map_err(Error::Storage)?;
+        Ok(len)
+    }
+
+    /// 追加在正在使用的扇区
+    fn append(&mut self, key: u8, value: &[u8]) -> Result<(), Error<F::Error>> {
+        let addr = self.pos;
+        // 先移动地址,写入失败时不会在不完整的记录上再写
+        self.pos = self.sector_end(self.active);
+        self.pos = addr + self.write_record(addr, key, value)?;
+        self.index[key as usize] = addr;
+        Ok(())
+    }
+
+    /// 把每个键最新的值和新的值复制到下一个扇区,最后写扇区头
+    fn compact(&mut self, key: u8, value: &[u8]) -> Result<(), Error<F::Error>> {
+        let next = (self.active + 1) % self.sectors;
+        self.erase(next)?;
+        let end = self.sector_end(next);
+        let mut addr = self.sector_addr(next) + SECTOR_HEADER_LEN as u32;
+        let mut index = [NONE; MAX_KEYS];
+        let mut record = [0u8; MAX_RECORD_LEN];
+        for k in 0..MAX_KEYS as u8 {
+            let old = self.index[k as usize];
+            if k == key || old == NONE {
+                continue;
+            }
+            let (_, v) = self.read_record(old, &mut record)?.ok_or(Error::Crc)?;
+            let mut copy = [0u8; MAX_VALUE_LEN];
+            let copy = &mut copy[..v.len()];
+            copy.copy_from_slice(v);
+            if addr + Self::record_len(copy.len()) > end {
+                return Err(Error::Full);
+            }
+            index[k as usize] = addr;
+            addr += self.write_record(addr, k, copy)?;
+        }
+        if addr + Self::record_len(value.len()) > end {
+            return Err(Error::Full);
+        }
+        index[key as usize] = addr;
+        addr += self.write_record(addr, key, value)?;
+
+        self.write_sector_header(next, self.seq + 1)?;
+        self.active = next;
+        self.seq += 1;
+        self.pos = addr;
+        self.index = index;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::mem::{MemError, MemFlash, PowerCut};
+    use std::{vec, vec::Vec};
+
+    const SECTORS: u32 = 4;
+    type Flash = PowerCut<{ SECTORS as usize * 4096 }>;
+
+    /// 每个键每一代的值,长度各不相同
+    fn value(key: u8, gen: u32) -> Vec<u8> {
+        let len = 1 + (key as usize * 7 + gen as usize) % 40;
+        (0..len)
+            .map(|i| key ^ (gen as u8).wrapping_add(i as u8))
+            .collect()
+    }
+
+    fn mount(flash: Flash) -> Log<Flash> {
+        Log::new(flash, 0, SECTORS).unwrap()
+    }
+
+    fn get(log: &mut Log<Flash>, key: u8) -> Option<Vec<u8>> {
+        let mut buf = [0u8; MAX_VALUE_LEN];
+        let len = log.get(key, &mut buf).unwrap()?;
+        Some(buf[..len].to_vec())
+    }
+
+    /// 写入三个键,返回每个键最新的值
+    fn populate(log: &mut Log<Flash>, gens: u32) -> Vec<Vec<u8>> {
+        let mut values = vec![Vec::new(); 3];
+        for gen in 0..gens {
+            for key in 0..3 {
+                values[key as usize] = value(key, gen);
+                log.set(key, &values[key as usize]).unwrap();
+            }
+        }
+        values
+    }
+
+    /// 在每一个字节掉电,重新挂载之后每个键都是最后写入成功的值,写入中的键是旧的或者新的值
+    fn cut_everywhere(flash: Flash, committed: &[Vec<u8>], key: u8, new: &[u8]) -> usize {
+        for cut in 0.. {
+            let mut log = mount(flash.clone());
+            log.flash().cut_after(cut);
+            let result = log.set(key, new);
+            if result.is_ok() {
+                return cut;
+            }
+            let mut flash = log.into_inner();
+
+            flash.power_on();
+            let mut log = mount(flash);
+            for (k, v) in committed.iter().enumerate() {
+                let got = get(&mut log, k as u8);
+                if k as u8 == key {
+                    assert!(
+                        got.as_deref() == Some(v) || got.as_deref() == Some(new),
+                        "cut at {cut}: key {k} is {got:?}"
+                    );
+                } else {
+                    assert_eq!(got.as_ref(), Some(v), "cut at {cut}: key {k}");
+                }
+            }
+
+            // 掉电之后仍然可以继续写入
+            let next = value(key, 1000);
+            log.set(key, &next).unwrap();
+            let mut log = mount(log.into_inner());
+            assert_eq!(get(&mut log, key), Some(next), "cut at {cut}");
+            for (k, v) in committed.iter().enumerate() {
+                if k as u8 != key {
+                    assert_eq!(get(&mut log, k as u8).as_ref(), Some(v), "cut at {cut}");
+                }
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn set_get_remount() {
+        let mut log = mount(Flash::new(MemFlash::new()));
+        assert_eq!(get(&mut log, 0), None);
+        let values = populate(&mut log, 3);
+        assert_eq!(log.get(MAX_KEYS as u8, &mut [0; 4]), Err(Error::Key));
+
+        let mut log = mount(log.into_inner());
+        for (k, v) in values.iter().enumerate() {
+            assert_eq!(get(&mut log, k as u8).as_ref(), Some(v));
+        }
+    }
+
+    #[test]
+    fn same_value_is_not_written() {
+        let mut log = mount(Flash::new(MemFlash::new()));
+        log.set(0, b"abc").unwrap();
+        let pos = log.pos;
+        log.set(0, b"abc").unwrap();
+        assert_eq!(log.pos, pos);
+    }
+
+    #[test]
+    fn wear_is_spread_across_sectors() {
+        let mut log = mount(Flash::new(MemFlash::new()));
+        for gen in 0..2000 {
+            log.set(gen as u8 % 3, &value(gen as u8 % 3, gen)).unwrap();
+        }
+        let erases = &log.into_inner().flash.erases[..SECTORS as usize];
+        let (min, max) = (erases.iter().min().unwrap(), erases.iter().max().unwrap());
+        assert!(*min > 0 && max - min <= 1, "{erases:?}");
+    }
+
+    #[test]
+    fn power_loss_during_append() {
+        let mut log = mount(Flash::new(MemFlash::new()));
+        let committed = populate(&mut log, 2);
+        for key in 0..3 {
+            let new = value(key, 500);
+            let bytes = cut_everywhere(log.flash().clone(), &committed, key, &new);
+            assert_eq!(bytes as u32, Log::<Flash>::record_len(new.len()));
+        }
+        // 新的键
+        let new = value(5, 500);
+        cut_everywhere(log.into_inner(), &committed, 5, &new);
+    }
+
+    #[test]
+    fn power_loss_during_compact() {
+        let mut log = mount(Flash::new(MemFlash::new()));
+        let mut committed = populate(&mut log, 1);
+        // 写满正在使用的扇区,下一次写入需要换扇区
+        let new = value(1, 500);
+        let mut gen = 1;
+        while log.pos + Log::<Flash>::record_len(new.len()) <= log.sector_end(log.active) {
+            committed[0] = value(0, gen);
+            log.set(0, &committed[0]).unwrap();
+            gen += 1;
+        }
+        let active = log.active;
+
+        let bytes = cut_everywhere(log.flash().clone(), &committed, 1, &new);
+        // 擦除整个扇区,复制三条记录,最后写扇区头
+        assert!(bytes > 4096 + SECTOR_HEADER_LEN);
+
+        let mut log = mount(log.into_inner());
+        log.set(1, &new).unwrap();
+        assert_ne!(log.active, active);
+    }
+
+    #[test]
+    fn failed_compact_keeps_old_sector() {
+        let mut log = mount(Flash::new(MemFlash::new()));
+        let mut committed = populate(&mut log, 1);
+        let new = value(1, 500);
+        while log.pos + Log::<Flash>::record_len(new.len()) <= log.sector_end(log.active) {
+            committed[0] = value(0, log.pos);
+            log.set(0, &committed[0]).unwrap();
+        }
+        let (active, seq) = (log.active, log.seq);
+
+        // 擦除完成,复制记录的途中掉电
+        log.flash().cut_after(4096 + 20);
+        assert_eq!(log.set(1, &new), Err(Error::Storage(MemError::PowerLoss)));
+        let mut flash = log.into_inner();
+        flash.power_on();
+
+        let mut log = mount(flash);
+        assert_eq!((log.active, log.seq), (active, seq));
+        for (k, v) in committed.iter().enumerate() {
+            assert_eq!(get(&mut log, k as u8).as_ref(), Some(v));
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! 内存 flash,用来在电脑上测试读写 flash 的流程
+//!
+//! 和真实的 flash 一样,写入只能把 1 变成 0,擦除之后全为 0xff.
+//! [`PowerCut`] 包装内存 flash,可以指定还能写入多少字节来模拟掉电,之后的写入和擦除都失败,直到重新上电.
+
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// 读写的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemError {
+    /// 越界访问
+    OutOfBounds,
+    /// 没有对齐
+    NotAligned,
+    /// 掉电
+    PowerLoss,
+}
+
+impl NorFlashError for MemError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            MemError::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            MemError::NotAligned => NorFlashErrorKind::NotAligned,
+            MemError::PowerLoss => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// 内存 flash,扇区大小和写入单位与 esp32c3 的 flash 相同
+#[derive(Debug, Clone)]
+pub struct MemFlash<const N: usize> {
+    /// 存储的内容
+    pub data: [u8; N],
+    /// 每个扇区擦除的次数
+    pub erases: [u32; 16],
+}
+
+impl<const N: usize> Default for MemFlash<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> MemFlash<N> {
+    /// 新建擦除过的 flash
+    pub fn new() -> Self {
+        Self {
+            data: [0xff; N],
+            erases: [0; 16],
+        }
+    }
+
+    /// 检查范围和对齐
+    fn range(offset: u32, len: usize, align: usize) -> Result<core::ops::Range<usize>, MemError> {
+        let start = offset as usize;
+        let end = start.checked_add(len).ok_or(MemError::OutOfBounds)?;
+        if end > N {
+            return Err(MemError::OutOfBounds);
+        }
+        if !start.is_multiple_of(align) || !len.is_multiple_of(align) {
+            return Err(MemError::NotAligned);
+        }
+        Ok(start..end)
+    }
+
+    /// 检查擦除的范围,记录每个扇区擦除的次数
+    fn erase_range(&mut self, from: u32, to: u32) -> Result<core::ops::Range<usize>, MemError> {
+        let len = to.checked_sub(from).ok_or(MemError::OutOfBounds)? as usize;
+        let range = Self::range(from, len, Self::ERASE_SIZE)?;
+        for sector in range.clone().step_by(Self::ERASE_SIZE) {
+            if let Some(erases) = self.erases.get_mut(sector / Self::ERASE_SIZE) {
+                *erases += 1;
+            }
+        }
+        Ok(range)
+    }
+
+    /// 依次修改每个字节,预算用完时停止并返回掉电
+    fn program(
+        &mut self,
+        range: core::ops::Range<usize>,
+        mut budget: Option<&mut usize>,
+        f: impl Fn(u8, usize) -> u8,
+    ) -> Result<(), MemError> {
+        for (i, addr) in range.enumerate() {
+            match &mut budget {
+                Some(0) => return Err(MemError::PowerLoss),
+                Some(budget) => **budget -= 1,
+                None => {}
+            }
+            self.data[addr] = f(self.data[addr], i);
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> ErrorType for MemFlash<N> {
+    type Error = MemError;
+}
+
+impl<const N: usize> ReadNorFlash for MemFlash<N> {
+    const READ_SIZE: usize = 4;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let range = Self::range(offset, bytes.len(), Self::READ_SIZE)?;
+        bytes.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> NorFlash for MemFlash<N> {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = 4096;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let range = self.erase_range(from, to)?;
+        self.program(range, None, |_, _| 0xff)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let range = Self::range(offset, bytes.len(), Self::WRITE_SIZE)?;
+        self.program(range, None, |old, i| old & bytes[i])
+    }
+}
+
+/// 会掉电的内存 flash
+///
+/// 按字节写入和擦除,预算用完时停在当前的字节,和写到一半掉电的 flash 一样
+#[derive(Debug, Clone, Default)]
+pub struct PowerCut<const N: usize> {
+    /// 内存 flash
+    pub flash: MemFlash<N>,
+    /// 还能写入或者擦除的字节数,为 None 时不会掉电
+    pub budget: Option<usize>,
+}
+
+impl<const N: usize> PowerCut<N> {
+    /// 包装内存 flash,不会掉电
+    pub fn new(flash: MemFlash<N>) -> Self {
+        Self {
+            flash,
+            budget: None,
+        }
+    }
+
+    /// 再写入或者擦除 n 个字节之后掉电
+    pub fn cut_after(&mut self, n: usize) {
+        self.budget = Some(n);
+    }
+
+    /// 重新上电
+    pub fn power_on(&mut self) {
+        self.budget = None;
+    }
+}
+
+impl<const N: usize> ErrorType for PowerCut<N> {
+    type Error = MemError;
+}
+
+impl<const N: usize> ReadNorFlash for PowerCut<N> {
+    const READ_SIZE: usize = MemFlash::<N>::READ_SIZE;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.flash.read(offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> NorFlash for PowerCut<N> {
+    const WRITE_SIZE: usize = MemFlash::<N>::WRITE_SIZE;
+    const ERASE_SIZE: usize = MemFlash::<N>::ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let range = self.flash.erase_range(from, to)?;
+        self.flash.program(range, self.budget.as_mut(), |_, _| 0xff)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let range = MemFlash::<N>::range(offset, bytes.len(), Self::WRITE_SIZE)?;
+        self.flash
+            .program(range, self.budget.as_mut(), |old, i| old & bytes[i])
+    }
+}
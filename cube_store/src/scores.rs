@@ -0,0 +1,361 @@
+//! 排行榜
+//!
+//! 每个游戏的每种模式一张排行榜,记录前五名的缩写和分数.
+//! 一张排行榜编码之后长度固定,可以直接通过 ESP-NOW 或者网页分享:
+//!
+//! ```text
+//! 10  00  02  414243 2a00  2d2d2d 0700  000000 0000 ...
+//! ^   ^   ^   ^      ^
+//! |   |   |   |      分数
+//! |   |   |   缩写,三个大写字母或者 '-'
+//! |   |   记录数,后面固定五条记录,没有的记录全为 0
+//! |   模式
+//! 游戏代码
+//! ```
+
+use crate::{key, Reader, Record, Writer};
+
+/// 一条记录
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    /// 缩写
+    pub initials: [u8; 3],
+    /// 分数
+    pub score: u16,
+}
+
+/// 一张排行榜,分数从高到低排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Table {
+    /// 游戏代码
+    pub game: u8,
+    /// 模式
+    pub mode: u8,
+    len: u8,
+    entries: [Entry; Table::LEN],
+}
+
+impl Table {
+    /// 最多记录的名次
+    pub const LEN: usize = 5;
+    /// 编码之后的长度
+    pub const ENCODED_LEN: usize = 3 + Self::LEN * 5;
+
+    /// 空的排行榜
+    pub const fn new(game: u8, mode: u8) -> Self {
+        Self {
+            game,
+            mode,
+            len: 0,
+            entries: [Entry {
+                initials: [0; 3],
+                score: 0,
+            }; Self::LEN],
+        }
+    }
+
+    /// 所有的记录,分数从高到低
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries[..self.len as usize]
+    }
+
+    /// 最高分,没有记录时为 0
+    pub fn highest(&self) -> u16 {
+        self.entries().first().map_or(0, |e| e.score)
+    }
+
+    /// 分数能否上榜
+    pub fn qualifies(&self, score: u16) -> bool {
+        score > 0
+            && (self.entries().len() < Self::LEN
+                || self.entries().last().is_some_and(|e| score > e.score))
+    }
+
+    /// 插入一条记录,返回名次,从 0 开始;分数相同时先上榜的排在前面,没有上榜时返回 None
+    pub fn insert(&mut self, initials: [u8; 3], score: u16) -> Option<usize> {
+        if !self.qualifies(score) {
+            return None;
+        }
+        let rank = self
+            .entries()
+            .iter()
+            .position(|e| score > e.score)
+            .unwrap_or(self.len as usize);
+        let len = (self.len as usize + 1).min(Self::LEN);
+        self.entries.copy_within(rank..len - 1, rank + 1);
+        self.entries[rank] = Entry { initials, score };
+        self.len = len as u8;
+        Some(rank)
+    }
+
+    /// 编码,buf 的长度不能小于 [`Table::ENCODED_LEN`]
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[..Self::ENCODED_LEN].fill(0);
+        buf[0] = self.game;
+        buf[1] = self.mode;
+        buf[2] = self.len;
+        for (e, chunk) in self.entries().iter().zip(buf[3..].chunks_mut(5)) {
+            chunk[..3].copy_from_slice(&e.initials);
+            chunk[3..5].copy_from_slice(&e.score.to_le_bytes());
+        }
+    }
+
+    /// 解码,数据无效时返回 None
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let buf = buf.get(..Self::ENCODED_LEN)?;
+        let mut table = Self::new(buf[0], buf[1]);
+        let len = buf[2] as usize;
+        if len > Self::LEN {
+            return None;
+        }
+        for chunk in buf[3..].chunks(5).take(len) {
+            let initials = [chunk[0], chunk[1], chunk[2]];
+            if !initials
+                .iter()
+                .all(|c| c.is_ascii_uppercase() || *c == b'-')
+            {
+                return None;
+            }
+            let score = u16::from_le_bytes([chunk[3], chunk[4]]);
+            // 分数必须从高到低排列
+            if table.entries().last().is_some_and(|e| score > e.score) {
+                return None;
+            }
+            table.entries[table.len as usize] = Entry { initials, score };
+            table.len += 1;
+        }
+        Some(table)
+    }
+}
+
+/// 所有的排行榜
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scores {
+    len: u8,
+    tables: [Table; Scores::LEN],
+}
+
+impl Default for Scores {
+    fn default() -> Self {
+        Self {
+            len: 0,
+            tables: [Table::new(0, 0); Self::LEN],
+        }
+    }
+}
+
+impl Scores {
+    /// 最多的排行榜数,受日志中值的最大长度限制
+    pub const LEN: usize = 4;
+
+    /// 所有的排行榜
+    pub fn tables(&self) -> &[Table] {
+        &self.tables[..self.len as usize]
+    }
+
+    /// 游戏和模式的排行榜,还没有时返回 None
+    pub fn table(&self, game: u8, mode: u8) -> Option<&Table> {
+        self.tables()
+            .iter()
+            .find(|t| t.game == game && t.mode == mode)
+    }
+
+    /// 游戏和模式的排行榜,还没有时新建,排行榜太多时返回 None
+    pub fn table_mut(&mut self, game: u8, mode: u8) -> Option<&mut Table> {
+        let i = match self
+            .tables()
+            .iter()
+            .position(|t| t.game == game && t.mode == mode)
+        {
+            Some(i) => i,
+            None if (self.len as usize) < Self::LEN => {
+                self.tables[self.len as usize] = Table::new(game, mode);
+                self.len += 1;
+                self.len as usize - 1
+            }
+            None => return None,
+        };
+        Some(&mut self.tables[i])
+    }
+}
+
+impl Record for Scores {
+    const KEY: u8 = key::SCORES;
+    const VERSION: u16 = 1;
+
+    fn encode(&self, w: &mut Writer) {
+        w.u8(self.len);
+        let mut buf = [0u8; Table::ENCODED_LEN];
+        for table in self.tables() {
+            table.encode(&mut buf);
+            w.bytes(&buf);
+        }
+    }
+
+    fn decode(version: u16, r: &mut Reader) -> Option<Self> {
+        match version {
+            1 => {
+                let mut scores = Scores::default();
+                let len = r.u8()? as usize;
+                if len > Self::LEN {
+                    return None;
+                }
+                for table in scores.tables.iter_mut().take(len) {
+                    *table = Table::decode(&r.array::<{ Table::ENCODED_LEN }>()?)?;
+                }
+                scores.len = len as u8;
+                Some(scores)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{mem::MemFlash, Store};
+    use std::vec::Vec;
+
+    fn table(scores: &[u16]) -> Table {
+        let mut table = Table::new(0x10, 0);
+        for (i, score) in scores.iter().enumerate() {
+            table.insert([b'A' + i as u8; 3], *score);
+        }
+        table
+    }
+
+    fn scores(table: &Table) -> Vec<u16> {
+        table.entries().iter().map(|e| e.score).collect()
+    }
+
+    #[test]
+    fn empty_table() {
+        let table = Table::new(0x10, 0);
+        assert!(table.entries().is_empty());
+        assert_eq!(table.highest(), 0);
+    }
+
+    #[test]
+    fn qualifies() {
+        let empty = Table::new(0x10, 0);
+        // 0 分不能上榜
+        assert!(!empty.qualifies(0));
+        assert!(empty.qualifies(1));
+        let full = table(&[50, 40, 30, 20, 10]);
+        assert!(!full.qualifies(9));
+        // 和最后一名同分不能挤掉对方
+        assert!(!full.qualifies(10));
+        assert!(full.qualifies(11));
+    }
+
+    #[test]
+    fn insert_keeps_order() {
+        let mut table = Table::new(0x10, 0);
+        assert_eq!(table.insert(*b"AAA", 30), Some(0));
+        assert_eq!(table.insert(*b"BBB", 50), Some(0));
+        assert_eq!(table.insert(*b"CCC", 40), Some(1));
+        assert_eq!(table.insert(*b"DDD", 10), Some(3));
+        assert_eq!(scores(&table), [50, 40, 30, 10]);
+        assert_eq!(table.highest(), 50);
+        assert_eq!(table.entries()[1].initials, *b"CCC");
+    }
+
+    #[test]
+    fn ties_keep_the_earlier_entry_first() {
+        let mut table = table(&[50, 30]);
+        assert_eq!(table.insert(*b"ZZZ", 30), Some(2));
+        assert_eq!(table.insert(*b"YYY", 50), Some(1));
+        let initials: Vec<_> = table.entries().iter().map(|e| e.initials).collect();
+        assert_eq!(initials, [*b"AAA", *b"YYY", *b"BBB", *b"ZZZ"]);
+    }
+
+    #[test]
+    fn full_table_drops_the_lowest() {
+        let mut table = table(&[50, 40, 30, 20, 10]);
+        assert_eq!(table.insert(*b"NEW", 35), Some(2));
+        assert_eq!(scores(&table), [50, 40, 35, 30, 20]);
+        assert_eq!(table.insert(*b"TOP", 60), Some(0));
+        assert_eq!(scores(&table), [60, 50, 40, 35, 30]);
+        // 没有上榜时不变
+        let before = table;
+        assert_eq!(table.insert(*b"LOW", 30), None);
+        assert_eq!(table.insert(*b"NIL", 0), None);
+        assert_eq!(table, before);
+    }
+
+    #[test]
+    fn encode_layout() {
+        let mut table = Table::new(0x10, 0);
+        table.insert(*b"ABC", 42);
+        table.insert(*b"---", 7);
+        let mut buf = [0xaa; Table::ENCODED_LEN];
+        table.encode(&mut buf);
+        let mut expected = [0u8; Table::ENCODED_LEN];
+        expected[..13].copy_from_slice(&[
+            0x10, 0x00, 0x02, b'A', b'B', b'C', 0x2a, 0x00, b'-', b'-', b'-', 0x07, 0x00,
+        ]);
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        for table in [
+            Table::new(0x12, 1),
+            table(&[7]),
+            table(&[900, 800, 700, 600, 500]),
+        ] {
+            let mut buf = [0u8; Table::ENCODED_LEN];
+            table.encode(&mut buf);
+            assert_eq!(Table::decode(&buf), Some(table));
+        }
+    }
+
+    #[test]
+    fn decode_rejects_invalid_data() {
+        let mut buf = [0u8; Table::ENCODED_LEN];
+        table(&[50, 40]).encode(&mut buf);
+        assert_eq!(Table::decode(&buf[..Table::ENCODED_LEN - 1]), None);
+        // 记录数太多
+        let mut bad = buf;
+        bad[2] = Table::LEN as u8 + 1;
+        assert_eq!(Table::decode(&bad), None);
+        // 缩写不是大写字母
+        let mut bad = buf;
+        bad[4] = b'a';
+        assert_eq!(Table::decode(&bad), None);
+        // 分数没有从高到低排列
+        let mut bad = buf;
+        bad[11] = 51;
+        assert_eq!(Table::decode(&bad), None);
+        // 空的 flash
+        assert_eq!(Table::decode(&[0xff; Table::ENCODED_LEN]), None);
+    }
+
+    #[test]
+    fn scores_table_limit() {
+        let mut scores = Scores::default();
+        for game in 0..Scores::LEN as u8 {
+            assert!(scores.table_mut(game, 0).is_some());
+        }
+        // 已有的排行榜不会重复创建
+        assert!(scores.table_mut(0, 0).is_some());
+        assert!(scores.table_mut(0xff, 0).is_none());
+        assert_eq!(scores.tables().len(), Scores::LEN);
+        assert!(scores.table(0xff, 0).is_none());
+    }
+
+    #[test]
+    fn scores_round_trip_through_store() {
+        let mut scores = Scores::default();
+        scores.table_mut(0x10, 0).unwrap().insert(*b"ABC", 42);
+        scores.table_mut(0x12, 0).unwrap().insert(*b"---", 7);
+        let mut store = Store::new(MemFlash::<{ 4 * 4096 }>::new(), 0, 4, 0).unwrap();
+        store.save(&scores).unwrap();
+        let mut store = Store::new(store.into_inner(), 0, 4, 0).unwrap();
+        assert_eq!(store.load::<Scores>().unwrap(), scores);
+        assert_eq!(scores.table(0x10, 0).unwrap().highest(), 42);
+    }
+}
@@ -0,0 +1,242 @@
+//! 统计和成就
+
+use crate::{key, Reader, Record, Writer};
+
+/// 统计和成就
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// 每个界面进入的次数,按界面的编号存储
+    pub played: [u16; Self::UIS],
+    /// 总的游玩时间,单位秒
+    pub play_seconds: u32,
+    /// 贪吃蛇最长的长度
+    pub longest_snake: u16,
+    /// 方块人最深的层数
+    pub deepest_cube_man: u16,
+    /// 走出迷宫的次数
+    pub mazes_solved: u16,
+    /// 骰子每一面掷出的次数
+    pub dice_rolls: [u32; 6],
+    /// 起卦的次数
+    pub hexagrams: u32,
+    /// 已经解锁的成就,每一位表示一个成就
+    pub achievements: u32,
+}
+
+impl Stats {
+    /// 最多统计的界面数
+    pub const UIS: usize = 16;
+
+    /// 所有界面进入的总次数
+    pub fn total_played(&self) -> u32 {
+        self.played.iter().map(|n| *n as u32).sum()
+    }
+
+    /// 统计了进入次数的游戏的界面编号,全部玩过之后解锁探险家
+    pub const GAMES: [usize; 11] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 10, 11];
+
+    /// 是否已经解锁第 i 个成就
+    pub fn unlocked(&self, i: usize) -> bool {
+        self.achievements & (1 << i) > 0
+    }
+
+    /// 解锁达到条件的成就,返回新解锁的成就,每一位表示一个成就
+    pub fn unlock(&mut self) -> u32 {
+        let mut unlocked = 0;
+        for (i, a) in Achievement::ALL.iter().enumerate() {
+            if !self.unlocked(i) && a.reached(self) {
+                unlocked |= 1 << i;
+            }
+        }
+        self.achievements |= unlocked;
+        unlocked
+    }
+}
+
+/// 成就
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Achievement {
+    /// 玩过第一个游戏
+    FirstGame,
+    /// 贪吃蛇长到 10
+    Snake10,
+    /// 贪吃蛇长到 30
+    Snake30,
+    /// 方块人下到 20 层
+    CubeMan20,
+    /// 走出 5 个迷宫
+    Maze5,
+    /// 骰子的每一面都掷出过
+    AllFaces,
+    /// 起过 64 卦
+    Hexagram64,
+    /// 一共玩了一个小时
+    Marathon,
+    /// 所有的游戏都玩过
+    Explorer,
+}
+
+impl Achievement {
+    /// 所有的成就,序号就是保存在flash中的位,只能在后面新增
+    pub const ALL: [Achievement; 9] = [
+        Achievement::FirstGame,
+        Achievement::Snake10,
+        Achievement::Snake30,
+        Achievement::CubeMan20,
+        Achievement::Maze5,
+        Achievement::AllFaces,
+        Achievement::Hexagram64,
+        Achievement::Marathon,
+        Achievement::Explorer,
+    ];
+
+    /// 是否达到了解锁的条件
+    pub fn reached(&self, stats: &Stats) -> bool {
+        let played = |id: &usize| stats.played[*id] > 0;
+        match self {
+            Achievement::FirstGame => Stats::GAMES.iter().any(played),
+            Achievement::Snake10 => stats.longest_snake >= 10,
+            Achievement::Snake30 => stats.longest_snake >= 30,
+            Achievement::CubeMan20 => stats.deepest_cube_man >= 20,
+            Achievement::Maze5 => stats.mazes_solved >= 5,
+            Achievement::AllFaces => stats.dice_rolls.iter().all(|n| *n > 0),
+            Achievement::Hexagram64 => stats.hexagrams >= 64,
+            Achievement::Marathon => stats.play_seconds >= 60 * 60,
+            Achievement::Explorer => Stats::GAMES.iter().all(played),
+        }
+    }
+}
+
+impl Record for Stats {
+    const KEY: u8 = key::STATS;
+    const VERSION: u16 = 1;
+
+    fn encode(&self, w: &mut Writer) {
+        for n in self.played {
+            w.u16(n);
+        }
+        w.u32(self.play_seconds);
+        w.u16(self.longest_snake);
+        w.u16(self.deepest_cube_man);
+        w.u16(self.mazes_solved);
+        for n in self.dice_rolls {
+            w.u32(n);
+        }
+        w.u32(self.hexagrams);
+        w.u32(self.achievements);
+    }
+
+    fn decode(version: u16, r: &mut Reader) -> Option<Self> {
+        match version {
+            1 => {
+                let mut stats = Stats::default();
+                for n in stats.played.iter_mut() {
+                    *n = r.u16()?;
+                }
+                stats.play_seconds = r.u32()?;
+                stats.longest_snake = r.u16()?;
+                stats.deepest_cube_man = r.u16()?;
+                stats.mazes_solved = r.u16()?;
+                for n in stats.dice_rolls.iter_mut() {
+                    *n = r.u32()?;
+                }
+                stats.hexagrams = r.u32()?;
+                stats.achievements = r.u32()?;
+                Some(stats)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bit(a: Achievement) -> u32 {
+        1 << Achievement::ALL.iter().position(|x| *x == a).unwrap()
+    }
+
+    #[test]
+    fn nothing_reached_by_default() {
+        let stats = Stats::default();
+        assert!(Achievement::ALL.iter().all(|a| !a.reached(&stats)));
+    }
+
+    #[test]
+    fn thresholds() {
+        let stats = Stats {
+            longest_snake: 29,
+            deepest_cube_man: 20,
+            mazes_solved: 4,
+            hexagrams: 64,
+            play_seconds: 60 * 60 - 1,
+            ..Stats::default()
+        };
+        assert!(Achievement::Snake10.reached(&stats));
+        assert!(!Achievement::Snake30.reached(&stats));
+        assert!(Achievement::CubeMan20.reached(&stats));
+        assert!(!Achievement::Maze5.reached(&stats));
+        assert!(Achievement::Hexagram64.reached(&stats));
+        assert!(!Achievement::Marathon.reached(&stats));
+    }
+
+    #[test]
+    fn all_faces() {
+        let mut stats = Stats {
+            dice_rolls: [1, 2, 3, 4, 5, 0],
+            ..Stats::default()
+        };
+        assert!(!Achievement::AllFaces.reached(&stats));
+        stats.dice_rolls[5] = 1;
+        assert!(Achievement::AllFaces.reached(&stats));
+    }
+
+    #[test]
+    fn games_played() {
+        let mut stats = Stats::default();
+        // 沙盒不算游戏
+        stats.played[9] = 1;
+        assert!(!Achievement::FirstGame.reached(&stats));
+
+        stats.played[3] = 1;
+        assert!(Achievement::FirstGame.reached(&stats));
+        assert!(!Achievement::Explorer.reached(&stats));
+
+        for id in Stats::GAMES {
+            stats.played[id] = 1;
+        }
+        assert!(Achievement::Explorer.reached(&stats));
+    }
+
+    #[test]
+    fn unlock_only_reports_new_achievements() {
+        let mut stats = Stats {
+            longest_snake: 12,
+            ..Stats::default()
+        };
+        assert_eq!(stats.unlock(), bit(Achievement::Snake10));
+        assert_eq!(stats.unlock(), 0);
+
+        stats.longest_snake = 30;
+        stats.played[0] = 1;
+        assert_eq!(
+            stats.unlock(),
+            bit(Achievement::Snake30) | bit(Achievement::FirstGame)
+        );
+        assert_eq!(
+            stats.achievements,
+            bit(Achievement::FirstGame) | bit(Achievement::Snake10) | bit(Achievement::Snake30)
+        );
+    }
+
+    #[test]
+    fn unlocked_achievements_stay_unlocked() {
+        let mut stats = Stats {
+            achievements: bit(Achievement::Maze5),
+            ..Stats::default()
+        };
+        assert_eq!(stats.unlock(), 0);
+        assert!(stats.unlocked(4));
+    }
+}
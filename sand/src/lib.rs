@@ -0,0 +1,443 @@
+//! 落沙模拟
+//!
+//! 在 8*8 的点阵上模拟沙粒:沙粒沿重力方向下落,下方被挡住时沿斜下方滑落,直到堆积稳定
+
+#![no_std]
+#![warn(missing_docs)]
+
+use core::ops::Range;
+
+/// 点阵的宽度
+pub const WIDTH: i32 = 8;
+/// 点阵的高度
+pub const HEIGHT: i32 = 8;
+/// 加速度分量小于该值时忽略
+const THRESHOLD: f32 = 0.3;
+
+/// 八个方向,按顺时针排列,用于将重力方向旋转 45 度
+const DIRS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+/// 重力方向,屏幕坐标系,向右为 x,向下为 y,每个分量为 -1,0,1
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Gravity {
+    /// x 方向
+    pub dx: i32,
+    /// y 方向
+    pub dy: i32,
+}
+
+impl Gravity {
+    /// 由屏幕坐标系下的加速度得到重力方向
+    ///
+    /// 只保留八个方向:一个分量远大于另一个分量时忽略较小的分量
+    pub fn from_accel(gx: f32, gy: f32) -> Self {
+        let ax = if gx <= 0.0 { 0.0 - gx } else { gx };
+        let ay = if gy <= 0.0 { 0.0 - gy } else { gy };
+        let sign = |v: f32, abs: f32, other: f32| {
+            // tan(67.5°) ≈ 2.4
+            if abs < THRESHOLD || abs * 2.4 < other {
+                0
+            } else if v > 0.0 {
+                1
+            } else {
+                -1
+            }
+        };
+        Self {
+            dx: sign(gx, ax, ay),
+            dy: sign(gy, ay, ax),
+        }
+    }
+
+    /// 没有重力,如平放时
+    pub fn is_zero(&self) -> bool {
+        self.dx == 0 && self.dy == 0
+    }
+
+    /// 顺时针旋转 45 度的倍数,负数为逆时针
+    fn rotate(&self, n: i32) -> (i32, i32) {
+        let i = DIRS
+            .iter()
+            .position(|d| *d == (self.dx, self.dy))
+            .unwrap_or(0) as i32;
+        DIRS[(i + n).rem_euclid(8) as usize]
+    }
+}
+
+/// 沙盘
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Sand {
+    /// 沙粒,每一行用一个字节表示,最高位为第 0 列
+    pub grains: [u8; 8],
+    /// 隔板所在的行,沙粒不能在该行和上一行之间移动
+    divider: Option<i32>,
+    /// 斜向滑落时优先的方向,每一步交替,使沙堆左右对称
+    flip: bool,
+}
+
+impl Sand {
+    /// 空的沙盘
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 在第 row 行的上方加一块隔板,沙漏用它分成上下两半
+    pub fn with_divider(mut self, row: i32) -> Self {
+        self.divider = Some(row);
+        self
+    }
+
+    /// 将若干行填满沙粒
+    pub fn fill(&mut self, rows: Range<i32>) {
+        for y in rows {
+            if (0..HEIGHT).contains(&y) {
+                self.grains[y as usize] = 0xff;
+            }
+        }
+    }
+
+    /// 该位置是否有沙粒
+    pub fn get(&self, x: i32, y: i32) -> bool {
+        Self::inside(x, y) && self.grains[y as usize] & (1 << (7 - x)) > 0
+    }
+
+    /// 放置或移除沙粒
+    pub fn set(&mut self, x: i32, y: i32, on: bool) {
+        if !Self::inside(x, y) {
+            return;
+        }
+        if on {
+            self.grains[y as usize] |= 1 << (7 - x);
+        } else {
+            self.grains[y as usize] &= !(1 << (7 - x));
+        }
+    }
+
+    /// 沙粒的数量
+    pub fn count(&self) -> u32 {
+        self.grains.iter().map(|r| r.count_ones()).sum()
+    }
+
+    /// 若干行中沙粒的数量
+    pub fn count_rows(&self, rows: Range<i32>) -> u32 {
+        rows.filter(|y| (0..HEIGHT).contains(y))
+            .map(|y| self.grains[y as usize].count_ones())
+            .sum()
+    }
+
+    fn inside(x: i32, y: i32) -> bool {
+        (0..WIDTH).contains(&x) && (0..HEIGHT).contains(&y)
+    }
+
+    /// 是否跨过隔板
+    fn crosses(&self, from: i32, to: i32) -> bool {
+        self.divider.is_some_and(|d| (from < d) != (to < d))
+    }
+
+    /// 沙粒能否从一个位置移动到另一个位置
+    fn can_move(&self, (_, y): (i32, i32), (nx, ny): (i32, i32)) -> bool {
+        Self::inside(nx, ny) && !self.get(nx, ny) && !self.crosses(y, ny)
+    }
+
+    /// 模拟一步,沿重力方向下落,被挡住时沿斜下方滑落
+    ///
+    /// 返回是否有沙粒移动
+    pub fn step(&mut self, gravity: Gravity) -> bool {
+        if gravity.is_zero() {
+            return false;
+        }
+
+        // 沿重力方向越靠前的沙粒越先移动,保证每粒沙子每一步最多移动一次
+        let mut cells = [(0, 0); 64];
+        let mut len = 0;
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if self.get(x, y) {
+                    cells[len] = (x, y);
+                    len += 1;
+                }
+            }
+        }
+        let cells = &mut cells[..len];
+        cells.sort_unstable_by_key(|(x, y)| -(x * gravity.dx + y * gravity.dy));
+
+        self.flip = !self.flip;
+        let (first, second) = if self.flip { (1, -1) } else { (-1, 1) };
+        let moves = [
+            (gravity.dx, gravity.dy),
+            gravity.rotate(first),
+            gravity.rotate(second),
+        ];
+
+        let mut moved = false;
+        for &(x, y) in cells.iter() {
+            let target = moves
+                .iter()
+                .map(|(dx, dy)| (x + dx, y + dy))
+                .find(|to| self.can_move((x, y), *to));
+            if let Some((nx, ny)) = target {
+                self.set(x, y, false);
+                self.set(nx, ny, true);
+                moved = true;
+            }
+        }
+        moved
+    }
+
+    /// 隔板处可以漏下的沙粒,从 hint 列开始依次查找
+    ///
+    /// 沿 dy 方向流动时,隔板前一行有沙粒,且隔板后一行还有空位
+    pub fn neck(&self, dy: i32, hint: i32) -> Option<(i32, i32)> {
+        let (from, _) = self.rows(dy)?;
+        (0..WIDTH)
+            .map(|i| (hint + i).rem_euclid(WIDTH))
+            .find(|x| self.drop_to(*x, dy).is_some())
+            .map(|x| (x, from))
+    }
+
+    /// 让一粒沙子穿过隔板,沙漏每次只漏下一粒
+    pub fn release(&mut self, dy: i32, hint: i32) -> bool {
+        let Some((x, y)) = self.neck(dy, hint) else {
+            return false;
+        };
+        let Some((nx, ny)) = self.drop_to(x, dy) else {
+            return false;
+        };
+        self.set(x, y, false);
+        self.set(nx, ny, true);
+        true
+    }
+
+    /// 沿 dy 方向流动时,隔板前后的两行
+    fn rows(&self, dy: i32) -> Option<(i32, i32)> {
+        let d = self.divider?;
+        match dy {
+            1 => Some((d - 1, d)),
+            -1 => Some((d, d - 1)),
+            _ => None,
+        }
+    }
+
+    /// 隔板前一行第 x 列的沙粒漏下的位置,落到隔板后一行离它最近的空位
+    ///
+    /// 堆积稳定时,另一半没有装满,隔板后一行就一定有空位
+    fn drop_to(&self, x: i32, dy: i32) -> Option<(i32, i32)> {
+        let (from, to) = self.rows(dy)?;
+        if !self.get(x, from) {
+            return None;
+        }
+        (0..WIDTH)
+            .filter(|nx| !self.get(*nx, to))
+            .min_by_key(|nx| (nx - x).abs())
+            .map(|nx| (nx, to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOWN: Gravity = Gravity { dx: 0, dy: 1 };
+    const UP: Gravity = Gravity { dx: 0, dy: -1 };
+
+    /// 一直模拟到稳定,返回步数
+    fn settle(sand: &mut Sand, gravity: Gravity) -> u32 {
+        let count = sand.count();
+        let mut steps = 0;
+        while sand.step(gravity) {
+            steps += 1;
+            assert_eq!(sand.count(), count, "grains lost or created");
+            assert!(steps < 100, "never settles");
+        }
+        steps
+    }
+
+    /// 稳定之后任何沙粒都不能再移动
+    fn assert_stable(sand: &Sand, gravity: Gravity) {
+        let moves = [
+            (gravity.dx, gravity.dy),
+            gravity.rotate(1),
+            gravity.rotate(-1),
+        ];
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if sand.get(x, y) {
+                    for (dx, dy) in moves {
+                        assert!(!sand.can_move((x, y), (x + dx, y + dy)), "({x}, {y})");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gravity_from_accel() {
+        assert_eq!(Gravity::from_accel(0.0, 1.0), DOWN);
+        assert_eq!(Gravity::from_accel(0.1, -0.1), Gravity::default());
+        assert_eq!(Gravity::from_accel(0.7, 0.7), Gravity { dx: 1, dy: 1 });
+        assert_eq!(Gravity::from_accel(-1.0, 0.2), Gravity { dx: -1, dy: 0 });
+        assert_eq!(Gravity::from_accel(-0.6, -0.5), Gravity { dx: -1, dy: -1 });
+    }
+
+    #[test]
+    fn grain_falls_one_cell_per_step() {
+        let mut sand = Sand::new();
+        sand.set(3, 0, true);
+        for y in 1..HEIGHT {
+            assert!(sand.step(DOWN));
+            assert!(sand.get(3, y));
+            assert_eq!(sand.count(), 1);
+        }
+        assert!(!sand.step(DOWN));
+    }
+
+    #[test]
+    fn column_falls_together() {
+        let mut sand = Sand::new();
+        sand.set(2, 0, true);
+        sand.set(2, 1, true);
+        assert!(sand.step(DOWN));
+        assert!(!sand.get(2, 0) && sand.get(2, 1) && sand.get(2, 2));
+    }
+
+    #[test]
+    fn no_gravity_no_motion() {
+        let mut sand = Sand::new();
+        sand.set(4, 4, true);
+        assert!(!sand.step(Gravity::default()));
+        assert!(sand.get(4, 4));
+    }
+
+    #[test]
+    fn pile_slides_sideways() {
+        // 堆在一列上的沙粒会向两边滑落,堆成 45 度的沙堆
+        let mut sand = Sand::new();
+        for y in 0..HEIGHT {
+            sand.set(3, y, true);
+        }
+        settle(&mut sand, DOWN);
+        assert_stable(&sand, DOWN);
+        assert_eq!(sand.grains[7], 0b0111_1100);
+        assert_eq!(sand.grains[6], 0b0011_1000);
+        assert_eq!(sand.count_rows(0..6), 0);
+    }
+
+    #[test]
+    fn random_sand_settles_in_every_direction() {
+        let mut rng = 0x2545_f491u32;
+        for round in 0..64 {
+            let mut sand = Sand::new();
+            for row in sand.grains.iter_mut() {
+                rng ^= rng << 13;
+                rng ^= rng >> 17;
+                rng ^= rng << 5;
+                *row = rng as u8 & (rng >> 8) as u8;
+            }
+            let count = sand.count();
+            let (dx, dy) = DIRS[round % 8];
+            let gravity = Gravity { dx, dy };
+            settle(&mut sand, gravity);
+            assert_stable(&sand, gravity);
+            assert_eq!(sand.count(), count);
+        }
+    }
+
+    #[test]
+    fn full_board_cannot_move() {
+        let mut sand = Sand::new();
+        sand.fill(0..HEIGHT);
+        for (dx, dy) in DIRS {
+            assert!(!sand.step(Gravity { dx, dy }));
+        }
+        assert_eq!(sand.count(), 64);
+    }
+
+    #[test]
+    fn divider_holds_sand() {
+        let mut sand = Sand::new().with_divider(4);
+        sand.fill(0..2);
+        settle(&mut sand, DOWN);
+        assert_eq!(sand.count_rows(2..4), 16);
+        assert_eq!(sand.count_rows(4..HEIGHT), 0);
+        // 倒过来之后下半部分的沙粒也不能穿过隔板
+        let mut sand = Sand::new().with_divider(4);
+        sand.fill(6..HEIGHT);
+        settle(&mut sand, UP);
+        assert_eq!(sand.count_rows(4..6), 16);
+        assert_eq!(sand.count_rows(0..4), 0);
+    }
+
+    #[test]
+    fn neck_needs_a_divider_and_a_direction() {
+        let mut sand = Sand::new();
+        sand.fill(0..4);
+        assert_eq!(sand.neck(1, 0), None);
+        assert!(!sand.release(1, 0));
+        let sand = sand.with_divider(4);
+        assert_eq!(sand.neck(0, 0), None);
+        // 往上流时隔板前一行是空的
+        assert_eq!(sand.neck(-1, 0), None);
+        assert_eq!(sand.neck(1, 0), Some((0, 3)));
+    }
+
+    #[test]
+    fn neck_starts_at_hint() {
+        let mut sand = Sand::new().with_divider(4);
+        sand.set(1, 3, true);
+        sand.set(6, 3, true);
+        assert_eq!(sand.neck(1, 2), Some((6, 3)));
+        assert_eq!(sand.neck(1, 7), Some((1, 3)));
+        // 超出范围的 hint 回绕
+        assert_eq!(sand.neck(1, 10), Some((6, 3)));
+        assert_eq!(sand.neck(1, -1), Some((1, 3)));
+    }
+
+    #[test]
+    fn release_drops_to_nearest_gap() {
+        let mut sand = Sand::new().with_divider(4);
+        sand.set(3, 3, true);
+        sand.grains[4] = 0b1111_0111;
+        assert!(sand.release(1, 3));
+        assert!(!sand.get(3, 3));
+        assert_eq!(sand.grains[4], 0xff);
+        // 隔板后一行满了就漏不下去
+        sand.set(3, 3, true);
+        assert_eq!(sand.neck(1, 3), None);
+        assert!(!sand.release(1, 3));
+    }
+
+    #[test]
+    fn hourglass_runs_out_one_grain_at_a_time() {
+        let mut sand = Sand::new().with_divider(4);
+        sand.fill(0..4);
+        let mut hint = 0;
+        for released in 1..=32 {
+            assert!(sand.release(1, hint), "grain {released}");
+            hint += 3;
+            settle(&mut sand, DOWN);
+            assert_eq!(sand.count_rows(4..HEIGHT), released);
+            assert_eq!(sand.count(), 32);
+        }
+        assert_eq!(sand.neck(1, 0), None);
+        assert!(!sand.release(1, 0));
+        assert_eq!(sand.count_rows(4..HEIGHT), 32);
+
+        // 翻过来再漏回去
+        settle(&mut sand, UP);
+        for released in 1..=32 {
+            assert!(sand.release(-1, hint));
+            settle(&mut sand, UP);
+            assert_eq!(sand.count_rows(0..4), released);
+        }
+        assert!(!sand.release(-1, 0));
+    }
+}